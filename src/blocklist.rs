@@ -0,0 +1,87 @@
+use std::collections::HashMap;
+
+use crate::error::{FinError, Result};
+
+/// Why a blocked checksum is listed when the entry gives no reason
+const DEFAULT_REASON: &str = "listed as known bad";
+
+/// Plugin checksums fin refuses to install, e.g. of a compromised release
+///
+/// Entries come from the config and optionally a feed at `url`, a text
+/// file with one `sha256:<hex>` per line, optionally followed by a reason.
+/// Blank lines and lines starting with `#` are ignored.
+#[derive(Debug, Clone, Default)]
+pub struct Blocklist {
+    url: Option<String>,
+    /// Reason by checksum
+    checksums: HashMap<String, String>,
+}
+
+impl Blocklist {
+    pub fn new(checksums: &[String], url: Option<String>) -> Self {
+        let mut blocklist = Self {
+            url,
+            checksums: HashMap::new(),
+        };
+        blocklist.extend(&checksums.join("\n"));
+        blocklist
+    }
+
+    /// The configured entries together with those from the feed
+    ///
+    /// A feed that cannot be fetched fails the whole install rather than
+    /// letting plugins through unchecked.
+    pub(crate) async fn load(&self, client: &reqwest::Client) -> Result<Self> {
+        let Some(url) = &self.url else {
+            return Ok(self.clone());
+        };
+        let error = |reason: String| FinError::Blocklist {
+            url: url.clone(),
+            reason,
+        };
+
+        let response = client
+            .get(url)
+            .send()
+            .await
+            .map_err(|err| error(err.to_string()))?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(error(format!("HTTP {status}")));
+        }
+        let body = response
+            .text()
+            .await
+            .map_err(|err| error(err.to_string()))?;
+
+        let mut loaded = self.clone();
+        loaded.extend(&body);
+        Ok(loaded)
+    }
+
+    /// Fail with [`FinError::Blocked`] when `checksum` is listed
+    pub fn check(&self, checksum: &str) -> Result<()> {
+        match self.checksums.get(checksum) {
+            Some(reason) => Err(FinError::Blocked {
+                checksum: checksum.to_string(),
+                reason: reason.clone(),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    fn extend(&mut self, lines: &str) {
+        for line in lines.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (checksum, reason) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let reason = match reason.trim() {
+                "" => DEFAULT_REASON,
+                reason => reason,
+            };
+            self.checksums
+                .insert(checksum.to_ascii_lowercase(), reason.to_string());
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use anyhow::Result;
+use fin::{Config, Fin, github};
+use std::{env, fmt::Write, path::Path};
+
+use crate::doctor_findings;
+
+/// Variables that change where fin looks for things, reported by name only
+const ENV_VARS: &[&str] = &[
+    "FIN_CONFIG",
+    "FIN_FISH_CONFIG_DIR",
+    "__fish_config_dir",
+    "XDG_CONFIG_HOME",
+    "XDG_CACHE_HOME",
+    "GITHUB_TOKEN",
+];
+/// Variables whose values must never show up in a report
+const SECRET_VARS: &[&str] = &["GITHUB_TOKEN"];
+
+/// A markdown report of fin's setup for pasting into an issue
+///
+/// Holds versions, the paths fin uses, the installed plugins by name and
+/// ref, and what `fin doctor` finds, never file contents or variable
+/// values. The home directory is shown as `~`, and local plugins are
+/// numbered instead of named unless `include_paths` is set.
+pub fn render(fin: &Fin, include_paths: bool) -> Result<String> {
+    let mut report = String::new();
+    writeln!(report, "### Versions\n")?;
+    let build = match cfg!(debug_assertions) {
+        true => "debug",
+        false => "release",
+    };
+    writeln!(
+        report,
+        "- fin: {} ({build} build)",
+        env!("CARGO_PKG_VERSION")
+    )?;
+    writeln!(report, "- OS: {} {}", env::consts::OS, env::consts::ARCH)?;
+    writeln!(
+        report,
+        "- fish: {}",
+        fin.fish_version().unwrap_or("not found")
+    )?;
+
+    writeln!(report, "\n### Environment\n")?;
+    let dirs = [
+        ("Fish config directory", fin.fish_config_dir()),
+        ("Installation path", fin.fin_path()),
+        ("Lock file", fin.lock_file_path()),
+    ];
+    for (label, path) in dirs {
+        writeln!(report, "- {label}: `{}`{}", path.display(), presence(path))?;
+    }
+    if let Some(path) = Config::path() {
+        writeln!(
+            report,
+            "- Config file: `{}`{}",
+            path.display(),
+            presence(&path)
+        )?;
+    }
+    let set: Vec<&str> = ENV_VARS
+        .iter()
+        .copied()
+        .filter(|var| env::var_os(var).is_some_and(|value| !value.is_empty()))
+        .collect();
+    match set.is_empty() {
+        true => writeln!(report, "- Set variables: none")?,
+        false => writeln!(report, "- Set variables: {}", set.join(", "))?,
+    }
+
+    let plugins = fin.list()?;
+    let mut local_names = Vec::new();
+    writeln!(report, "\n### Plugins ({})\n", plugins.len())?;
+    for plugin in &plugins {
+        if plugin.is_local() {
+            local_names.push(plugin.name.as_str());
+            if !include_paths {
+                writeln!(report, "- local plugin {}", local_names.len())?;
+                continue;
+            }
+        }
+        match github::repo_and_ref(plugin) {
+            Some((repo, ref_name)) => writeln!(report, "- {repo}@{ref_name}")?,
+            None if plugin.is_local() => writeln!(report, "- {} (local)", plugin.name)?,
+            None => writeln!(report, "- {} (archive)", plugin.name)?,
+        }
+    }
+
+    writeln!(report, "\n### Doctor\n")?;
+    let findings = match fin.doctor() {
+        Ok(doctor) if doctor.problems() == 0 => vec!["No problems found".to_string()],
+        Ok(doctor) => doctor_findings(&doctor),
+        Err(err) => vec![format!("Failed to run: {}", err.full_message())],
+    };
+    writeln!(report, "```\n{}\n```", findings.join("\n"))?;
+
+    if !include_paths {
+        // Longest first, so a plugin inside another one's directory keeps its own number
+        let mut numbered: Vec<(usize, &str)> = local_names.into_iter().enumerate().collect();
+        numbered.sort_by_key(|(_, name)| std::cmp::Reverse(name.len()));
+        for (i, name) in numbered {
+            report = report.replace(name, &format!("local plugin {}", i + 1));
+        }
+    }
+    Ok(redact(report))
+}
+
+/// ` (not found)` for a path that does not exist
+fn presence(path: &Path) -> &'static str {
+    match path.exists() {
+        true => "",
+        false => " (not found)",
+    }
+}
+
+/// Replace the home directory with `~` and secret values with `[redacted]`
+fn redact(mut report: String) -> String {
+    for var in SECRET_VARS {
+        if let Ok(value) = env::var(var)
+            && !value.is_empty()
+        {
+            report = report.replace(&value, "[redacted]");
+        }
+    }
+    if let Some(home) = dirs::home_dir()
+        && home.parent().is_some()
+    {
+        report = report.replace(&*home.to_string_lossy(), "~");
+    }
+    report
+}
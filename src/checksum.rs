@@ -0,0 +1,89 @@
+use sha2::{Digest, Sha256};
+use std::{
+    fmt::Write,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    error::{IoResultExt, Result},
+    filesystem::FileSystem,
+};
+
+/// Suffix of disabled `conf.d` snippets, ignored so toggling them keeps the checksum
+pub(crate) const DISABLED_SUFFIX: &str = ".disabled";
+
+/// Checksum over the names and contents of a plugin's installed files
+///
+/// `files` are relative to `base` as recorded in the lock file. The result
+/// looks like `sha256:<hex>` and does not depend on the order of `files`.
+/// Contents are streamed through the hasher, so large files are never held
+/// in memory.
+pub fn checksum<'a>(
+    fs: &dyn FileSystem,
+    base: &Path,
+    files: impl IntoIterator<Item = &'a String>,
+) -> Result<String> {
+    checksum_paths(
+        fs,
+        files
+            .into_iter()
+            .map(|file| (file.as_str(), base.join(file))),
+    )
+}
+
+/// Like [`checksum`] for files that are not in place yet
+///
+/// Each file is hashed under `name`, its path as it will be recorded, while
+/// its contents are read from `path`.
+pub(crate) fn checksum_paths<'a>(
+    fs: &dyn FileSystem,
+    files: impl IntoIterator<Item = (&'a str, PathBuf)>,
+) -> Result<String> {
+    let mut files: Vec<(&str, PathBuf)> = files.into_iter().collect();
+    files.sort_by_key(|(name, _)| normalized(name));
+
+    let mut hasher = Sha256::new();
+    for (name, path) in files {
+        let size = fs.file_size(&path).with_path("read", &path)?;
+        let mut contents = fs.open(&path).with_path("read", &path)?;
+        hasher.update(normalized(name).as_bytes());
+        hasher.update([0]);
+        hasher.update(size.to_le_bytes());
+        let read = io::copy(&mut contents, &mut hasher).with_path("read", &path)?;
+        if read != size {
+            // Changed while hashing, the size recorded above is already wrong
+            return Err(io::Error::other("file changed while hashing")).with_path("read", &path);
+        }
+    }
+
+    Ok(to_hex(hasher))
+}
+
+/// Checksum over the bytes of a single file, like a downloaded archive
+pub(crate) fn checksum_file(path: &Path) -> Result<String> {
+    let mut file = fs::File::open(path).with_path("read", path)?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher).with_path("read", path)?;
+    Ok(to_hex(hasher))
+}
+
+/// Like [`checksum_file`] for an installed file, read through `fs`
+pub(crate) fn checksum_contents(fs: &dyn FileSystem, path: &Path) -> Result<String> {
+    let mut file = fs.open(path).with_path("read", path)?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher).with_path("read", path)?;
+    Ok(to_hex(hasher))
+}
+
+fn to_hex(hasher: Sha256) -> String {
+    let mut hex = String::from("sha256:");
+    for byte in hasher.finalize() {
+        let _ = write!(hex, "{byte:02x}");
+    }
+    hex
+}
+
+fn normalized(file: &str) -> &str {
+    file.strip_suffix(DISABLED_SUFFIX).unwrap_or(file)
+}
@@ -0,0 +1,78 @@
+use serde::Deserialize;
+use std::{collections::HashMap, env, fs, path::PathBuf};
+
+use crate::{
+    error::{FinError, Result},
+    lock::{ChecksumScope, LockFormat},
+};
+
+const FIN_CONFIG_FILENAME: &str = "config.toml";
+
+/// User configuration read from `~/.config/fin/config.toml`
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Fish configuration directory holding the lock file, found from the environment when unset
+    pub fish_config_dir: Option<PathBuf>,
+    /// Where to install plugin man pages and docs, nothing is installed when unset
+    pub docs_dir: Option<PathBuf>,
+    /// Format of the lock file, `fin-lock.json` is used for json
+    pub lock_format: Option<LockFormat>,
+    /// Plugins fetched at the same time
+    pub jobs: Option<usize>,
+    /// Simultaneous downloads allowed from one host
+    pub host_jobs: Option<usize>,
+    /// Per-host overrides of `host_jobs`, keyed by host name
+    pub host_limits: HashMap<String, usize>,
+    /// Times a download is tried again after a server error or dropped connection
+    pub retries: Option<u32>,
+    /// URL of the plugin registry index
+    pub registry_url: Option<String>,
+    /// Leading archive path components to drop, keyed by plugin name or source URL
+    pub strip_components: HashMap<String, usize>,
+    /// Plugins whose `conf.d` snippets are installed disabled
+    pub disable_confd: Vec<String>,
+    /// Conflict priority per plugin name, higher wins
+    pub priority: HashMap<String, i32>,
+    /// Shell command run after each plugin is installed, updated or removed
+    pub post_command: Option<String>,
+    /// What plugin checksums cover, the installed files by default
+    pub checksum_scope: Option<ChecksumScope>,
+    /// Checksums of known-bad plugin versions that are never installed
+    pub blocked_checksums: Vec<String>,
+    /// Security feed listing more blocked checksums, fetched on every install
+    pub blocklist_url: Option<String>,
+    /// Run the build commands plugins declare in their `fin.toml`
+    pub allow_scripts: bool,
+    /// Where programs built by plugins are installed, `bin` in the installation path when unset
+    pub bin_dir: Option<PathBuf>,
+    /// Commit the lock file after every change when it is in a git work tree
+    pub git_autocommit: bool,
+    /// Message of those commits, `{plugins}` is replaced by the changed plugins
+    pub git_commit_message: Option<String>,
+}
+
+impl Config {
+    /// Load the configuration, `FIN_CONFIG` overrides the default location
+    pub fn load() -> Result<Self> {
+        let Some(path) = Self::path() else {
+            return Ok(Self::default());
+        };
+
+        match fs::read_to_string(&path) {
+            Ok(content) => {
+                toml::from_str(&content).map_err(|source| FinError::Config { path, source })
+            }
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    /// Where the configuration is read from
+    pub fn path() -> Option<PathBuf> {
+        if let Ok(path) = env::var("FIN_CONFIG") {
+            return Some(PathBuf::from(path));
+        }
+
+        dirs::config_dir().map(|p| p.join("fin").join(FIN_CONFIG_FILENAME))
+    }
+}
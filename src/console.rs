@@ -0,0 +1,239 @@
+use fin::{FinError, Phase, Plugin, ProgressObserver, Warning};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::short_sha;
+
+/// Prints progress of plugin operations to the terminal
+pub struct ConsoleObserver {
+    /// Past tense verb for finished plugins, e.g. "Installed"
+    done_verb: &'static str,
+    /// Keep stdout free, e.g. for `install --activate`
+    to_stderr: bool,
+    timings: Option<Arc<Timings>>,
+    warnings: Option<Arc<Warnings>>,
+}
+
+impl ConsoleObserver {
+    pub fn new(done_verb: &'static str, to_stderr: bool) -> Self {
+        Self {
+            done_verb,
+            to_stderr,
+            timings: None,
+            warnings: None,
+        }
+    }
+
+    /// Also record how long each plugin spends in each phase into `timings`
+    pub fn with_timings(mut self, timings: Arc<Timings>) -> Self {
+        self.timings = Some(timings);
+        self
+    }
+
+    /// Collect warnings into `warnings` instead of printing them as they come
+    pub fn with_warnings(mut self, warnings: Arc<Warnings>) -> Self {
+        self.warnings = Some(warnings);
+        self
+    }
+
+    fn line(&self, line: &str) {
+        if self.to_stderr {
+            eprintln!("{line}");
+        } else {
+            println!("{line}");
+        }
+    }
+}
+
+impl ProgressObserver for ConsoleObserver {
+    fn plugin_started(&self, plugin: &Plugin) {
+        self.line(&format!("Downloading: {}", plugin.source));
+        if let Some(timings) = &self.timings {
+            timings.enter(plugin, None);
+        }
+    }
+
+    fn phase_changed(&self, plugin: &Plugin, phase: Phase) {
+        if let Some(timings) = &self.timings {
+            timings.enter(plugin, Some(phase));
+        }
+    }
+
+    fn plugin_finished(&self, plugin: &Plugin, result: Result<&Plugin, &FinError>) {
+        if let Some(timings) = &self.timings {
+            timings.finish(plugin);
+        }
+        match result {
+            Ok(installed) => match (&plugin.commit_hash, &installed.commit_hash) {
+                (Some(old), Some(new)) if old != new => self.line(&format!(
+                    "{}: {} ({} -> {})",
+                    self.done_verb,
+                    installed.name,
+                    short_sha(old),
+                    short_sha(new)
+                )),
+                _ => self.line(&format!("{}: {}", self.done_verb, installed.name)),
+            },
+            Err(err @ FinError::Blocked { .. }) => {
+                eprintln!(
+                    "WARNING: {} may be compromised and was not installed",
+                    plugin.name
+                );
+                eprintln!("WARNING: {}", err.full_message());
+            }
+            Err(err) => eprintln!("Failed: {} - {}", plugin.name, err.full_message()),
+        }
+    }
+
+    fn warning(&self, plugin: &Plugin, warning: &Warning) {
+        match &self.warnings {
+            Some(warnings) => warnings.push(&plugin.name, warning),
+            None => eprintln!("Warning: {}: {warning}", plugin.name),
+        }
+    }
+
+    fn lock_warning(&self, warning: &Warning) {
+        match &self.warnings {
+            Some(warnings) => warnings.push(LOCK_WARNING_SUBJECT, warning),
+            None => eprintln!("Warning: {LOCK_WARNING_SUBJECT}: {warning}"),
+        }
+    }
+}
+
+/// What lock file warnings are listed under instead of a plugin name
+const LOCK_WARNING_SUBJECT: &str = "lock file";
+
+/// Warnings of one operation, printed together once it is done
+///
+/// Plugins are processed in parallel, inline warnings would be lost among
+/// their progress lines.
+#[derive(Debug, Default)]
+pub struct Warnings {
+    warnings: Mutex<Vec<(String, String)>>,
+}
+
+impl Warnings {
+    fn push(&self, subject: &str, warning: &Warning) {
+        self.warnings
+            .lock()
+            .expect("warnings lock poisoned")
+            .push((subject.to_string(), warning.to_string()));
+    }
+
+    /// Print the collected warnings by plugin, nothing when there are none
+    pub fn print(&self) {
+        let mut warnings = self.warnings.lock().expect("warnings lock poisoned");
+        if warnings.is_empty() {
+            return;
+        }
+        // Stable, so each plugin's warnings keep the order they happened in
+        warnings.sort_by(|a, b| a.0.cmp(&b.0));
+        eprintln!("Warnings ({}):", warnings.len());
+        for (plugin, warning) in warnings.iter() {
+            eprintln!("  {plugin}: {warning}");
+        }
+    }
+}
+
+/// Columns of the `--timings` table, the time before the first phase comes first
+const TIMING_COLUMNS: [(&str, Option<Phase>); 6] = [
+    ("Resolve", None),
+    ("Download", Some(Phase::Download)),
+    ("Extract", Some(Phase::Extract)),
+    ("Build", Some(Phase::Build)),
+    ("Copy", Some(Phase::Copy)),
+    ("Events", Some(Phase::Events)),
+];
+
+/// Time each plugin spent in each phase, for `--timings`
+#[derive(Debug, Default)]
+pub struct Timings {
+    plugins: Mutex<HashMap<String, PluginTimings>>,
+}
+
+#[derive(Debug)]
+struct PluginTimings {
+    /// Column of the phase the plugin is in and since when, `None` once finished
+    current: Option<(usize, Instant)>,
+    spent: [Duration; TIMING_COLUMNS.len()],
+}
+
+impl Timings {
+    /// `plugin` moved on to `phase`, `None` when it just started
+    fn enter(&self, plugin: &Plugin, phase: Option<Phase>) {
+        let now = Instant::now();
+        let column = TIMING_COLUMNS
+            .iter()
+            .position(|(_, p)| *p == phase)
+            .unwrap_or_default();
+        let mut plugins = self.plugins.lock().expect("timings lock poisoned");
+        let timings = plugins.entry(plugin.name.clone()).or_insert(PluginTimings {
+            current: None,
+            spent: Default::default(),
+        });
+        if let Some((previous, since)) = timings.current {
+            timings.spent[previous] += now - since;
+        }
+        timings.current = Some((column, now));
+    }
+
+    fn finish(&self, plugin: &Plugin) {
+        let mut plugins = self.plugins.lock().expect("timings lock poisoned");
+        if let Some(timings) = plugins.get_mut(&plugin.name)
+            && let Some((previous, since)) = timings.current.take()
+        {
+            timings.spent[previous] += since.elapsed();
+        }
+    }
+
+    /// Print a table of the recorded plugins, slowest first
+    pub fn print(&self) {
+        let plugins = self.plugins.lock().expect("timings lock poisoned");
+        let mut rows: Vec<(&String, &PluginTimings, Duration)> = plugins
+            .iter()
+            .map(|(name, timings)| (name, timings, timings.spent.iter().sum()))
+            .collect();
+        rows.sort_by_key(|&(_, _, total)| std::cmp::Reverse(total));
+
+        let width = rows
+            .iter()
+            .map(|(name, ..)| name.len())
+            .fold("Plugin".len(), usize::max);
+        let mut header = format!("{:width$}", "Plugin");
+        for (column, _) in TIMING_COLUMNS {
+            header.push_str(&format!("  {column:>9}"));
+        }
+        eprintln!("{header}  {:>9}", "Total");
+        for (name, timings, total) in rows {
+            let mut row = format!("{name:width$}");
+            for spent in timings.spent {
+                row.push_str(&format!("  {:>9}", format_duration(spent)));
+            }
+            eprintln!("{row}  {:>9}", format_duration(total));
+        }
+    }
+}
+
+/// Duration in milliseconds, e.g. `12.3ms`
+fn format_duration(duration: Duration) -> String {
+    format!("{:.1}ms", duration.as_secs_f64() * 1000.0)
+}
+
+/// Human readable size with binary units, e.g. `12.3 KiB`
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["KiB", "MiB", "GiB"];
+
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
@@ -1,241 +1,3987 @@
-use anyhow::{Context, Result};
+use chrono::{SecondsFormat, Utc};
+use futures_util::{FutureExt, StreamExt, stream};
 use rayon::prelude::*;
 use std::{
-    collections::HashSet,
+    collections::{BTreeMap, HashMap, HashSet},
     env,
-    fs::{self},
     path::{Path, PathBuf},
-    process::{Command, Stdio},
+    sync::{Arc, OnceLock},
+    time::{Duration, Instant},
 };
 use tempfile::TempDir;
+use tokio::{runtime::Runtime, sync::watch};
 
-use crate::lock::{LockFile, Plugin, PluginVecExt};
+use crate::{
+    blocklist::Blocklist,
+    checksum::{DISABLED_SUFFIX, checksum, checksum_contents, checksum_file, checksum_paths},
+    config::Config,
+    diff,
+    error::{FinError, IoResultExt, Result},
+    events::{self, Event, fish_quote},
+    fetch::{self, blocking},
+    fetcher::{ArchiveFetcher, DEFAULT_STRIP_COMPONENTS, FetchContext, Fetcher, LocalFetcher},
+    filesystem::{FileSystem, StdFs},
+    fisher::FisherInstall,
+    functions,
+    github::{self, Changelog, GitHub, LATEST_REF, RepoStatus},
+    license::{self, LICENSE_FILENAMES},
+    limit::{DEFAULT_HOST_JOBS, HostLimiter},
+    lock::{ChecksumScope, LockFile, LockFormat, Plugin, Plugins},
+    manifest::{InstallManifest, PLUGIN_MANIFEST_FILENAME, PluginManifest},
+    progress::{NoopObserver, Phase, ProgressObserver, Warning},
+    prompt::Prompt,
+    registry::Registry,
+    report::{
+        Deprecation, DiskUsage, DoctorReport, Drift, DuplicateFunction, FileInventory, FileState,
+        HookRun, HooksReport, INVENTORY_VERSION, InstallReport, Inventory, LicenseInfo,
+        LockInventory, Outdated, OutdatedReport, PluginInventory, PluginState, PreviewReport,
+        RemoveReport, ReplaceReport, StatusReport, Theme, UpdatePreview, UpdateReport,
+        VerifyReport,
+    },
+    runner::{CommandRunner, Invocation, Output, SystemRunner},
+};
 
 const PLUGIN_SUBDIRS: &[&str] = &["functions", "conf.d", "completions"];
-const FIN_LOCK_FILENAME: &str = "fin-lock.toml";
+/// Key bindings a plugin ships at its root, installed as a `conf.d` snippet
+const KEY_BINDINGS_FILENAME: &str = "key_bindings.fish";
+const MAN_SUBDIR: &str = "man";
+const DOC_SUBDIR: &str = "doc";
+/// Where built companion programs go inside `fin_path` unless configured otherwise
+const BIN_SUBDIR: &str = "bin";
+/// License texts kept at install time, below `fin_path` by plugin name
+const LICENSES_DIRNAME: &str = ".fin-licenses";
+/// Per-plugin staging directories are created inside `fin_path` with this prefix
+const STAGING_PREFIX: &str = ".fin-staging-";
+/// Replaced files are parked here inside the staging directory until the install succeeds
+const BACKUP_SUBDIR: &str = ".fin-backup";
+/// Written and removed again to check a directory is writable
+const PROBE_FILENAME: &str = ".fin-write-probe";
+/// Copies of files that lost a conflict, below `fin_path` by plugin name
+const SHADOW_DIRNAME: &str = ".fin-shadow";
+/// Raw installs, below `fin_path` by plugin name
+const RAW_DIRNAME: &str = ".fin-raw";
+/// Fish's directory for snippets sourced at startup
+const CONFD_SUBDIR: &str = "conf.d";
+/// Prompt functions a theme plugin ships, `fish_prompt` makes a plugin a theme
+const PROMPT_FILES: &[&str] = &[
+    "functions/fish_prompt.fish",
+    "functions/fish_right_prompt.fish",
+    "functions/fish_mode_prompt.fish",
+];
+/// Snippet in `conf.d` fin writes with facts about the installed plugins
+const META_FILENAME: &str = "_fin_meta.fish";
+/// Name of the wrapper function and completions `fin init --install` writes
+const WRAPPER_FILENAME: &str = "fin.fish";
+
+/// How long a plugin's uninstall hook may run before it is killed
+const UNINSTALL_HOOK_TIMEOUT: Duration = Duration::from_secs(30);
+/// Commit message when the lock file is committed without a configured one
+const DEFAULT_GIT_COMMIT_MESSAGE: &str = "Update fish plugins: {plugins}";
+
+/// How long a plugin's build command may run before it is killed
+const BUILD_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Default number of plugins fetched at the same time
+pub const DEFAULT_JOBS: usize = 8;
+/// Default number of times a failed download is tried again
+pub const DEFAULT_RETRIES: u32 = 3;
+
+/// A plugin, whether its archive still matches and how long checking took
+type ArchiveCheck<'p> = (&'p Plugin, Result<(bool, Duration)>);
+
+/// How [`Fin::install`] and [`Fin::install_from_lock`] treat the requested plugins
+#[derive(Debug, Default, Clone)]
+pub struct InstallOptions {
+    /// Reinstall plugins that are already installed
+    pub force: bool,
+    /// Leave the lock file untouched and fail unless every plugin installs
+    pub frozen: bool,
+    /// Install one plugin at a time in the requested order
+    pub ordered: bool,
+    /// Emit install events only after every plugin's files are in place
+    pub defer_events: bool,
+    /// Leading archive path components to drop, overriding the lock file and config
+    pub strip_components: Option<usize>,
+    /// What to do when a file is already installed by another plugin
+    pub on_conflict: OnConflict,
+    /// Conflict priority of the requested plugins, overriding the lock file and config
+    pub priority: Option<i32>,
+    /// Install symlinks in local plugins as copies of their targets
+    pub dereference: bool,
+    /// Install plugins whose completions replace ones Fish ships
+    pub allow_shadow_completions: bool,
+    /// Directory levels copied from local plugins, overriding the lock file
+    pub max_depth: Option<usize>,
+    /// Record the checksum of each downloaded archive and refuse different ones later
+    pub pin_digest: bool,
+    /// Warn about functions the installed plugins define that other plugins define too
+    pub check_functions: bool,
+    /// Run the build commands the plugins declare in their `fin.toml`
+    pub allow_scripts: bool,
+    /// Install the whole archive without stripping or filtering it, see [`Plugin::raw`]
+    pub no_strip: bool,
+    /// Fail plugins that need a newer Fish than the installed one instead of warning
+    pub strict: bool,
+    /// Record plugins from a lock file whose files are already in place
+    /// and match its checksum, instead of fetching them again
+    pub ignore_existing: bool,
+    /// Installed plugins to fetch again at their recorded ref, keeping their settings
+    pub reinstall: Vec<String>,
+}
+
+/// How [`Fin::remove`] treats the removed plugins
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RemoveOptions {
+    /// Skip the uninstall hooks plugins declare in their `fin.toml`
+    pub no_hooks: bool,
+}
+
+/// How an install treats files that another installed plugin already provides
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OnConflict {
+    /// Fail the plugin and leave the other plugin's files alone
+    Error,
+    /// The plugin with the higher priority provides the file, ties go to
+    /// the plugin being installed. The losing copy is kept aside.
+    #[default]
+    Overwrite,
+    /// The plugin already providing the file keeps it, whatever the
+    /// priorities. The new copy is kept aside.
+    Skip,
+}
+
+impl std::str::FromStr for OnConflict {
+    type Err = FinError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "error" => Ok(Self::Error),
+            "overwrite" => Ok(Self::Overwrite),
+            "skip" => Ok(Self::Skip),
+            _ => Err(FinError::InvalidConflictMode(s.to_string())),
+        }
+    }
+}
 
-#[allow(dead_code)]
+/// What a user supplied name refers to, from [`Fin::match_name`]
+#[derive(Debug)]
+pub enum NameMatch<'a> {
+    /// No installed plugin goes by that name
+    None,
+    Unique(&'a Plugin),
+    /// Several installed plugins end in that name, sorted by name
+    Ambiguous(Vec<&'a Plugin>),
+}
+
+/// A plugin manager bound to a Fish configuration directory
+///
+/// Operations return what they did instead of printing, so the binary is
+/// just one consumer of this API.
 pub struct Fin {
     fin_path: PathBuf,
     fish_config_dir: PathBuf,
     fin_lock_file_path: PathBuf,
-    lock_file: LockFile,
+    lock_format: LockFormat,
+    /// Lock file in the other format, read until the first save replaces it
+    previous_lock_file_path: Option<PathBuf>,
+    docs_dir: Option<PathBuf>,
+    jobs: usize,
+    retries: u32,
+    strip_components: HashMap<String, usize>,
+    disable_confd: HashSet<String>,
+    priority: HashMap<String, i32>,
+    host_limiter: HostLimiter,
+    observer: Arc<dyn ProgressObserver>,
+    fs: Arc<dyn FileSystem>,
+    /// Tried in order, the first one that handles a plugin fetches it
+    fetchers: Vec<Arc<dyn Fetcher>>,
+    /// Starts `fish` to emit plugin events and the `post_command` hook
+    runner: Arc<dyn CommandRunner>,
+    post_command: Option<String>,
+    /// What checksums of newly installed plugins cover
+    checksum_scope: ChecksumScope,
+    /// Run every plugin's build command, not only those of plugins allowed to
+    allow_scripts: bool,
+    /// Where the programs plugin builds produce are installed
+    bin_dir: PathBuf,
+    /// Where plugins' license texts are kept
+    license_dir: PathBuf,
+    /// Commit message template when the lock file is committed to git after each change
+    git_commit_message: Option<String>,
+    registry: Registry,
+    github: GitHub,
+    blocklist: Blocklist,
+    /// Completion file names Fish ships, asked from `fish` on first use
+    shipped_completions: OnceLock<Arc<HashSet<String>>>,
+    /// Output of `fish --version`, asked on first use
+    fish_version: OnceLock<Option<String>>,
+    /// Read on first use, see [`Self::load_lock`]
+    lock_file: OnceLock<LockFile>,
+    /// Whether the in-memory lock file may differ from the one on disk
+    lock_dirty: bool,
 }
 
 impl Fin {
     /// Initialize a Fin instance
-    pub fn new(fin_path: Option<PathBuf>) -> Result<Self> {
+    ///
+    /// `fin_path` is where plugin files go and defaults to the Fish
+    /// configuration directory, which always holds the lock file.
+    pub fn new(fin_path: Option<PathBuf>, config: Config) -> Result<Self> {
+        Self::with_fs(fin_path, config, Arc::new(StdFs))
+    }
+
+    /// Initialize a Fin instance that writes plugin files through `fs`
+    pub fn with_fs(
+        fin_path: Option<PathBuf>,
+        config: Config,
+        fs: Arc<dyn FileSystem>,
+    ) -> Result<Self> {
         // Get Fish configuration directory
-        let fish_config_dir = Self::get_fish_config_dir()?;
+        let fish_config_dir = match config.fish_config_dir {
+            Some(dir) => dir,
+            None => Self::get_fish_config_dir()?,
+        };
         let fin_path = fin_path.unwrap_or_else(|| fish_config_dir.clone());
-        let fin_lock_file_path = fish_config_dir.join(FIN_LOCK_FILENAME);
-
-        // Ensure installation directories exist
-        for subdir in PLUGIN_SUBDIRS {
-            fs::create_dir_all(fin_path.join(subdir))?;
-        }
-
-        let lock_file = LockFile::load(&fin_lock_file_path).context("fin-lock.toml has broken")?;
+        let (lock_format, previous_lock_file_path) =
+            Self::find_lock_format(&fish_config_dir, config.lock_format);
+        let fin_lock_file_path = fish_config_dir.join(lock_format.filename());
 
         Ok(Self {
+            bin_dir: config.bin_dir.unwrap_or_else(|| fin_path.join(BIN_SUBDIR)),
+            license_dir: fin_path.join(LICENSES_DIRNAME),
             fin_path,
             fish_config_dir,
             fin_lock_file_path,
-            lock_file,
+            lock_format,
+            previous_lock_file_path,
+            docs_dir: config.docs_dir,
+            jobs: config.jobs.unwrap_or(DEFAULT_JOBS).max(1),
+            retries: config.retries.unwrap_or(DEFAULT_RETRIES),
+            strip_components: config.strip_components,
+            disable_confd: config.disable_confd.into_iter().collect(),
+            priority: config.priority,
+            host_limiter: HostLimiter::new(
+                config.host_jobs.unwrap_or(DEFAULT_HOST_JOBS),
+                config.host_limits,
+            ),
+            observer: Arc::new(NoopObserver),
+            fs,
+            fetchers: vec![Arc::new(LocalFetcher), Arc::new(ArchiveFetcher)],
+            runner: Arc::new(SystemRunner),
+            post_command: config.post_command,
+            checksum_scope: config.checksum_scope.unwrap_or_default(),
+            allow_scripts: config.allow_scripts,
+            git_commit_message: config.git_autocommit.then(|| {
+                config
+                    .git_commit_message
+                    .unwrap_or_else(|| DEFAULT_GIT_COMMIT_MESSAGE.to_string())
+            }),
+            registry: Registry::new(config.registry_url),
+            github: GitHub::default(),
+            blocklist: Blocklist::new(&config.blocked_checksums, config.blocklist_url),
+            shipped_completions: OnceLock::new(),
+            fish_version: OnceLock::new(),
+            lock_file: OnceLock::new(),
+            lock_dirty: false,
         })
     }
 
-    /// Install plugins
-    pub fn install(&mut self, plugins: Option<Vec<String>>, force: bool) -> Result<()> {
-        let plugins_to_install = self.get_plugins_to_install(plugins, force);
+    /// The lock file format to write, and the lock file in the other format to read first
+    ///
+    /// Without a configured format an existing `fin-lock.json` keeps being
+    /// used. When the configured format changes, the old file is converted
+    /// the next time the lock file is saved.
+    fn find_lock_format(
+        fish_config_dir: &Path,
+        configured: Option<LockFormat>,
+    ) -> (LockFormat, Option<PathBuf>) {
+        let exists = |format: LockFormat| fish_config_dir.join(format.filename()).exists();
+        let format =
+            configured.unwrap_or(if !exists(LockFormat::Toml) && exists(LockFormat::Json) {
+                LockFormat::Json
+            } else {
+                LockFormat::Toml
+            });
+        let other = match format {
+            LockFormat::Toml => LockFormat::Json,
+            LockFormat::Json => LockFormat::Toml,
+        };
+        let previous =
+            (!exists(format) && exists(other)).then(|| fish_config_dir.join(other.filename()));
+        (format, previous)
+    }
+
+    /// Start external programs such as `fish` through `runner`
+    pub fn with_runner(mut self, runner: Arc<dyn CommandRunner>) -> Self {
+        self.runner = runner;
+        self
+    }
+
+    /// Report progress of installs and updates to `observer`
+    pub fn with_observer(mut self, observer: Arc<dyn ProgressObserver>) -> Self {
+        self.observer = observer;
+        self
+    }
+
+    /// Keep the license texts of installed plugins in `dir` instead of below `fin_path`
+    pub fn with_license_dir(mut self, dir: PathBuf) -> Self {
+        self.license_dir = dir;
+        self
+    }
+
+    /// Fetch plugins `fetcher` handles with it, ahead of the built-in fetchers
+    pub fn with_fetcher(mut self, fetcher: Arc<dyn Fetcher>) -> Self {
+        self.fetchers.insert(0, fetcher);
+        self
+    }
+
+    /// Install plugins given as `owner/repo[@ref]`, or everything in the lock file when `None`
+    ///
+    /// Already installed plugins are skipped unless `options.force` is set.
+    /// A plugin failing to install does not stop the others.
+    pub fn install(
+        &mut self,
+        plugins: Option<Vec<String>>,
+        options: InstallOptions,
+    ) -> Result<InstallReport> {
+        self.load_lock()?;
+        let (plugins_to_install, skipped) = match plugins {
+            Some(plugins) => {
+                let requested = parse_requested(&plugins)?;
+                let (to_install, skipped) = self.get_plugins_to_install(&requested, options.force);
+                (to_install.into_iter().cloned().collect(), skipped)
+            }
+            None => {
+                let (to_install, skipped) =
+                    self.get_plugins_to_install(self.loaded_lock().plugins.sorted(), options.force);
+                (to_install.into_iter().cloned().collect(), skipped)
+            }
+        };
+        let (plugins_to_install, skipped) =
+            self.with_reinstalls(plugins_to_install, skipped, &options.reinstall)?;
+
+        self.install_plugins(plugins_to_install, skipped, options)
+    }
+
+    /// Add the recorded entries of `reinstall` to the plugins to install
+    ///
+    /// They replace any requested spec of the same name and are fetched
+    /// again from their recorded source, so the stale-file cleanup swaps
+    /// their files while pinning and other settings stay as they were.
+    fn with_reinstalls(
+        &self,
+        mut plugins: Vec<Plugin>,
+        mut skipped: Vec<String>,
+        reinstall: &[String],
+    ) -> Result<(Vec<Plugin>, Vec<String>)> {
+        for name in reinstall {
+            let Some(installed) = self.loaded_lock().plugins.get(name) else {
+                return Err(FinError::NotReinstallable(name.clone()));
+            };
+            plugins.retain(|plugin| plugin.name != *name);
+            skipped.retain(|skipped| skipped != name);
+            plugins.push(Plugin {
+                commit_hash: None,
+                installed_files: None,
+                checksum: None,
+                ..installed.clone()
+            });
+        }
+        Ok((plugins, skipped))
+    }
+
+    /// Install every plugin recorded in another lock file, such as one from `fin export --lock`
+    ///
+    /// With `options.frozen` the install is all or nothing: if any plugin
+    /// fails the lock file is not written and [`FinError::Frozen`] is returned.
+    pub fn install_from_lock(
+        &mut self,
+        lock_file: &LockFile,
+        options: InstallOptions,
+    ) -> Result<InstallReport> {
+        self.load_lock()?;
+        let (to_install, skipped) =
+            self.get_plugins_to_install(lock_file.plugins.sorted(), options.force);
+        let (adopted, to_install): (Vec<&Plugin>, Vec<&Plugin>) = to_install
+            .into_iter()
+            .partition(|plugin| options.ignore_existing && self.is_in_place(plugin));
+        for plugin in &adopted {
+            self.loaded_lock_mut().plugins.insert((*plugin).clone());
+        }
+        let plugins_to_install = to_install
+            .into_iter()
+            .map(|plugin| Plugin {
+                installed_files: None,
+                ..plugin.clone()
+            })
+            .collect();
+
+        let mut report = self.install_plugins(plugins_to_install, skipped, options)?;
+        report.adopted = adopted.into_iter().map(|p| p.name.clone()).collect();
+        Ok(report)
+    }
+
+    /// Whether every file recorded for `plugin` is in place with the recorded contents
+    fn is_in_place(&self, plugin: &Plugin) -> bool {
+        let (Some(expected), Some(files)) = (&plugin.checksum, &plugin.installed_files) else {
+            return false;
+        };
+        if plugin.checksum_scope == Some(ChecksumScope::Tarball) {
+            return false;
+        }
+        !files.is_empty()
+            && checksum(&*self.fs, &self.fish_config_dir, files)
+                .is_ok_and(|actual| &actual == expected)
+    }
+
+    /// Install plugins picked by [`Self::get_plugins_to_install`] and record them
+    fn install_plugins(
+        &mut self,
+        plugins_to_install: Vec<Plugin>,
+        skipped: Vec<String>,
+        options: InstallOptions,
+    ) -> Result<InstallReport> {
+        let plugins_to_install: Vec<Plugin> = plugins_to_install
+            .into_iter()
+            .map(|plugin| self.with_install_settings(plugin, &options))
+            .collect();
+        let mut report = InstallReport {
+            skipped,
+            ..Default::default()
+        };
+        let damaged: HashSet<String> = plugins_to_install
+            .iter()
+            .filter(|p| !options.force && self.is_damaged(&p.name))
+            .map(|p| p.name.clone())
+            .collect();
 
         if plugins_to_install.is_empty() {
-            println!("All plugins are already installed");
-            return Ok(());
+            // Adopted plugins may still need recording
+            self.save_lock()?;
+            return Ok(report);
+        }
+        self.check_writable()?;
+
+        let order: Vec<String> = plugins_to_install.iter().map(|p| p.name.clone()).collect();
+        let runtime = self.runtime()?;
+        let results = runtime.block_on(self.install_all(plugins_to_install, &options))?;
+
+        for (plugin, result) in results {
+            match result {
+                Ok((installed, build_output)) => {
+                    if damaged.contains(&installed.name) {
+                        report.repaired.push(installed.name.clone());
+                    }
+                    if let Some(output) = build_output {
+                        report.build_output.push((installed.name.clone(), output));
+                    }
+                    report.installed.push(installed);
+                }
+                Err(err) => report.failed.push((plugin, err)),
+            }
+        }
+        report.interrupted = report
+            .failed
+            .iter()
+            .any(|(_, err)| matches!(err, FinError::Interrupted));
+
+        if options.defer_events {
+            // Requested order, so interdependent plugins see a stable sequence
+            let mut installed: Vec<&Plugin> = report.installed.iter().collect();
+            installed.sort_by_key(|p| order.iter().position(|name| *name == p.name));
+            for plugin in installed.into_iter().filter(|p| !p.raw) {
+                let files = plugin.installed_files.iter().flatten();
+                if let Err(err) = events::emit(
+                    &*self.runner,
+                    self.event_for(plugin),
+                    &self.fin_path,
+                    &conf_files(files.map(|f| self.fish_config_dir.join(f))),
+                    plugin.confd_prefix().as_deref(),
+                ) {
+                    self.observer.warning(plugin, &Warning::Events(err));
+                }
+            }
         }
 
-        println!("Installing {} plugins...", plugins_to_install.len());
+        if options.frozen && !report.failed.is_empty() {
+            return Err(FinError::Frozen {
+                failed: report.failed.into_iter().map(|(p, _)| p.name).collect(),
+            });
+        }
 
-        let installed_plugins: Vec<_> = plugins_to_install
-            .into_par_iter()
-            .filter_map(|plugin| self.install_plugin(plugin).ok())
+        let actions: Vec<Event> = report
+            .installed
+            .iter()
+            .map(|plugin| self.event_for(plugin))
             .collect();
+        for plugin in &report.installed {
+            if let Some(previous) = self.loaded_lock_mut().plugins.insert(plugin.clone()) {
+                self.drop_stale_files(&previous, plugin);
+                self.drop_stale_shadows(&previous, plugin);
+            }
+        }
+        self.reassign_overridden_files(&report.installed);
+        self.save_lock()?;
+        if options.check_functions {
+            self.warn_duplicate_functions(&report.installed)?;
+        }
+        for (plugin, action) in report.installed.iter().zip(actions) {
+            if let Err(err) = self.run_hook(action, plugin) {
+                report.hook_failed.push((plugin.clone(), err));
+            }
+        }
+        Ok(report)
+    }
+
+    /// Remove plugins by name and delete their installed files
+    ///
+    /// A plugin's uninstall hook runs after its uninstall event and before
+    /// its files are deleted. The plugin is removed even if the hook fails.
+    pub fn remove(&mut self, plugins: &[String], options: RemoveOptions) -> Result<RemoveReport> {
+        self.load_lock()?;
+        let mut report = RemoveReport::default();
+
+        for name in plugins {
+            let Some(plugin) = self.loaded_lock_mut().plugins.remove(name) else {
+                report.not_installed.push(name.clone());
+                continue;
+            };
 
-        for plugin in &installed_plugins {
-            println!("Installed: {}", &plugin.name);
+            if let Some(files) = plugin.installed_files.as_ref().filter(|_| !plugin.raw) {
+                let confs = conf_files(files.iter().map(|f| self.fish_config_dir.join(f)));
+                // Handlers are best effort, a broken one must not keep the files around
+                if let Err(err) = events::emit(
+                    &*self.runner,
+                    Event::Uninstall,
+                    &self.fin_path,
+                    &confs,
+                    plugin.confd_prefix().as_deref(),
+                ) {
+                    self.observer.warning(&plugin, &Warning::Events(err));
+                }
+                if let Some(hook) = plugin.uninstall_hook.as_ref().filter(|_| !options.no_hooks) {
+                    match events::run_uninstall_hook(
+                        &*self.runner,
+                        hook,
+                        &self.fin_path,
+                        &confs,
+                        UNINSTALL_HOOK_TIMEOUT,
+                    ) {
+                        Ok(output) if output.trim().is_empty() => {}
+                        Ok(output) => report.hook_output.push((plugin.name.clone(), output)),
+                        Err(err) => report.hook_failed.push((plugin.clone(), err)),
+                    }
+                }
+            }
+            let missing = self.remove_files(&plugin, &HashSet::new());
+            let _ = self.fs.remove_file(&self.license_path(&plugin));
+            report.missing_files.extend(missing);
+            report.removed.push(plugin);
         }
 
-        self.lock_file.plugins.extend(installed_plugins);
-        self.lock_file.save(&self.fin_lock_file_path)?;
-        Ok(())
+        self.save_lock()?;
+        if !report.removed.is_empty() {
+            // Only empty ones go, after the lock file's metadata snippet may have left conf.d
+            for subdir in PLUGIN_SUBDIRS {
+                let _ = self.fs.remove_dir(&self.fin_path.join(subdir));
+            }
+        }
+        for plugin in &report.removed {
+            if let Err(err) = self.run_hook(Event::Uninstall, plugin) {
+                report.hook_failed.push((plugin.clone(), err));
+            }
+        }
+        Ok(report)
     }
 
-    /// Remove plugins
-    pub fn remove(&mut self, plugins: &[String]) -> Result<()> {
-        let plugins_to_remove: HashSet<_> = plugins.iter().collect();
-        let mut removed_count = 0;
+    /// Delete the files `plugin` installed, except the `kept` ones
+    ///
+    /// Files the plugin took from another one are handed back to it, and
+    /// copies of its own files kept aside are deleted too. Returns the files
+    /// that were already gone.
+    fn remove_files(&mut self, plugin: &Plugin, kept: &HashSet<String>) -> Vec<PathBuf> {
+        let mut missing = Vec::new();
+        let files = plugin.installed_files.iter().flatten();
+        for file in files.filter(|file| !plugin.raw && !kept.contains(*file)) {
+            if self.restore_shadowed(file) {
+                continue;
+            }
+            let path = self.fish_config_dir.join(file);
+            if self.fs.remove_file(&path).is_err() {
+                missing.push(path);
+            }
+        }
+        for file in plugin.shadowed_files.iter().flatten() {
+            let shadow = shadow_path(&self.fin_path.join(SHADOW_DIRNAME), &plugin.name);
+            let _ = self.fs.remove_file(&shadow.join(file));
+        }
+        if plugin.raw {
+            let _ = self.fs.remove_dir_all(&self.raw_dir(plugin));
+        }
+        missing
+    }
 
-        self.lock_file.plugins.retain(|plugin| {
-            if !plugins_to_remove.contains(&plugin.name) {
-                return true;
+    /// Install `new` in place of the installed plugin `old` and remove `old`
+    ///
+    /// All or nothing: when `new` fails, `old` stays installed and the lock
+    /// file is left alone. `old`'s files do not count as conflicts, and
+    /// those `new` does not ship are deleted afterwards. Its uninstall event
+    /// and hook do not run, since a successor usually keeps the settings
+    /// they would erase. Tracking the newest release, a pinned archive
+    /// digest, the priority and the allowed scripts and completions carry
+    /// over; a ref pinned on `old` does not, as `new` has other refs.
+    pub fn replace(&mut self, old: &str, new: &str) -> Result<ReplaceReport> {
+        self.load_lock()?;
+        let Some(previous) = self.loaded_lock_mut().plugins.remove(old) else {
+            return Err(FinError::NotInstalled(old.to_string()));
+        };
+        let spec = match previous.latest && !new.contains('@') {
+            true => format!("{new}@{LATEST_REF}"),
+            false => new.to_string(),
+        };
+        let options = InstallOptions {
+            force: true,
+            frozen: true,
+            priority: previous.priority,
+            allow_shadow_completions: previous.shadow_completions,
+            pin_digest: previous.archive_digest.is_some(),
+            allow_scripts: previous.allow_scripts,
+            ..Default::default()
+        };
+        let installed = parse_requested(&[spec])
+            .and_then(|plugins| self.install_plugins(plugins, Vec::new(), options));
+        let mut installed = match installed {
+            Ok(report) => report,
+            Err(err) => {
+                self.loaded_lock_mut().plugins.insert(previous);
+                return Err(match err {
+                    FinError::Frozen { .. } => FinError::Replace {
+                        old: old.to_string(),
+                        new: new.to_string(),
+                    },
+                    err => err,
+                });
             }
+        };
 
-            if let Some(files) = &plugin.installed_files {
-                for file in files {
-                    let plugin_path = &self.fish_config_dir.join(file);
-                    // Ignore error for now
-                    let _ = fs::remove_file(plugin_path).map_err(|_| {
-                        println!("File not found: {}", file);
-                    });
+        // Being frozen, the install only succeeded if the plugin installed
+        let plugin = installed.installed.remove(0);
+        let kept: HashSet<String> = plugin.installed_files.clone().unwrap_or_default();
+        let missing_files = self.remove_files(&previous, &kept);
+        if previous.name != plugin.name {
+            let _ = self.fs.remove_file(&self.license_path(&previous));
+        }
+        self.save_lock()?;
+        Ok(ReplaceReport {
+            installed: plugin,
+            removed: previous,
+            build_output: installed.build_output,
+            missing_files,
+            hook_failed: installed.hook_failed,
+        })
+    }
+
+    /// The license of every installed plugin, sorted by name
+    ///
+    /// `full` adds the license texts kept at install time. Plugins
+    /// installed before fin recorded licenses have none until updated.
+    pub fn licenses(&self, full: bool) -> Result<Vec<LicenseInfo>> {
+        Ok(self
+            .list()?
+            .into_iter()
+            .map(|plugin| LicenseInfo {
+                name: plugin.name.clone(),
+                license: plugin.license.clone(),
+                text: full.then(|| self.license_path(plugin)).and_then(|path| {
+                    let mut text = String::new();
+                    let mut file = self.fs.open(&path).ok()?;
+                    file.read_to_string(&mut text).ok()?;
+                    Some(text)
+                }),
+            })
+            .collect())
+    }
+
+    /// Where the license text of `plugin` is kept
+    fn license_path(&self, plugin: &Plugin) -> PathBuf {
+        let file = format!("{}.txt", plugin.name.replace('/', "__"));
+        self.license_dir.join(file)
+    }
+
+    /// Installed plugins the registry marks as deprecated or renamed
+    ///
+    /// Checks every installed plugin when `plugins` is empty. Silent
+    /// without registry data: when no index can be loaded nothing is
+    /// reported. The registry only lists GitHub plugins, so it is not
+    /// loaded unless one is installed.
+    pub fn deprecations(&self, plugins: &[String]) -> Result<Vec<Deprecation>> {
+        let repos: Vec<(&Plugin, &str)> = self
+            .list()?
+            .into_iter()
+            .filter(|plugin| plugins.is_empty() || plugins.contains(&plugin.name))
+            .filter_map(|plugin| Some((plugin, github::repo_and_ref(plugin)?.0)))
+            .collect();
+        if repos.is_empty() {
+            return Ok(Vec::new());
+        }
+        let Ok(loaded) = self.registry.load() else {
+            return Ok(Vec::new());
+        };
+        Ok(repos
+            .into_iter()
+            .filter_map(|(plugin, repo)| {
+                let entry = loaded.index.by_repo(repo)?;
+                (entry.deprecated.is_some() || entry.replaced_by.is_some()).then(|| Deprecation {
+                    name: plugin.name.clone(),
+                    reason: entry.deprecated.clone(),
+                    replacement: entry.replaced_by.clone(),
+                })
+            })
+            .collect())
+    }
+
+    /// Installed plugins that none of the `declared` lock files list
+    ///
+    /// The declared lock files are the intended plugin set, e.g. shared
+    /// ones from `fin export --lock`. Sorted by name.
+    pub fn orphans(&self, declared: &[LockFile]) -> Result<Vec<String>> {
+        Ok(self
+            .list()?
+            .into_iter()
+            .filter(|plugin| {
+                !declared
+                    .iter()
+                    .any(|lock_file| lock_file.plugins.contains_name(&plugin.name))
+            })
+            .map(|plugin| plugin.name.clone())
+            .collect())
+    }
+
+    /// Files in the plugin directories of the installation path that no plugin owns
+    ///
+    /// Left behind by removed plugins, interrupted installs or copied in by
+    /// hand. fin's own metadata snippet and the wrapper from `fin init
+    /// --install` do not count. Sorted.
+    pub fn orphaned_files(&self) -> Result<Vec<PathBuf>> {
+        let owned: HashSet<PathBuf> = self
+            .list()?
+            .into_iter()
+            .flat_map(|plugin| plugin.installed_files.iter().flatten())
+            .map(|file| self.fish_config_dir.join(file))
+            .collect();
+        let own_files = [
+            self.fin_path.join(CONFD_SUBDIR).join(META_FILENAME),
+            self.fish_config_dir
+                .join("functions")
+                .join(WRAPPER_FILENAME),
+            self.fish_config_dir
+                .join("completions")
+                .join(WRAPPER_FILENAME),
+        ];
+        let mut orphaned = Vec::new();
+        for subdir in PLUGIN_SUBDIRS {
+            let dir = self.fin_path.join(subdir);
+            if !self.fs.is_dir(&dir) {
+                continue;
+            }
+            for path in self.fs.read_dir(&dir).with_path("read", &dir)? {
+                if !self.fs.is_dir(&path) && !owned.contains(&path) && !own_files.contains(&path) {
+                    orphaned.push(path);
                 }
             }
-            removed_count += 1;
-            println!("Removed: {}", &plugin.name);
-            false
-        });
+        }
+        orphaned.sort();
+        Ok(orphaned)
+    }
 
-        println!("Removed {removed_count} plugins total");
-        self.lock_file.save(&self.fin_lock_file_path)?;
-        Ok(())
+    /// Compare the lock file with what is on disk, without changing anything
+    ///
+    /// Checks that every file a plugin installed still exists, and lists
+    /// the [orphaned files](Self::orphaned_files) next to them.
+    pub fn status(&self) -> Result<StatusReport> {
+        let mut report = StatusReport::default();
+        for plugin in self.list()? {
+            let missing = self.disk_usage(plugin).missing_files;
+            match missing.is_empty() {
+                true => report.ok.push(plugin.name.clone()),
+                false => report.incomplete.push((plugin.name.clone(), missing)),
+            }
+        }
+        report.orphaned_files = self.orphaned_files()?;
+        Ok(report)
+    }
+
+    /// The plugins listed in `fin.toml` in the Fish config directory, if it has one
+    pub fn install_manifest(&self) -> Result<Option<InstallManifest>> {
+        InstallManifest::read(&*self.fs, &self.fish_config_dir)
     }
 
-    /// Update plugins
-    pub fn update(&mut self, plugins: &[String]) -> Result<()> {
-        let installed_plugins: std::collections::HashSet<String> =
-            self.plugins().map(|p| p.to_string()).collect();
+    /// Installed plugins that none of the install `specs` name, sorted by name
+    ///
+    /// What `fin install --sync` removes to match the manifest.
+    pub fn unlisted(&self, specs: &[String]) -> Result<Vec<String>> {
+        let listed: Plugins = parse_requested(specs)?.into_iter().collect();
+        Ok(self
+            .list()?
+            .into_iter()
+            .filter(|plugin| !listed.contains_name(&plugin.name))
+            .map(|plugin| plugin.name.clone())
+            .collect())
+    }
 
-        let plugins_to_update: Vec<String> = if plugins.is_empty() {
-            installed_plugins.into_iter().collect()
-        } else {
-            plugins
-                .iter()
-                .filter(|&p| installed_plugins.contains(p))
-                .cloned()
-                .collect()
+    /// Update plugins by name, or all installed plugins when `plugins` is empty
+    ///
+    /// GitHub plugins whose recorded commit is still the one their ref
+    /// points to are left alone, everything else is fetched again: other
+    /// sources, `@latest` plugins, plugins without a recorded commit and
+    /// ones the GitHub API could not tell about. The lock file is saved by
+    /// the reinstall, and only if anything changed.
+    pub fn update(&mut self, plugins: &[String]) -> Result<UpdateReport> {
+        let (names, not_installed) = self.installed_and_missing(plugins)?;
+        let mut report = UpdateReport {
+            not_installed,
+            ..Default::default()
         };
+        if names.is_empty() {
+            return Ok(report);
+        }
 
-        if plugins_to_update.is_empty() {
-            println!("No plugins to update");
-            return Ok(());
+        report.up_to_date = self.unmoved(&names)?;
+        report.deprecated = self.deprecations(&names)?;
+        let names: Vec<String> = names
+            .into_iter()
+            .filter(|name| !report.up_to_date.contains(name))
+            .collect();
+        if names.is_empty() {
+            return Ok(report);
         }
 
-        println!("Updating {} plugins...", plugins_to_update.len());
+        let previous: HashMap<String, (Option<String>, Option<ChecksumScope>)> = names
+            .iter()
+            .filter_map(|name| self.loaded_lock().plugins.get(name))
+            .map(|p| (p.name.clone(), (p.checksum.clone(), p.checksum_scope)))
+            .collect();
+        // Fetched again from the recorded source, keeping the recorded settings
+        let plugins_to_update = names
+            .iter()
+            .filter_map(|name| self.loaded_lock().plugins.get(name))
+            .map(|plugin| Plugin {
+                installed_files: None,
+                checksum: None,
+                ..plugin.clone()
+            })
+            .collect();
+        let installed = self.install_plugins(
+            plugins_to_update,
+            Vec::new(),
+            InstallOptions {
+                force: true,
+                ..Default::default()
+            },
+        )?;
+        // Only checksums of the same kind compare, and plugins without one are not reported
+        report.changed = installed
+            .installed
+            .iter()
+            .filter(|plugin| match previous.get(&plugin.name) {
+                Some((Some(checksum), scope)) => {
+                    *scope == plugin.checksum_scope
+                        && plugin.checksum.as_ref().is_some_and(|new| new != checksum)
+                }
+                _ => false,
+            })
+            .map(|plugin| plugin.name.clone())
+            .collect();
+        report.updated = installed.installed;
+        report.failed = installed.failed;
+        report.interrupted = installed.interrupted;
+        report.hook_failed = installed.hook_failed;
+        report.build_output = installed.build_output;
+        Ok(report)
+    }
 
-        // Update by removing then reinstalling
-        self.install(Some(plugins_to_update), true)
+    /// The GitHub plugins among `names` whose ref still points to their recorded commit
+    fn unmoved(&self, names: &[String]) -> Result<Vec<String>> {
+        let checkable: Vec<&Plugin> = names
+            .iter()
+            .filter_map(|name| self.loaded_lock().plugins.get(name))
+            .filter(|plugin| {
+                !plugin.latest
+                    && plugin.commit_hash.is_some()
+                    && github::repo_and_ref(plugin).is_some()
+            })
+            .collect();
+        if checkable.is_empty() {
+            return Ok(Vec::new());
+        }
+        let latest = self.github.latest_commits(&checkable)?;
+        Ok(checkable
+            .into_iter()
+            .zip(latest)
+            .filter(|(plugin, latest)| {
+                latest
+                    .as_ref()
+                    .is_ok_and(|latest| plugin.commit_hash.as_ref() == Some(latest))
+            })
+            .map(|(plugin, _)| plugin.name.clone())
+            .collect())
     }
 
-    /// List installed plugins
-    pub fn list(&self) -> Result<()> {
-        for plugin in self.plugins() {
-            println!("{plugin}");
+    /// What updating plugins would change in their files, without updating them
+    ///
+    /// Each plugin is fetched again into a staging directory and its files
+    /// are compared with the installed ones. `diff` adds unified diffs of
+    /// the changed text files. Build commands do not run, so files they
+    /// would produce are not compared.
+    pub fn preview_update(&self, plugins: &[String], diff: bool) -> Result<PreviewReport> {
+        let (names, not_installed) = self.installed_and_missing(plugins)?;
+        let mut report = PreviewReport {
+            not_installed,
+            ..Default::default()
+        };
+        if names.is_empty() {
+            return Ok(report);
         }
 
-        Ok(())
+        let installed: Vec<&Plugin> = names
+            .iter()
+            .filter_map(|name| self.loaded_lock().plugins.get(name))
+            .collect();
+        let runtime = self.runtime()?;
+        let results: Vec<(&Plugin, Result<UpdatePreview>)> = runtime.block_on(async {
+            let client = fetch::client()?;
+            let previews = stream::iter(installed).map(|installed| {
+                let client = &client;
+                async move {
+                    let result = self.preview_plugin(client, installed, diff).await;
+                    (installed, result)
+                }
+            });
+            Ok::<_, FinError>(previews.buffer_unordered(self.jobs).collect().await)
+        })?;
+
+        for (installed, result) in results {
+            match result {
+                Ok(preview) => report.plugins.push(preview),
+                Err(err) => report.failed.push((installed.clone(), err)),
+            }
+        }
+        // By name regardless of which download finished first
+        report.plugins.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(report)
     }
 
-    /// Get Fish configuration directory
-    fn get_fish_config_dir() -> Result<PathBuf> {
-        // Prefer environment variable, fallback to default path
-        if let Ok(path) = env::var("__fish_config_dir") {
-            Ok(PathBuf::from(path))
+    /// Fetch one installed plugin again and compare the files it would install
+    async fn preview_plugin(
+        &self,
+        client: &reqwest::Client,
+        installed: &Plugin,
+        diff: bool,
+    ) -> Result<UpdatePreview> {
+        let mut plugin = self.with_install_settings(
+            Plugin {
+                commit_hash: None,
+                installed_files: None,
+                checksum: None,
+                ..installed.clone()
+            },
+            &InstallOptions::default(),
+        );
+        self.observer.plugin_started(&plugin);
+        let (staging, _) = self.fetch_plugin(client, &mut plugin).await?;
+        let raw_dir = plugin.raw.then(|| self.raw_dir(&plugin));
+        let fs = self.fs.clone();
+        let fin_path = self.fin_path.clone();
+        let fish_config_dir = self.fish_config_dir.clone();
+        let docs_dir = self.docs_dir.clone();
+        let installed = installed.clone();
+        blocking(move || {
+            let src = staging.path();
+            let mut transfers = match &raw_dir {
+                Some(raw_dir) => raw_file_moves(&*fs, src, raw_dir)?,
+                None => plugin_file_moves(&*fs, &fin_path, src, &plugin, &mut Vec::new())?,
+            };
+            if let Some(docs_dir) = docs_dir.as_ref().filter(|_| raw_dir.is_none()) {
+                transfers.extend(plugin_doc_copies(&*fs, &plugin, src, docs_dir)?);
+            }
+            let relative = |path: &Path| {
+                path.strip_prefix(&fish_config_dir)
+                    .unwrap_or(path)
+                    .to_string_lossy()
+                    .to_string()
+            };
+            let incoming: BTreeMap<String, PathBuf> = transfers
+                .into_iter()
+                .map(|(src, dest, _)| (relative(&dest), src))
+                .collect();
+            let current = installed.installed_files.clone().unwrap_or_default();
+
+            let mut preview = UpdatePreview {
+                name: installed.name.clone(),
+                ..Default::default()
+            };
+            let mut removed: Vec<&String> = current
+                .iter()
+                .filter(|file| !incoming.contains_key(*file))
+                .collect();
+            removed.sort();
+            preview.removed = removed.into_iter().cloned().collect();
+            for (file, new_path) in &incoming {
+                if !current.contains(file) {
+                    // Kept aside for another plugin's copy, not new
+                    if !provides(&installed, file) {
+                        preview.added.push(file.clone());
+                    }
+                    continue;
+                }
+                let old = read_bytes(&*fs, &fish_config_dir.join(file));
+                let new = read_bytes(&*fs, new_path)?;
+                if old.as_ref().is_ok_and(|old| *old == new) {
+                    continue;
+                }
+                preview.modified.push(file.clone());
+                if let (true, Ok(old)) = (diff, &old)
+                    && let (Some(old), Some(new)) = (as_text(old), as_text(&new))
+                {
+                    let name = |side| format!("{side}/{file}");
+                    preview.diffs.push((
+                        file.clone(),
+                        diff::unified(old, new, &name("a"), &name("b")),
+                    ));
+                }
+            }
+            Ok(preview)
+        })
+        .await
+    }
+
+    /// Emit the install event of installed plugins again
+    ///
+    /// For plugins whose files got in place without fin, e.g. copied from a
+    /// backup, or whose universal variables were wiped. Plugins go in the
+    /// order Fish loads their `conf.d` snippets, those without snippets and
+    /// [raw](Plugin::raw) installs are skipped.
+    pub fn run_hooks(&self, plugins: &[String]) -> Result<HooksReport> {
+        let (names, not_installed) = self.installed_and_missing(plugins)?;
+        let mut report = HooksReport {
+            not_installed,
+            ..Default::default()
+        };
+        let mut selected: Vec<(Vec<PathBuf>, &Plugin)> = names
+            .iter()
+            .filter_map(|name| self.loaded_lock().plugins.get(name))
+            .filter(|plugin| !plugin.raw)
+            .map(|plugin| {
+                let files = plugin.installed_files.iter().flatten();
+                let mut snippets = conf_files(files.map(|f| self.fish_config_dir.join(f)));
+                snippets.sort_by(|a, b| a.file_name().cmp(&b.file_name()));
+                (snippets, plugin)
+            })
+            .filter(|(snippets, _)| !snippets.is_empty())
+            .collect();
+        selected.sort_by(|(a, _), (b, _)| a[0].file_name().cmp(&b[0].file_name()));
+
+        for (snippets, plugin) in selected {
+            match events::emit_reporting(
+                &*self.runner,
+                Event::Install,
+                &self.fin_path,
+                &snippets,
+                plugin.confd_prefix().as_deref(),
+            ) {
+                Ok((handled, output)) => report.plugins.push(HookRun {
+                    name: plugin.name.clone(),
+                    handled,
+                    output,
+                }),
+                Err(err) => report.failed.push((plugin.clone(), err)),
+            }
+        }
+        Ok(report)
+    }
+
+    /// Split plugin names into installed and unknown ones, all installed plugins when empty
+    fn installed_and_missing(&self, plugins: &[String]) -> Result<(Vec<String>, Vec<String>)> {
+        self.load_lock()?;
+        Ok(if plugins.is_empty() {
+            (self.plugins().map(String::from).collect(), Vec::new())
         } else {
-            dirs::home_dir()
-                .map(|p| p.join(".config/fish"))
-                .context("Failed to get user home directory")
+            plugins
+                .iter()
+                .cloned()
+                .partition(|p| self.loaded_lock().plugins.contains_name(p))
+        })
+    }
+
+    /// Fish commands that load freshly installed plugins into a running shell
+    ///
+    /// Functions are sourced before `conf.d` snippets so the snippets can use
+    /// them, completions come last.
+    pub fn activation_commands(&self, plugins: &[Plugin]) -> Vec<String> {
+        let mut files: Vec<(usize, PathBuf)> = plugins
+            .iter()
+            .filter(|p| !p.raw)
+            .filter_map(|p| p.installed_files.as_ref())
+            .flatten()
+            .map(|file| self.fish_config_dir.join(file))
+            .filter_map(|path| {
+                let subdir = path.parent()?.file_name()?.to_str()?;
+                let order = PLUGIN_SUBDIRS.iter().position(|&d| d == subdir)?;
+                (path.extension()? == "fish").then_some((order, path))
+            })
+            .collect();
+        files.sort();
+
+        files
+            .into_iter()
+            .map(|(_, path)| format!("source {}", fish_quote(&path.to_string_lossy())))
+            .collect()
+    }
+
+    /// Installed plugins sorted by name
+    pub fn list(&self) -> Result<Vec<&Plugin>> {
+        Ok(self.load_lock()?.plugins.sorted())
+    }
+
+    /// The installed plugin called `name`
+    pub fn plugin(&self, name: &str) -> Result<Option<&Plugin>> {
+        Ok(self.load_lock()?.plugins.get(name))
+    }
+
+    /// Where a [raw](Plugin::raw) install of `plugin` puts its files
+    pub fn raw_dir(&self, plugin: &Plugin) -> PathBuf {
+        shadow_path(&self.fin_path.join(RAW_DIRNAME), &plugin.name)
+    }
+
+    /// Absolute paths of the files installed by `plugin`, sorted
+    pub fn installed_paths(&self, plugin: &Plugin) -> Vec<PathBuf> {
+        let mut paths: Vec<_> = plugin
+            .installed_files
+            .iter()
+            .flatten()
+            .map(|file| self.fish_config_dir.join(file))
+            .collect();
+        paths.sort();
+        paths
+    }
+
+    /// Sum the sizes of the files installed by `plugin`
+    ///
+    /// Files that no longer exist are listed instead of failing.
+    pub fn disk_usage(&self, plugin: &Plugin) -> DiskUsage {
+        let mut usage = DiskUsage::default();
+        for path in self.installed_paths(plugin) {
+            match self.fs.file_size(&path) {
+                Ok(size) => usage.bytes += size,
+                Err(_) => usage.missing_files.push(path),
+            }
         }
+        usage
+    }
+
+    /// Every installed plugin with all its lock file fields, sorted by name
+    ///
+    /// The installed file lists are left out unless `include_files` is set,
+    /// which also checks whether each file is still on disk.
+    pub fn states(&self, include_files: bool) -> Result<Vec<PluginState>> {
+        Ok(self
+            .list()?
+            .into_iter()
+            .map(|plugin| PluginState {
+                files: include_files.then(|| {
+                    self.installed_paths(plugin)
+                        .into_iter()
+                        .map(|path| FileState {
+                            exists: self.fs.file_size(&path).is_ok(),
+                            path,
+                        })
+                        .collect()
+                }),
+                plugin: Plugin {
+                    installed_files: plugin.installed_files.clone().filter(|_| include_files),
+                    shadowed_files: plugin.shadowed_files.clone().filter(|_| include_files),
+                    ..plugin.clone()
+                },
+            })
+            .collect())
+    }
+
+    /// The lock file and every plugin's files with their sizes, checksums and drift
+    ///
+    /// Works offline, so plugins that record the checksum of their archive
+    /// instead of their files are not checked for modifications.
+    pub fn inventory(&self) -> Result<Inventory> {
+        let lock = self.load_lock()?;
+        let plugins = self
+            .list()?
+            .into_iter()
+            .map(|plugin| {
+                let files: Vec<FileInventory> = self
+                    .installed_paths(plugin)
+                    .into_iter()
+                    .map(|path| {
+                        let size = self.fs.file_size(&path).ok();
+                        FileInventory {
+                            exists: size.is_some(),
+                            checksum: size.and_then(|_| checksum_contents(&*self.fs, &path).ok()),
+                            size,
+                            path,
+                        }
+                    })
+                    .collect();
+                let modified = match (&plugin.checksum, &plugin.installed_files) {
+                    (Some(expected), Some(installed))
+                        if plugin.checksum_scope != Some(ChecksumScope::Tarball) =>
+                    {
+                        Some(
+                            !checksum(&*self.fs, &self.fish_config_dir, installed)
+                                .is_ok_and(|actual| &actual == expected),
+                        )
+                    }
+                    _ => None,
+                };
+                PluginInventory {
+                    drift: Drift {
+                        missing_files: files.iter().filter(|file| !file.exists).count(),
+                        modified,
+                    },
+                    files,
+                    plugin: plugin.clone(),
+                }
+            })
+            .collect();
+        Ok(Inventory {
+            inventory_version: INVENTORY_VERSION,
+            lock: LockInventory {
+                path: self.fin_lock_file_path.clone(),
+                version: lock.version.clone(),
+                generated_at: lock.generated_at,
+            },
+            plugins,
+        })
     }
 
-    fn get_plugins_to_install(&self, plugins: Option<Vec<String>>, force: bool) -> HashSet<Plugin> {
-        let mut plugins_to_install = if let Some(plugins) = plugins {
-            plugins.iter().map(|p| Plugin::from(p.as_str())).collect()
+    /// Compare installed files against the checksums in the lock file
+    ///
+    /// Checks every installed plugin when `plugins` is empty. Up to `jobs`
+    /// plugins are hashed at the same time, which hides the latency of
+    /// network filesystems. Plugins whose checksum covers the downloaded
+    /// archive are only checked with `download`, which fetches their archives
+    /// again; otherwise they are unchecked and nothing touches the network.
+    /// The report lists plugins sorted by name like [`Self::list`].
+    pub fn verify(&self, plugins: &[String], download: bool) -> Result<VerifyReport> {
+        let selected: Vec<&Plugin> = self
+            .list()?
+            .into_iter()
+            .filter(|plugin| plugins.is_empty() || plugins.contains(&plugin.name))
+            .collect();
+        let is_archive = |plugin: &Plugin| plugin.checksum_scope == Some(ChecksumScope::Tarball);
+        let archives: Vec<&Plugin> = if download {
+            selected.iter().copied().filter(|p| is_archive(p)).collect()
         } else {
-            self.lock_file.plugins.clone()
+            Vec::new()
         };
+        let mut downloaded: HashMap<&str, Result<(bool, Duration)>> = self
+            .verify_archives(&archives)?
+            .into_iter()
+            .map(|(plugin, result)| (plugin.name.as_str(), result))
+            .collect();
 
-        if !force {
-            plugins_to_install.diff_mut(&self.lock_file.plugins);
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(self.jobs)
+            .thread_name(|i| format!("fin-checksum-{i}"))
+            .build()
+            .map_err(FinError::ThreadPool)?;
+        let hashed: Vec<_> = pool.install(|| {
+            selected
+                .par_iter()
+                .map(|plugin| {
+                    let (Some(expected), Some(files)) = (&plugin.checksum, &plugin.installed_files)
+                    else {
+                        return None;
+                    };
+                    if is_archive(plugin) {
+                        return None;
+                    }
+                    let started = Instant::now();
+                    let matches = checksum(&*self.fs, &self.fish_config_dir, files)
+                        .is_ok_and(|actual| &actual == expected);
+                    Some((matches, started.elapsed()))
+                })
+                .collect()
+        });
+
+        let mut report = VerifyReport::default();
+        for (plugin, hashed) in selected.into_iter().zip(hashed) {
+            let name = plugin.name.clone();
+            let result = match downloaded.remove(plugin.name.as_str()) {
+                Some(result) => Some(result),
+                None => hashed.map(Ok),
+            };
+            match result {
+                None => report.unchecked.push(name),
+                Some(Ok((matches, elapsed))) => {
+                    report.timings.push((name.clone(), elapsed));
+                    if matches {
+                        report.ok.push(name);
+                    } else {
+                        report.mismatched.push(name);
+                    }
+                }
+                Some(Err(err)) => report.failed.push((plugin.clone(), err)),
+            }
         }
+        Ok(report)
+    }
 
-        plugins_to_install
+    /// Download the archives of tarball-checksummed plugins again and hash them
+    ///
+    /// Pairs each plugin with whether the archive still matches and how
+    /// long downloading and hashing it took.
+    fn verify_archives<'p>(&self, plugins: &[&'p Plugin]) -> Result<Vec<ArchiveCheck<'p>>> {
+        if plugins.is_empty() {
+            return Ok(Vec::new());
+        }
+        let runtime = self.runtime()?;
+        runtime.block_on(async {
+            let client = fetch::client()?;
+            let checks = stream::iter(plugins).map(|&plugin| {
+                let client = &client;
+                async move {
+                    let started = Instant::now();
+                    let result = async {
+                        let dir = TempDir::new().with_path("create", &env::temp_dir())?;
+                        let archive = dir.path().join("archive.tar.gz");
+                        fetch::download(client, &plugin.source, &archive, self.retries, |_, _| {})
+                            .await?;
+                        let actual = blocking(move || {
+                            let _dir = dir;
+                            checksum_file(&archive)
+                        })
+                        .await?;
+                        Ok((plugin.checksum.as_ref() == Some(&actual), started.elapsed()))
+                    }
+                    .await;
+                    (plugin, result)
+                }
+            });
+            Ok(checks.buffer_unordered(self.jobs).collect().await)
+        })
     }
 
-    fn install_plugin(&self, mut plugin: Plugin) -> Result<Plugin> {
-        let temp_dir = self.fetch_plugin(&plugin)?;
-        let installed_files = self.do_install_plugin_files(temp_dir.path())?;
+    /// Upstream releases, or commits, of `plugin` since its installed commit
+    ///
+    /// Read-only, nothing is updated.
+    pub fn changelog(&self, plugin: &Plugin) -> Result<Changelog> {
+        self.github.changelog(plugin)
+    }
 
-        if !installed_files.is_empty() {
-            plugin.installed_files = Some(
-                installed_files
-                    .into_iter()
-                    .map(|p| {
-                        p.strip_prefix(&self.fish_config_dir)
-                            .unwrap_or(&p)
-                            .to_string_lossy()
-                            .to_string()
-                    })
-                    .collect::<std::collections::HashSet<String>>(),
-            );
+    /// What the GitHub API says about the repository of `plugin`, `None` for other sources
+    pub fn repo_status(&self, plugin: &Plugin) -> Result<Option<RepoStatus>> {
+        let Some((repo, _)) = github::repo_and_ref(plugin) else {
+            return Ok(None);
+        };
+        self.github.repo_status(repo).map(Some)
+    }
+
+    /// Installed plugins whose upstream ref moved past the installed commit
+    ///
+    /// Checks every installed plugin when `plugins` is empty. Plugins
+    /// without a recorded commit always count as outdated, ones pinned to
+    /// a commit are not looked up. Nothing is updated.
+    pub fn outdated(&self, plugins: &[String]) -> Result<OutdatedReport> {
+        let (pinned, selected): (Vec<&Plugin>, Vec<&Plugin>) = self
+            .list()?
+            .into_iter()
+            .filter(|plugin| plugins.is_empty() || plugins.contains(&plugin.name))
+            .partition(|plugin| github::is_pinned(plugin));
+        let latest = self.github.latest_commits(&selected)?;
+
+        let mut report = OutdatedReport {
+            pinned: pinned.into_iter().map(|p| p.name.clone()).collect(),
+            ..Default::default()
+        };
+        for (plugin, latest) in selected.into_iter().zip(latest) {
+            match latest {
+                Ok(latest) if plugin.commit_hash.as_ref() == Some(&latest) => {
+                    report.up_to_date.push(plugin.name.clone());
+                }
+                Ok(latest) => report.outdated.push(Outdated {
+                    name: plugin.name.clone(),
+                    current: plugin.commit_hash.clone(),
+                    latest,
+                }),
+                Err(err) => report.failed.push((plugin.clone(), err)),
+            }
         }
+        report.deprecated = self.deprecations(plugins)?;
+        Ok(report)
+    }
 
-        Ok(plugin)
+    /// Check the installation for problems fin cannot fix on its own
+    pub fn doctor(&self) -> Result<DoctorReport> {
+        let shipped = self.shipped_completions();
+        let shadowed_completions = self
+            .list()?
+            .into_iter()
+            .flat_map(|plugin| {
+                let files = plugin.installed_files.iter().flatten();
+                completion_shadows(files.map(Path::new), &shipped)
+                    .into_iter()
+                    .map(|file| (plugin.name.clone(), file))
+            })
+            .collect();
+        let fish_too_old = self
+            .list()?
+            .into_iter()
+            .filter_map(|plugin| Some((plugin.name.clone(), self.fish_too_old(plugin)?.0)))
+            .collect();
+        Ok(DoctorReport {
+            fisher: self.detect_fisher()?,
+            shadowed_completions,
+            duplicate_functions: self.duplicate_functions()?,
+            fish_version: self.fish_version().map(str::to_string),
+            fish_too_old,
+            deprecated: self.deprecations(&[])?,
+        })
     }
 
-    /// Fetch a single plugin
-    fn fetch_plugin(&self, plugin: &Plugin) -> Result<TempDir> {
-        let temp_dir = TempDir::new()?;
-        let temp_path = temp_dir.path();
-        download_repo(&plugin.source, temp_path)?;
+    /// The installed Fish version, like `3.7.1`, `None` when `fish` cannot tell
+    pub fn fish_version(&self) -> Option<&str> {
+        self.fish_version
+            .get_or_init(|| {
+                let invocation = Invocation::new("fish", ["--version"]).captured();
+                let output = self.runner.run(&invocation).ok()?;
+                // `fish, version 3.7.1`
+                let version = output.stdout.trim().rsplit(' ').next()?;
+                (output.success() && !version.is_empty()).then(|| version.to_string())
+            })
+            .as_deref()
+    }
 
-        Ok(temp_dir)
+    /// The version `plugin` needs and the installed one, when that is older
+    ///
+    /// Nothing is reported when the installed version is unknown.
+    fn fish_too_old(&self, plugin: &Plugin) -> Option<(String, String)> {
+        let required = plugin.min_fish_version.as_ref()?;
+        let found = self.fish_version()?;
+        (version_numbers(found) < version_numbers(required))
+            .then(|| (required.clone(), found.to_string()))
     }
 
-    fn do_install_plugin_files(&self, temp_dir: &Path) -> Result<Vec<PathBuf>> {
-        let mut installed_files = Vec::new();
-        for component in PLUGIN_SUBDIRS {
-            let src_dir = temp_dir.join(component);
-            if src_dir.exists() {
-                let dest_dir = self.fin_path.join(component);
-                for entry in fs::read_dir(src_dir)? {
-                    let entry = entry?;
-                    let src_path = entry.path();
-                    let file_name = src_path.file_name().context("Invalid file name")?;
-                    let dest_path = dest_dir.join(file_name);
+    /// Warn about each function `plugins` define that another plugin defines too
+    fn warn_duplicate_functions(&self, plugins: &[Plugin]) -> Result<()> {
+        let duplicates = self.duplicate_functions()?;
+        for plugin in plugins {
+            for duplicate in &duplicates {
+                let definitions = &duplicate.definitions;
+                let Some((_, file)) = definitions.iter().find(|(owner, _)| *owner == plugin.name)
+                else {
+                    continue;
+                };
+                let others = definitions
+                    .iter()
+                    .filter(|(owner, _)| *owner != plugin.name);
+                for (owner, owner_file) in others {
+                    let warning = Warning::DuplicateFunction {
+                        function: duplicate.name.clone(),
+                        file: file.clone(),
+                        owner: owner.clone(),
+                        owner_file: owner_file.clone(),
+                    };
+                    self.observer.warning(plugin, &warning);
+                }
+            }
+        }
+        Ok(())
+    }
 
-                    fs::copy(&src_path, &dest_path)?;
-                    installed_files.push(dest_path);
+    /// Functions defined by more than one installed plugin, sorted by name
+    ///
+    /// Looks for `function <name>` lines in the installed `.fish` files, so
+    /// it also catches a plugin defining another one's function in a file
+    /// of a different name, like a `conf.d` snippet. Raw installs and
+    /// disabled snippets are not loaded by Fish and not scanned.
+    pub fn duplicate_functions(&self) -> Result<Vec<DuplicateFunction>> {
+        let mut definitions: BTreeMap<String, Vec<(String, String)>> = BTreeMap::new();
+        for plugin in self.list()?.into_iter().filter(|p| !p.raw) {
+            let mut files: Vec<&String> = plugin
+                .installed_files
+                .iter()
+                .flatten()
+                .filter(|file| file.ends_with(".fish"))
+                .collect();
+            files.sort();
+            for file in files {
+                let path = self.fish_config_dir.join(file);
+                for name in functions::declared_in_file(&*self.fs, &path) {
+                    definitions
+                        .entry(name)
+                        .or_default()
+                        .push((plugin.name.clone(), file.clone()));
                 }
             }
         }
-        Ok(installed_files)
+        Ok(definitions
+            .into_iter()
+            .filter(|(_, definitions)| {
+                definitions
+                    .iter()
+                    .any(|(plugin, _)| *plugin != definitions[0].0)
+            })
+            .map(|(name, definitions)| DuplicateFunction { name, definitions })
+            .collect())
     }
 
-    fn plugins(&self) -> impl Iterator<Item = &str> {
-        self.lock_file.plugins.iter().map(|p| p.name.as_str())
+    /// File names in Fish's own `completions` directory
+    ///
+    /// Plugin completions of the same name are found first and replace
+    /// them. Empty when `fish` is not available to say where it lives.
+    fn shipped_completions(&self) -> Arc<HashSet<String>> {
+        self.shipped_completions
+            .get_or_init(|| {
+                let invocation =
+                    Invocation::new("fish", ["--no-config", "-c", "echo $__fish_data_dir"])
+                        .captured();
+                let Ok(output) = self.runner.run(&invocation) else {
+                    return Arc::default();
+                };
+                let dir = Path::new(output.stdout.trim()).join("completions");
+                let names = match output.success() {
+                    true => self.fs.read_dir(&dir).unwrap_or_default(),
+                    false => Vec::new(),
+                };
+                Arc::new(
+                    names
+                        .iter()
+                        .filter_map(|path| Some(path.file_name()?.to_string_lossy().into_owned()))
+                        .collect(),
+                )
+            })
+            .clone()
+    }
+
+    /// fisher's files next to fin's, `None` when fisher is not installed
+    pub fn detect_fisher(&self) -> Result<Option<FisherInstall>> {
+        FisherInstall::detect(&*self.fs, &self.fish_config_dir, &self.fin_path)
+    }
+
+    /// Files fisher installed for `plugins` that no fin plugin tracks
+    ///
+    /// After fin reinstalled the plugins these are what fisher's versions
+    /// shipped and fin's do not, or files outside fin's installation path.
+    pub fn fisher_leftovers(
+        &self,
+        fisher: &FisherInstall,
+        plugins: &[String],
+    ) -> Result<Vec<PathBuf>> {
+        let tracked: HashSet<PathBuf> = self
+            .list()?
+            .into_iter()
+            .flat_map(|plugin| plugin.installed_files.iter().flatten())
+            .map(|file| self.fish_config_dir.join(file))
+            .collect();
+        let mut leftovers: Vec<PathBuf> = plugins
+            .iter()
+            .filter_map(|plugin| fisher.plugin_files.get(plugin))
+            .flatten()
+            .filter(|file| !tracked.contains(*file) && self.fs.file_size(file).is_ok())
+            .cloned()
+            .collect();
+        leftovers.sort();
+        Ok(leftovers)
+    }
+
+    /// Delete fisher's own files and plugin list, and erase its universal variables
+    ///
+    /// Erasing the variables needs `fish`, returns whether that worked.
+    /// Plugins fisher installed are left alone.
+    pub fn remove_fisher(&self, fisher: &FisherInstall) -> Result<bool> {
+        for file in fisher.own_files.iter().chain(&fisher.plugins_file) {
+            self.fs.remove_file(file).with_path("remove", file)?;
+        }
+        let erase = Invocation::new(
+            "fish",
+            [
+                "--no-config",
+                "-c",
+                "set -l names (set --names --universal | string match -- '_fisher_*')\n\
+                 test -z \"$names\"; or set --erase --universal $names",
+            ],
+        );
+        Ok(self.runner.run(&erase).is_ok_and(|output| output.success()))
     }
-}
 
-fn download_repo(url: &str, dest: &Path) -> Result<()> {
-    println!("Downloading: {url}");
-    let curl = Command::new("curl")
-        .arg("-sL")
-        .arg(url)
-        .stdout(Stdio::piped())
-        .spawn()
-        .context("Failed to spawn curl")?;
+    /// Copy the lock file to `<lock file>.bak`, `None` when there is none yet
+    pub fn backup_lock(&self) -> Result<Option<PathBuf>> {
+        let path = self
+            .previous_lock_file_path
+            .as_ref()
+            .unwrap_or(&self.fin_lock_file_path);
+        if self.fs.file_size(path).is_err() {
+            return Ok(None);
+        }
+        let mut backup = path.clone().into_os_string();
+        backup.push(".bak");
+        let backup = PathBuf::from(backup);
+        self.fs.copy(path, &backup).with_path("copy", path)?;
+        Ok(Some(backup))
+    }
+
+    /// The Fish configuration directory, which holds the lock file
+    pub fn fish_config_dir(&self) -> &Path {
+        &self.fish_config_dir
+    }
 
-    let tar_status = Command::new("tar")
-        .arg("-xz")
-        .arg("-C")
-        .arg(dest.as_os_str())
-        .arg("--strip-components=1")
-        .stdin(curl.stdout.context("Failed to get curl stdout")?)
-        .status()
-        .context("Failed to run tar")?;
+    /// Where plugin files are installed
+    pub fn fin_path(&self) -> &Path {
+        &self.fin_path
+    }
+
+    /// The lock file written on changes
+    pub fn lock_file_path(&self) -> &Path {
+        &self.fin_lock_file_path
+    }
 
-    if !tar_status.success() {
-        return Err(anyhow::anyhow!("tar command failed"));
+    /// The configured plugin registry
+    pub fn registry(&self) -> &Registry {
+        &self.registry
     }
 
-    Ok(())
+    /// The lock file, read on first use
+    pub fn lock_file(&self) -> Result<&LockFile> {
+        self.load_lock()
+    }
+
+    /// Rewrite the lock file normalized
+    ///
+    /// Saving always sorts plugins and their files, `dedup` additionally
+    /// collapses duplicate entries left behind by older versions. The
+    /// `conf.d/_fin_meta.fish` snippet is regenerated as well. Returns the
+    /// number of dropped duplicates.
+    pub fn lock(&mut self, dedup: bool) -> Result<usize> {
+        // Dropping the duplicates is what the warning would suggest
+        self.load_lock_warning(!dedup)?;
+        let lock_file = self.loaded_lock_mut();
+        let removed = if dedup { lock_file.dedup() } else { 0 };
+
+        self.save_lock()?;
+        self.write_meta()?;
+        Ok(removed)
+    }
+
+    /// Resolve user supplied names to installed plugin names
+    ///
+    /// A trailing part of a name like `fzf` matches `owner/fzf`. When
+    /// several plugins match the user picks one interactively, otherwise
+    /// the full name is required. Names matching nothing are kept as they
+    /// are, operations report them as not installed.
+    pub fn resolve_names(&self, names: &[String], prompt: &Prompt) -> Result<Vec<String>> {
+        names
+            .iter()
+            .map(|name| self.resolve_name(name, prompt))
+            .collect()
+    }
+
+    fn resolve_name(&self, name: &str, prompt: &Prompt) -> Result<String> {
+        match self.match_name(name)? {
+            NameMatch::None => Ok(name.to_string()),
+            NameMatch::Unique(plugin) => Ok(plugin.name.clone()),
+            NameMatch::Ambiguous(candidates) => {
+                let options: Vec<String> = candidates
+                    .iter()
+                    .map(|p| format!("{} ({})", p.name, p.source))
+                    .collect();
+                let message = format!("\"{name}\" matches multiple plugins");
+
+                if !prompt.is_interactive() {
+                    return Err(FinError::Ambiguous {
+                        name: name.to_string(),
+                        candidates: options,
+                    });
+                }
+
+                let index = prompt.choose(&message, &options)?;
+                Ok(candidates[index].name.clone())
+            }
+        }
+    }
+
+    /// Installed plugins `name` refers to
+    ///
+    /// The full name always wins. Otherwise `name` matches the plugins whose
+    /// names end in it at a `/`, like `fzf` or `owner/fzf` for `owner/fzf`
+    /// and `dev/my-plugin` for a local `/home/me/dev/my-plugin`.
+    pub fn match_name(&self, name: &str) -> Result<NameMatch<'_>> {
+        if let Some(plugin) = self.plugin(name)? {
+            return Ok(NameMatch::Unique(plugin));
+        }
+
+        let suffix = format!("/{}", name.trim_start_matches('/')).to_ascii_lowercase();
+        let mut candidates: Vec<&Plugin> = self
+            .list()?
+            .into_iter()
+            .filter(|p| p.name.to_ascii_lowercase().ends_with(&suffix))
+            .collect();
+
+        Ok(match candidates.len() {
+            0 => NameMatch::None,
+            1 => NameMatch::Unique(candidates.remove(0)),
+            _ => NameMatch::Ambiguous(candidates),
+        })
+    }
+
+    /// Short name to show for an installed plugin
+    ///
+    /// That is its basename, which [`Self::resolve_names`] accepts, unless
+    /// another installed plugin shares it and only the full name is clear.
+    pub fn display_name<'a>(&self, plugin: &'a Plugin) -> Result<&'a str> {
+        let shared = self.list()?.into_iter().any(|other| {
+            Plugins::key(&other.name) != Plugins::key(&plugin.name)
+                && other.basename().eq_ignore_ascii_case(plugin.basename())
+        });
+        Ok(if shared {
+            &plugin.name
+        } else {
+            plugin.basename()
+        })
+    }
+
+    /// Get Fish configuration directory
+    ///
+    /// `FIN_FISH_CONFIG_DIR` wins over fish's own `__fish_config_dir`, so
+    /// tests and scripts can point fin at a throwaway directory. Like fish,
+    /// falls back to `$XDG_CONFIG_HOME/fish`. The home directory is only
+    /// needed when none of these are set.
+    fn get_fish_config_dir() -> Result<PathBuf> {
+        // Prefer environment variables, fallback to default path
+        let from_env = |name| {
+            env::var_os(name)
+                .filter(|v| !v.is_empty())
+                .map(PathBuf::from)
+        };
+        if let Some(path) =
+            from_env("FIN_FISH_CONFIG_DIR").or_else(|| from_env("__fish_config_dir"))
+        {
+            Ok(path)
+        } else if let Some(config_home) = from_env("XDG_CONFIG_HOME") {
+            Ok(config_home.join("fish"))
+        } else {
+            dirs::home_dir()
+                .map(|p| p.join(".config/fish"))
+                .ok_or(FinError::NoHomeDir)
+        }
+    }
+
+    /// Settle how `plugin` is extracted, its priority and whether its `conf.d` is disabled
+    ///
+    /// Explicit options win over the values recorded in the plugin or its
+    /// lock entry, so updates behave the same way, then over the config. Only
+    /// non-default values are recorded. A disabled `conf.d` stays disabled
+    /// across reinstalls until `fin enable-confd`, and so do `dereference`,
+    /// `max_depth`, allowed completion shadows and build commands and pinned
+    /// archive digests until the plugin is removed.
+    fn with_install_settings(&self, mut plugin: Plugin, options: &InstallOptions) -> Plugin {
+        let installed = self.loaded_lock().plugins.get(&plugin.name);
+        plugin.raw = options.no_strip || plugin.raw || installed.is_some_and(|p| p.raw);
+        let strip = if plugin.raw {
+            Some(0)
+        } else {
+            options
+                .strip_components
+                .or(plugin.strip_components)
+                .or_else(|| installed?.strip_components)
+                .or_else(|| self.strip_components.get(&plugin.name).copied())
+                .or_else(|| self.strip_components.get(&plugin.source).copied())
+        };
+        plugin.strip_components = strip.filter(|&n| n != DEFAULT_STRIP_COMPONENTS);
+
+        plugin.priority = options
+            .priority
+            .or(plugin.priority)
+            .or_else(|| installed?.priority)
+            .or_else(|| self.priority.get(&plugin.name).copied())
+            .filter(|&p| p != 0);
+
+        plugin.load_order = plugin.load_order.or_else(|| installed?.load_order);
+        plugin.confd_disabled = plugin.confd_disabled
+            || installed.map_or_else(
+                || self.disable_confd.contains(&plugin.name),
+                |p| p.confd_disabled,
+            );
+        plugin.dereference =
+            options.dereference || plugin.dereference || installed.is_some_and(|p| p.dereference);
+        plugin.max_depth = options
+            .max_depth
+            .or(plugin.max_depth)
+            .or_else(|| installed?.max_depth);
+        plugin.shadow_completions = options.allow_shadow_completions
+            || plugin.shadow_completions
+            || installed.is_some_and(|p| p.shadow_completions);
+        plugin.archive_digest = plugin
+            .archive_digest
+            .or_else(|| installed?.archive_digest.clone());
+        plugin.allow_scripts = options.allow_scripts
+            || plugin.allow_scripts
+            || installed.is_some_and(|p| p.allow_scripts);
+        plugin
+    }
+
+    /// Enable or disable the `conf.d` snippets of an installed plugin
+    ///
+    /// Disabled snippets keep a `.disabled` suffix so Fish does not load
+    /// them, while the plugin's functions and completions stay usable.
+    /// Returns the renamed files.
+    pub fn set_confd_enabled(&mut self, name: &str, enabled: bool) -> Result<Vec<PathBuf>> {
+        let Some(mut plugin) = self.plugin(name)?.cloned() else {
+            return Ok(Vec::new());
+        };
+        let result = self.rename_confd(&mut plugin, enabled);
+        self.loaded_lock_mut().plugins.insert(plugin);
+        self.save_lock()?;
+        result
+    }
+
+    /// Make Fish source the `conf.d` snippets of an installed plugin in `order`
+    ///
+    /// The snippets are renamed to start with the zero-padded number, `None`
+    /// drops the number again. Returns the renamed files.
+    pub fn set_load_order(&mut self, name: &str, order: Option<u8>) -> Result<Vec<PathBuf>> {
+        let Some(mut plugin) = self.plugin(name)?.cloned() else {
+            return Ok(Vec::new());
+        };
+        let old_prefix = plugin.confd_prefix().unwrap_or_default();
+        plugin.load_order = order;
+        let new_prefix = plugin.confd_prefix().unwrap_or_default();
+
+        let mut renamed = Vec::new();
+        let mut result = Ok(());
+        if let Some(files) = &mut plugin.installed_files {
+            let targets: Vec<(String, PathBuf)> = files
+                .iter()
+                .filter(|file| {
+                    Path::new(file).parent().and_then(Path::file_name)
+                        == Some(CONFD_SUBDIR.as_ref())
+                })
+                .map(|file| (file.clone(), PathBuf::from(file)))
+                .collect();
+            for (file, path) in targets {
+                let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+                let base = file_name.strip_prefix(&old_prefix).unwrap_or(&file_name);
+                let target = path
+                    .with_file_name(format!("{new_prefix}{base}"))
+                    .to_string_lossy()
+                    .to_string();
+                let from = self.fish_config_dir.join(&file);
+                let to = self.fish_config_dir.join(&target);
+                if let Err(err) = self.fs.rename(&from, &to).with_path("rename", &from) {
+                    result = Err(err);
+                    break;
+                }
+                files.remove(&file);
+                files.insert(target);
+                renamed.push(to);
+            }
+        }
+
+        self.loaded_lock_mut().plugins.insert(plugin);
+        self.refresh_checksum(name);
+        self.save_lock()?;
+        result.map(|()| renamed)
+    }
+
+    /// Installed plugins that ship prompt functions, sorted by name
+    pub fn themes(&self) -> Result<Vec<Theme>> {
+        let mut themes: Vec<Theme> = self
+            .list()?
+            .into_iter()
+            .filter_map(|plugin| {
+                let prompts: Vec<String> = PROMPT_FILES
+                    .iter()
+                    .filter(|file| provides(plugin, file))
+                    .filter_map(|file| Some(Path::new(file).file_stem()?.to_string_lossy()))
+                    .map(String::from)
+                    .collect();
+                // Right and mode prompts alone do not make a theme active
+                let owns = |file: &str| {
+                    plugin
+                        .installed_files
+                        .as_ref()
+                        .is_some_and(|f| f.contains(file))
+                };
+                let active = if provides(plugin, PROMPT_FILES[0]) {
+                    owns(PROMPT_FILES[0])
+                } else {
+                    PROMPT_FILES.iter().any(|file| owns(file))
+                };
+                (!prompts.is_empty()).then(|| Theme {
+                    name: plugin.name.clone(),
+                    prompts,
+                    active,
+                })
+            })
+            .collect();
+        themes.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(themes)
+    }
+
+    /// Make the prompt of theme `name` the one Fish uses
+    ///
+    /// The prompt functions of the current theme are kept aside like files
+    /// that lost a conflict, including ones the new theme does not ship, and
+    /// its `conf.d` snippets are disabled. The new theme's kept aside prompt
+    /// functions are put in place and its snippets enabled. Nothing is
+    /// uninstalled. Returns the plugins whose prompt was deactivated.
+    pub fn set_theme(&mut self, name: &str) -> Result<Vec<String>> {
+        let Some(theme) = self.plugin(name)?.cloned() else {
+            return Ok(Vec::new());
+        };
+        if !PROMPT_FILES.iter().any(|file| provides(&theme, file)) {
+            return Err(FinError::NotATheme(theme.name));
+        }
+
+        let mut deactivated: Vec<String> = Vec::new();
+        let result = self.swap_prompts(&theme, &mut deactivated);
+        for name in &deactivated {
+            if let Some(mut plugin) = self.loaded_lock().plugins.get(name).cloned() {
+                let renamed = self.rename_confd(&mut plugin, false);
+                self.loaded_lock_mut().plugins.insert(plugin);
+                renamed?;
+            }
+        }
+        if let Some(mut plugin) = self.loaded_lock().plugins.get(&theme.name).cloned() {
+            let renamed = self.rename_confd(&mut plugin, true);
+            self.loaded_lock_mut().plugins.insert(plugin);
+            renamed?;
+        }
+        for name in deactivated.iter().chain([&theme.name]) {
+            self.refresh_checksum(name);
+        }
+        self.save_lock()?;
+        result.map(|()| deactivated)
+    }
+
+    /// Move other plugins' prompt functions aside and `theme`'s into place
+    fn swap_prompts(&mut self, theme: &Plugin, deactivated: &mut Vec<String>) -> Result<()> {
+        let shadow_dir = self.fin_path.join(SHADOW_DIRNAME);
+        for &file in PROMPT_FILES {
+            let is_installed =
+                |p: &Plugin| p.installed_files.as_ref().is_some_and(|f| f.contains(file));
+            if is_installed(theme) {
+                continue;
+            }
+            let path = self.fish_config_dir.join(file);
+            let owner = self
+                .loaded_lock()
+                .plugins
+                .iter()
+                .find(|p| is_installed(p))
+                .map(|p| p.name.clone());
+            if let Some(owner) = owner {
+                let aside = shadow_path(&shadow_dir, &owner).join(file);
+                if let Some(parent) = aside.parent() {
+                    self.fs.create_dir_all(parent).with_path("create", parent)?;
+                }
+                self.fs.rename(&path, &aside).with_path("move", &path)?;
+                if let Some(plugin) = self.loaded_lock_mut().plugins.get_mut(&owner) {
+                    plugin.installed_files.iter_mut().for_each(|f| {
+                        f.remove(file);
+                    });
+                    plugin
+                        .shadowed_files
+                        .get_or_insert_with(HashSet::new)
+                        .insert(file.to_string());
+                }
+                if !deactivated.contains(&owner) {
+                    deactivated.push(owner);
+                }
+            }
+            if theme
+                .shadowed_files
+                .as_ref()
+                .is_some_and(|f| f.contains(file))
+            {
+                let aside = shadow_path(&shadow_dir, &theme.name).join(file);
+                self.fs.rename(&aside, &path).with_path("move", &aside)?;
+                if let Some(plugin) = self.loaded_lock_mut().plugins.get_mut(&theme.name) {
+                    if let Some(shadowed) = &mut plugin.shadowed_files {
+                        shadowed.remove(file);
+                    }
+                    plugin.shadowed_files.take_if(|files| files.is_empty());
+                    plugin
+                        .installed_files
+                        .get_or_insert_with(HashSet::new)
+                        .insert(file.to_string());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Start an interactive `fish` that shows the prompt of theme `name`
+    ///
+    /// The theme's prompt functions are sourced from wherever they are, in
+    /// place or kept aside, after the user's config. Nothing is changed.
+    pub fn preview_theme(&self, name: &str) -> Result<()> {
+        let Some(theme) = self.plugin(name)? else {
+            return Ok(());
+        };
+        let shadow = shadow_path(&self.fin_path.join(SHADOW_DIRNAME), &theme.name);
+        let mut script = format!(
+            "set -p fish_function_path {}\n",
+            fish_quote(&shadow.join("functions").to_string_lossy())
+        );
+        let mut found = false;
+        for &file in PROMPT_FILES {
+            let path = if theme
+                .installed_files
+                .as_ref()
+                .is_some_and(|f| f.contains(file))
+            {
+                self.fish_config_dir.join(file)
+            } else if theme
+                .shadowed_files
+                .as_ref()
+                .is_some_and(|f| f.contains(file))
+            {
+                shadow.join(file)
+            } else {
+                // Without the theme's version Fish's default is shown
+                let function = Path::new(file).file_stem().unwrap_or_default();
+                script.push_str(&format!(
+                    "functions --erase {}\n",
+                    fish_quote(&function.to_string_lossy())
+                ));
+                continue;
+            };
+            found = true;
+            script.push_str(&format!("source {}\n", fish_quote(&path.to_string_lossy())));
+        }
+        if !found {
+            return Err(FinError::NotATheme(theme.name.clone()));
+        }
+
+        let invocation = Invocation::new("fish", ["-C", script.as_str()]).interactive();
+        self.runner
+            .run(&invocation)
+            .with_path("run", Path::new("fish"))?;
+        Ok(())
+    }
+
+    /// Rename the `conf.d` snippets of `plugin`, keeping `installed_files` in step
+    fn rename_confd(&self, plugin: &mut Plugin, enabled: bool) -> Result<Vec<PathBuf>> {
+        let mut renamed = Vec::new();
+        let Some(files) = &mut plugin.installed_files else {
+            return Ok(renamed);
+        };
+
+        let targets: Vec<(String, String)> = files
+            .iter()
+            .filter(|file| {
+                Path::new(file).parent().and_then(Path::file_name) == Some(CONFD_SUBDIR.as_ref())
+            })
+            .filter_map(|file| {
+                let target = match file.strip_suffix(DISABLED_SUFFIX) {
+                    Some(name) if enabled => name.to_string(),
+                    None if !enabled => format!("{file}{DISABLED_SUFFIX}"),
+                    _ => return None,
+                };
+                Some((file.clone(), target))
+            })
+            .collect();
+
+        for (file, target) in targets {
+            let from = self.fish_config_dir.join(&file);
+            let to = self.fish_config_dir.join(&target);
+            self.fs.rename(&from, &to).with_path("rename", &from)?;
+            files.remove(&file);
+            files.insert(target);
+            renamed.push(to);
+        }
+
+        plugin.confd_disabled = !enabled;
+        Ok(renamed)
+    }
+
+    /// Split the requested plugins into those to install and those already installed
+    ///
+    /// Keeps the requested order, a plugin requested twice is installed once.
+    /// A recorded plugin only counts as installed while all of its files are
+    /// still on disk. Works on references, so callers only clone the plugins
+    /// to install, the others are reported by name.
+    fn get_plugins_to_install<'a>(
+        &self,
+        requested: impl IntoIterator<Item = &'a Plugin>,
+        force: bool,
+    ) -> (Vec<&'a Plugin>, Vec<String>) {
+        let mut seen = HashSet::new();
+        let mut to_install = Vec::new();
+        let mut skipped = Vec::new();
+        for plugin in requested {
+            if !seen.insert(plugin.name.as_str()) {
+                continue;
+            }
+            if force
+                || !self.loaded_lock().plugins.contains(plugin)
+                || self.is_damaged(&plugin.name)
+            {
+                to_install.push(plugin);
+            } else {
+                skipped.push(plugin.name.clone());
+            }
+        }
+        (to_install, skipped)
+    }
+
+    /// Whether files of a recorded plugin went missing, or it never had any recorded
+    fn is_damaged(&self, name: &str) -> bool {
+        self.loaded_lock().plugins.get(name).is_some_and(|plugin| {
+            plugin.installed_files.is_none()
+                || self
+                    .installed_paths(plugin)
+                    .iter()
+                    .any(|path| self.fs.file_size(path).is_err())
+        })
+    }
+
+    /// Runtime driving the async fetch pipeline of one operation
+    ///
+    /// Sized from `jobs` rather than the CPU count: downloads are network
+    /// bound, and at most `jobs` plugins extract or copy at the same time.
+    /// Owning the runtime keeps fin off any thread pool of an embedding
+    /// program.
+    fn runtime(&self) -> Result<Runtime> {
+        let cpus = std::thread::available_parallelism().map_or(1, |n| n.get());
+        tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(self.jobs.min(cpus))
+            .max_blocking_threads(self.jobs)
+            .thread_name("fin-worker")
+            .enable_all()
+            .build()
+            .map_err(FinError::Runtime)
+    }
+
+    /// Install plugins concurrently, at most `jobs` at a time
+    ///
+    /// `options.ordered` installs them one by one in the given order
+    /// instead, so their events fire in that order too. Ctrl-C stops
+    /// starting new plugins and abandons in-flight downloads, removing their
+    /// staging directories, while plugins already being put in place finish.
+    /// Plugins that did not finish fail with [`FinError::Interrupted`].
+    async fn install_all(
+        &self,
+        plugins: Vec<Plugin>,
+        options: &InstallOptions,
+    ) -> Result<Vec<(Plugin, Result<(Plugin, Option<String>)>)>> {
+        let client = fetch::client()?;
+        let blocklist = Arc::new(self.blocklist.load(&client).await?);
+        // Ask fish once, before the plugins run concurrently
+        self.shipped_completions();
+        self.fish_version();
+        let (interrupt, interrupted) = watch::channel(false);
+        let installs = stream::iter(plugins).map(|plugin| {
+            let client = &client;
+            let blocklist = &blocklist;
+            let interrupted = interrupted.clone();
+            async move {
+                if *interrupted.borrow() {
+                    return (plugin, Err(FinError::Interrupted));
+                }
+                self.observer.plugin_started(&plugin);
+                let result = self
+                    .install_plugin(client, &plugin, options, blocklist, interrupted)
+                    .await;
+                let installed = result.as_ref().map(|(installed, _)| installed);
+                self.observer.plugin_finished(&plugin, installed);
+                (plugin, result)
+            }
+        });
+        let mut installs = if options.ordered {
+            installs.buffered(1).collect::<Vec<_>>().boxed()
+        } else {
+            installs.buffer_unordered(self.jobs).collect().boxed()
+        };
+
+        tokio::select! {
+            results = &mut installs => Ok(results),
+            _ = tokio::signal::ctrl_c() => {
+                let _ = interrupt.send(true);
+                Ok(installs.await)
+            }
+        }
+    }
+
+    /// Fetch and install one plugin, with what its build command printed
+    ///
+    /// Events are emitted right away unless `options.defer_events` is set.
+    async fn install_plugin(
+        &self,
+        client: &reqwest::Client,
+        plugin: &Plugin,
+        options: &InstallOptions,
+        blocklist: &Arc<Blocklist>,
+        mut interrupted: watch::Receiver<bool>,
+    ) -> Result<(Plugin, Option<String>)> {
+        let mut plugin = plugin.clone();
+        let event = self.event_for(&plugin);
+        let emit_events = !options.defer_events && !plugin.raw;
+        let raw_dir = plugin.raw.then(|| self.raw_dir(&plugin));
+        let pin_digest = options.pin_digest || plugin.archive_digest.is_some();
+        // A reinstall keeps the release it has instead of looking for a newer one
+        let latest = plugin.latest;
+        plugin.latest = latest && !options.reinstall.contains(&plugin.name);
+        let (staging, archive_checksum) = tokio::select! {
+            fetched = self.fetch_plugin(client, &mut plugin) => fetched?,
+            _ = interrupted.wait_for(|&interrupted| interrupted) => {
+                return Err(FinError::Interrupted);
+            }
+        };
+        if let Some(checksum) = &archive_checksum {
+            blocklist.check(checksum)?;
+        }
+        plugin.latest = latest;
+        let manifest = PluginManifest::read(&*self.fs, staging.path())?.unwrap_or_default();
+        plugin.description = manifest.description;
+        plugin.author = manifest.author;
+        plugin.homepage = manifest.homepage;
+        plugin.uninstall_hook = manifest.uninstall;
+        plugin.build = manifest.build;
+        plugin.binary = manifest.binary;
+        plugin.min_fish_version = manifest.min_fish_version;
+        let license_text = license::read(&*self.fs, staging.path());
+        plugin.license = license_text
+            .as_deref()
+            .and_then(license::identify)
+            .map(str::to_string);
+        if let Some((required, found)) = self.fish_too_old(&plugin) {
+            if options.strict {
+                return Err(FinError::FishTooOld { required, found });
+            }
+            self.observer
+                .warning(&plugin, &Warning::FishTooOld { required, found });
+        }
+        let build_output = match plugin.build.clone().filter(|_| !plugin.raw) {
+            Some(command) if self.builds(&plugin) => {
+                self.observer.phase_changed(&plugin, Phase::Build);
+                let runner = self.runner.clone();
+                let dir = staging.path().to_path_buf();
+                let output =
+                    blocking(move || events::run_build(&*runner, &command, &dir, BUILD_TIMEOUT))
+                        .await?;
+                Some(output)
+            }
+            Some(command) => {
+                self.observer
+                    .warning(&plugin, &Warning::BuildSkipped { command });
+                None
+            }
+            None => None,
+        };
+        // Past this point the plugin is put in place completely, or not at all
+        if *interrupted.borrow() {
+            return Err(FinError::Interrupted);
+        }
+        self.observer.phase_changed(&plugin, Phase::Copy);
+
+        let conflicts = Conflicts {
+            owners: self.file_owners(&plugin.name),
+            priority: plugin.priority.unwrap_or_default(),
+            on_conflict: options.on_conflict,
+            base: self.fish_config_dir.clone(),
+            shadow_dir: self.fin_path.join(SHADOW_DIRNAME),
+        };
+        let src = staging.path().to_path_buf();
+        let fin_path = self.fin_path.clone();
+        let docs_dir = self.docs_dir.clone();
+        // Only what a build that ran produced is installed
+        let binary = build_output
+            .as_ref()
+            .and(plugin.binary.as_ref())
+            .map(|binary| (binary.clone(), self.bin_dir.clone()));
+        let fs = self.fs.clone();
+        let runner = self.runner.clone();
+        let observer = self.observer.clone();
+        let copied = plugin.clone();
+        let blocklist = blocklist.clone();
+        let shipped_completions = self.shipped_completions();
+        let (installed_files, shadowed_files, checksum) = blocking(move || {
+            let mut warnings = Vec::new();
+            let mut transfers = match &raw_dir {
+                Some(raw_dir) => raw_file_moves(&*fs, &src, raw_dir)?,
+                None => {
+                    stage_key_bindings(&*fs, &src, &copied)?;
+                    let transfers =
+                        plugin_file_moves(&*fs, &fin_path, &src, &copied, &mut warnings)?;
+                    let shadows = completion_shadows(
+                        transfers.iter().map(|(_, dest, _)| dest.as_path()),
+                        &shipped_completions,
+                    );
+                    if !shadows.is_empty() && !copied.shadow_completions {
+                        return Err(FinError::ShadowsCompletions { files: shadows });
+                    }
+                    warnings.extend(
+                        shadows
+                            .into_iter()
+                            .map(|file| Warning::ShadowsCompletion { file }),
+                    );
+                    transfers
+                }
+            };
+            if let Some(docs_dir) = docs_dir.as_ref().filter(|_| raw_dir.is_none()) {
+                transfers.extend(plugin_doc_copies(&*fs, &copied, &src, docs_dir)?);
+            }
+            if let Some((binary, bin_dir)) = &binary {
+                transfers.push(binary_copy(&*fs, &copied, &src, binary, bin_dir)?);
+            }
+            // Hashed before conflicts are settled so a blocked plugin leaves no trace
+            let checksum = conflicts.checksum(&*fs, &transfers)?;
+            if let Some(checksum) = &checksum {
+                blocklist.check(checksum)?;
+            }
+            let shadowed_files =
+                conflicts.resolve(&*fs, &copied.name, &mut transfers, &mut warnings)?;
+            let mut installed_files = place_files(&*fs, &transfers, &src.join(BACKUP_SUBDIR))?;
+            installed_files.retain(|file| !file.starts_with(&conflicts.shadow_dir));
+            if emit_events {
+                observer.phase_changed(&copied, Phase::Events);
+                // Handlers are best effort like in fisher, the files stay installed
+                if let Err(err) = events::emit(
+                    &*runner,
+                    event,
+                    &fin_path,
+                    &conf_files(installed_files.iter().cloned()),
+                    copied.confd_prefix().as_deref(),
+                ) {
+                    warnings.push(Warning::Events(err));
+                }
+            }
+            for warning in &warnings {
+                observer.warning(&copied, warning);
+            }
+            Ok((installed_files, shadowed_files, checksum))
+        })
+        .await?;
+
+        // Written once the plugin is in place so a failed update keeps the installed
+        // version's text, only `fin licenses --full` misses one that could not be written
+        let path = self.license_path(&plugin);
+        let _ = match license_text {
+            Some(text) => self
+                .fs
+                .create_dir_all(&self.license_dir)
+                .and_then(|()| self.fs.write(&path, text.as_bytes())),
+            None => self.fs.remove_file(&path),
+        };
+        plugin.shadowed_files = (!shadowed_files.is_empty()).then_some(shadowed_files);
+
+        if !installed_files.is_empty() {
+            plugin.installed_files = Some(
+                installed_files
+                    .into_iter()
+                    .map(|p| {
+                        p.strip_prefix(&self.fish_config_dir)
+                            .unwrap_or(&p)
+                            .to_string_lossy()
+                            .to_string()
+                    })
+                    .collect::<std::collections::HashSet<String>>(),
+            );
+        }
+        if pin_digest {
+            plugin.archive_digest = archive_checksum.clone();
+        }
+        (plugin.checksum, plugin.checksum_scope) = match archive_checksum {
+            Some(archive) if self.checksum_scope == ChecksumScope::Tarball => {
+                (Some(archive), Some(ChecksumScope::Tarball))
+            }
+            _ => (checksum, None),
+        };
+
+        Ok((plugin, build_output))
+    }
+
+    /// Which plugin, other than `name`, provides each installed file and at what priority
+    fn file_owners(&self, name: &str) -> HashMap<String, (String, i32)> {
+        self.loaded_lock()
+            .plugins
+            .iter()
+            .filter(|p| Plugins::key(&p.name) != Plugins::key(name))
+            .flat_map(|p| {
+                let owner = (p.name.clone(), p.priority.unwrap_or_default());
+                p.installed_files
+                    .iter()
+                    .flatten()
+                    .map(move |file| (file.clone(), owner.clone()))
+            })
+            .collect()
+    }
+
+    /// Hand files taken over by freshly installed plugins from their previous owners
+    ///
+    /// The previous owner keeps them as shadowed files, restored when the
+    /// new owner is removed.
+    fn reassign_overridden_files(&mut self, installed: &[Plugin]) {
+        for plugin in installed {
+            for file in plugin.installed_files.iter().flatten() {
+                let previous: Vec<String> = self
+                    .loaded_lock()
+                    .plugins
+                    .iter()
+                    .filter(|p| Plugins::key(&p.name) != Plugins::key(&plugin.name))
+                    .filter(|p| p.installed_files.as_ref().is_some_and(|f| f.contains(file)))
+                    .map(|p| p.name.clone())
+                    .collect();
+                for name in previous {
+                    if let Some(owner) = self.loaded_lock_mut().plugins.get_mut(&name) {
+                        if let Some(files) = &mut owner.installed_files {
+                            files.remove(file);
+                        }
+                        owner
+                            .shadowed_files
+                            .get_or_insert_with(HashSet::new)
+                            .insert(file.clone());
+                    }
+                    self.refresh_checksum(&name);
+                }
+            }
+        }
+    }
+
+    /// Give `file` back to the highest priority plugin that had it shadowed
+    ///
+    /// Returns false when no plugin can take it over, the file should be
+    /// deleted then.
+    fn restore_shadowed(&mut self, file: &str) -> bool {
+        let heir = self
+            .loaded_lock()
+            .plugins
+            .iter()
+            .filter(|p| p.shadowed_files.as_ref().is_some_and(|f| f.contains(file)))
+            .max_by_key(|p| p.priority.unwrap_or_default())
+            .map(|p| p.name.clone());
+        let Some(heir) = heir else {
+            return false;
+        };
+
+        let shadow = shadow_path(&self.fin_path.join(SHADOW_DIRNAME), &heir).join(file);
+        let restored = self
+            .fs
+            .rename(&shadow, &self.fish_config_dir.join(file))
+            .is_ok();
+        if let Some(plugin) = self.loaded_lock_mut().plugins.get_mut(&heir) {
+            if let Some(shadowed) = &mut plugin.shadowed_files {
+                shadowed.remove(file);
+            }
+            plugin.shadowed_files.take_if(|files| files.is_empty());
+            if restored {
+                plugin
+                    .installed_files
+                    .get_or_insert_with(HashSet::new)
+                    .insert(file.to_string());
+            }
+        }
+        self.refresh_checksum(&heir);
+        restored
+    }
+
+    /// Recompute the checksum of a plugin whose set of files changed
+    ///
+    /// Archive checksums do not depend on the installed files and stay.
+    fn refresh_checksum(&mut self, name: &str) {
+        let Some(plugin) = self.loaded_lock().plugins.get(name) else {
+            return;
+        };
+        if plugin.checksum_scope == Some(ChecksumScope::Tarball) {
+            return;
+        }
+        let checksum = plugin
+            .installed_files
+            .as_ref()
+            .and_then(|files| checksum(&*self.fs, &self.fish_config_dir, files).ok());
+        if let Some(plugin) = self.loaded_lock_mut().plugins.get_mut(name) {
+            plugin.checksum = checksum;
+        }
+    }
+
+    /// Delete files `previous` installed that its reinstall no longer ships
+    ///
+    /// A file another plugin had shadowed goes back to that plugin instead.
+    fn drop_stale_files(&mut self, previous: &Plugin, current: &Plugin) {
+        for file in previous.installed_files.iter().flatten() {
+            if current
+                .installed_files
+                .as_ref()
+                .is_some_and(|f| f.contains(file))
+            {
+                continue;
+            }
+            if !self.restore_shadowed(file) {
+                let _ = self.fs.remove_file(&self.fish_config_dir.join(file));
+            }
+        }
+    }
+
+    /// Delete copies kept aside for `previous` that its reinstall no longer needs
+    fn drop_stale_shadows(&self, previous: &Plugin, current: &Plugin) {
+        let shadow_dir = shadow_path(&self.fin_path.join(SHADOW_DIRNAME), &previous.name);
+        for file in previous.shadowed_files.iter().flatten() {
+            if !current
+                .shadowed_files
+                .as_ref()
+                .is_some_and(|f| f.contains(file))
+            {
+                let _ = self.fs.remove_file(&shadow_dir.join(file));
+            }
+        }
+    }
+
+    /// Write `conf.d/_fin_meta.fish` for prompts and greetings to read cheaply
+    ///
+    /// It sets the number of installed plugins and when the lock file last
+    /// changed as global variables. It is not a plugin file, so it is never
+    /// part of a conflict, and it is removed along with the last plugin.
+    fn write_meta(&self) -> Result<()> {
+        let path = self.fin_path.join(CONFD_SUBDIR).join(META_FILENAME);
+        let count = self.loaded_lock().plugins.len();
+        if count == 0 {
+            return match self.fs.remove_file(&path) {
+                Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+                    Err(err).with_path("remove", &path)
+                }
+                _ => Ok(()),
+            };
+        }
+
+        let updated_at = Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true);
+        let content = format!(
+            "# Generated by fin whenever the lock file changes, do not edit\n\
+             set -g fin_plugin_count {count}\n\
+             set -g fin_updated_at {updated_at}\n"
+        );
+        let dir = self.fin_path.join(CONFD_SUBDIR);
+        self.fs.create_dir_all(&dir).with_path("create", &dir)?;
+        self.fs
+            .write(&path, content.as_bytes())
+            .with_path("write", &path)
+    }
+
+    /// Make sure plugin files and the lock file can be written before downloading anything
+    fn check_writable(&self) -> Result<()> {
+        for dir in [&self.fin_path, &self.fish_config_dir] {
+            let probe = dir.join(PROBE_FILENAME);
+            self.fs
+                .create_dir_all(dir)
+                .and_then(|()| self.fs.write(&probe, b""))
+                .and_then(|()| self.fs.remove_file(&probe))
+                .map_err(|source| FinError::NotWritable {
+                    path: dir.clone(),
+                    source,
+                })?;
+        }
+        Ok(())
+    }
+
+    /// Run the configured `post_command` for `plugin`, if any
+    fn run_hook(&self, event: Event, plugin: &Plugin) -> Result<()> {
+        match &self.post_command {
+            Some(template) => events::run_hook(&*self.runner, template, event, plugin),
+            None => Ok(()),
+        }
+    }
+
+    /// Whether `plugin` runs the build command it may declare
+    fn builds(&self, plugin: &Plugin) -> bool {
+        !plugin.raw && (self.allow_scripts || plugin.allow_scripts)
+    }
+
+    /// Whether installing `plugin` now is an install or an update of a recorded one
+    fn event_for(&self, plugin: &Plugin) -> Event {
+        if self.loaded_lock().plugins.contains_name(&plugin.name) {
+            Event::Update
+        } else {
+            Event::Install
+        }
+    }
+
+    /// Fetch a single plugin into a staging directory inside `fin_path`
+    ///
+    /// Only the directories fin installs from are fetched, and staging
+    /// next to the destination lets them be moved into place instead of
+    /// copied. The first registered fetcher that handles the plugin's
+    /// source does the work.
+    async fn fetch_plugin(
+        &self,
+        client: &reqwest::Client,
+        plugin: &mut Plugin,
+    ) -> Result<(TempDir, Option<String>)> {
+        // Whatever commit was recorded before may not be the one fetched now
+        plugin.commit_hash = None;
+        if plugin.latest {
+            self.resolve_latest(client, plugin).await?;
+        }
+        // A digest pins one archive, a plugin pointed at another one is pinned afresh
+        if let Some(installed) = self.loaded_lock().plugins.get(&plugin.name)
+            && installed.source != plugin.source
+        {
+            plugin.archive_digest = None;
+        }
+        let Some(fetcher) = self.fetchers.iter().find(|f| f.handles(plugin)) else {
+            return Err(FinError::Fetch {
+                url: plugin.source.clone(),
+                reason: "unsupported source".to_string(),
+            });
+        };
+
+        let staging = tempfile::Builder::new()
+            .prefix(STAGING_PREFIX)
+            .tempdir_in(&self.fin_path)
+            .with_path("create", &self.fin_path)?;
+        let mut keep = PLUGIN_SUBDIRS.to_vec();
+        keep.extend([PLUGIN_MANIFEST_FILENAME, KEY_BINDINGS_FILENAME]);
+        keep.extend(LICENSE_FILENAMES);
+        if self.docs_dir.is_some() {
+            keep.extend([MAN_SUBDIR, DOC_SUBDIR]);
+        }
+        let ctx = FetchContext {
+            client,
+            host_limiter: &self.host_limiter,
+            retries: self.retries,
+            observer: &*self.observer,
+            // A build may need all of the source
+            keep: (!plugin.raw && !self.builds(plugin)).then_some(keep.as_slice()),
+        };
+
+        let info = match fetcher.fetch(&ctx, plugin, staging.path()).await {
+            Err(FinError::NotFound { url }) => {
+                return Err(match github::repo_and_ref(plugin) {
+                    Some((repo, "HEAD")) => FinError::UnknownRef {
+                        spec: repo.to_string(),
+                    },
+                    Some((repo, ref_name)) => FinError::UnknownRef {
+                        spec: format!("{repo}@{ref_name}"),
+                    },
+                    None => FinError::NotFound { url },
+                });
+            }
+            info => info?,
+        };
+        if info.commit_hash.is_some() {
+            plugin.commit_hash = info.commit_hash;
+        } else if !plugin.latest
+            && let Some((repo, ref_name)) = github::repo_and_ref(plugin)
+        {
+            // Archives that do not say ask the API, a plugin the API cannot tell about still installs
+            plugin.commit_hash = self.github.commit(client, repo, ref_name).await.ok();
+        }
+        Ok((staging, info.archive_checksum))
+    }
+
+    /// Point an `@latest` plugin at its newest release tag and record the tag's commit
+    async fn resolve_latest(&self, client: &reqwest::Client, plugin: &mut Plugin) -> Result<()> {
+        let Some((repo, _)) = github::repo_and_ref(plugin) else {
+            return Err(FinError::Fetch {
+                url: plugin.source.clone(),
+                reason: "@latest only works for GitHub plugins".to_string(),
+            });
+        };
+        let repo = repo.to_string();
+        let (tag, commit) = self.github.newest_release(client, &repo).await?;
+        plugin.source = github::archive_url(&repo, &tag);
+        plugin.commit_hash = Some(commit);
+        Ok(())
+    }
+
+    /// The lock file, read from disk the first time it is needed
+    ///
+    /// Commands that never look at installed plugins, like `fin init`, do
+    /// not touch it at all.
+    fn load_lock(&self) -> Result<&LockFile> {
+        self.load_lock_warning(true)
+    }
+
+    /// Like [`Self::load_lock`], warning about duplicate entries only with `warn_duplicates`
+    fn load_lock_warning(&self, warn_duplicates: bool) -> Result<&LockFile> {
+        if let Some(lock_file) = self.lock_file.get() {
+            return Ok(lock_file);
+        }
+        let path = self
+            .previous_lock_file_path
+            .as_ref()
+            .unwrap_or(&self.fin_lock_file_path);
+        let lock_file = LockFile::load(path)?;
+        // E.g. after resolving a merge conflict by keeping both sides
+        if warn_duplicates && lock_file.duplicates() > 0 {
+            self.observer
+                .lock_warning(&Warning::DuplicateEntries(lock_file.duplicates()));
+        }
+        Ok(self.lock_file.get_or_init(|| lock_file))
+    }
+
+    /// The lock file of an operation that already called [`Self::load_lock`]
+    fn loaded_lock(&self) -> &LockFile {
+        self.lock_file
+            .get()
+            .expect("operations load the lock file before using it")
+    }
+
+    /// Like [`Self::loaded_lock`], marking the lock file for saving
+    fn loaded_lock_mut(&mut self) -> &mut LockFile {
+        self.lock_dirty = true;
+        self.lock_file
+            .get_mut()
+            .expect("operations load the lock file before using it")
+    }
+
+    /// Write the lock file if it was changed and differs from the one on disk
+    fn save_lock(&mut self) -> Result<()> {
+        if !self.lock_dirty {
+            return Ok(());
+        }
+        let content = self.loaded_lock().to_string_as(self.lock_format)?;
+        let mut current = String::new();
+        let unchanged = self
+            .fs
+            .open(&self.fin_lock_file_path)
+            .and_then(|mut file| file.read_to_string(&mut current))
+            .is_ok_and(|_| current == content);
+
+        if !unchanged {
+            self.fs
+                .create_dir_all(&self.fish_config_dir)
+                .with_path("create", &self.fish_config_dir)?;
+            self.fs
+                .write_atomic(&self.fin_lock_file_path, content.as_bytes())
+                .with_path("write", &self.fin_lock_file_path)?;
+            self.write_meta()?;
+            if let Some(template) = &self.git_commit_message {
+                let message = template.replace("{plugins}", &self.changed_plugins(&current));
+                // The operation itself succeeded, a failing commit only warns
+                if let Err(err) = self.commit_lock(&message) {
+                    self.observer.lock_warning(&Warning::Git(err));
+                }
+            }
+        }
+        if let Some(previous) = self.previous_lock_file_path.take() {
+            self.fs
+                .remove_file(&previous)
+                .with_path("remove", &previous)?;
+        }
+        self.lock_dirty = false;
+        Ok(())
+    }
+
+    /// Names of the plugins whose entries differ from the lock file `previous`, comma separated
+    ///
+    /// `lock file` when no plugin changed, like after sorting.
+    fn changed_plugins(&self, previous: &str) -> String {
+        let previous = LockFile::parse(previous, self.lock_format).map(|lock| lock.plugins);
+        let previous = previous.as_ref().ok();
+        let current = &self.loaded_lock().plugins;
+        let entry = |plugin: Option<&Plugin>| plugin.map(|p| serde_json::to_string(p).ok());
+        let mut names: Vec<&str> = current
+            .iter()
+            .chain(previous.into_iter().flat_map(|plugins| plugins.iter()))
+            .map(|plugin| plugin.name.as_str())
+            .filter(|name| {
+                entry(current.get(name)) != entry(previous.and_then(|plugins| plugins.get(name)))
+            })
+            .collect();
+        names.sort_unstable();
+        names.dedup();
+        match names.is_empty() {
+            true => "lock file".to_string(),
+            false => names.join(", "),
+        }
+    }
+
+    /// Commit the lock file, if it is in a git work tree and changed
+    ///
+    /// Only the lock file is committed, whatever else is staged stays staged.
+    fn commit_lock(&self, message: &str) -> Result<()> {
+        let dir = self.fish_config_dir.to_string_lossy();
+        let file = self.fin_lock_file_path.to_string_lossy();
+        let git = |args: &[&str]| {
+            let args = ["-C", &dir].into_iter().chain(args.iter().copied());
+            self.runner
+                .run(&Invocation::new("git", args).captured())
+                .map_err(|err| FinError::Git(format!("failed to run git: {err}")))
+        };
+        let inside = git(&["rev-parse", "--is-inside-work-tree"])?;
+        if !inside.success() || inside.stdout.trim() != "true" {
+            return Ok(());
+        }
+        let failed = |step: &str, output: Output| {
+            let printed = format!("{}{}", output.stdout, output.stderr);
+            FinError::Git(format!("git {step} failed: {}", printed.trim()))
+        };
+        let added = git(&["add", "--", &file])?;
+        if !added.success() {
+            return Err(failed("add", added));
+        }
+        // Exits 0 when the staged lock file matches the last commit
+        if git(&["diff", "--cached", "--quiet", "--", &file])?.success() {
+            return Ok(());
+        }
+        let committed = git(&["commit", "--quiet", "-m", message, "--", &file])?;
+        if !committed.success() {
+            return Err(failed("commit", committed));
+        }
+        Ok(())
+    }
+
+    fn plugins(&self) -> impl Iterator<Item = &str> {
+        self.loaded_lock().plugins.iter().map(|p| p.name.as_str())
+    }
+}
+
+/// How a staged file gets to its destination
+#[derive(Debug, Clone, Copy)]
+enum Transfer {
+    /// Rename, the destination is on the same filesystem as staging
+    Move,
+    /// Copy, the destination may be on another filesystem
+    Copy,
+}
+
+/// A fish version like `3.7.1` as comparable numbers
+///
+/// Only the leading digits of each part count, so `4.0b1` is `4.0` and
+/// `3.7.1-12-gabcdef` is `3.7.1`. Missing parts are zero.
+fn version_numbers(version: &str) -> Vec<u64> {
+    let mut numbers: Vec<u64> = version
+        .split('.')
+        .map_while(|part| {
+            let digits: String = part.chars().take_while(char::is_ascii_digit).collect();
+            digits.parse().ok()
+        })
+        .collect();
+    while numbers.last() == Some(&0) {
+        numbers.pop();
+    }
+    numbers
+}
+
+/// Parse the specs given on the command line, collapsing repeated ones
+///
+/// The same plugin asked for at two different refs would race two installs
+/// into the same files, so that is rejected naming both specs.
+fn parse_requested(specs: &[String]) -> Result<Vec<Plugin>> {
+    // First spec and source seen for each plugin
+    let mut seen: HashMap<String, (&str, String)> = HashMap::new();
+    let mut requested: Vec<Plugin> = Vec::new();
+    for spec in specs {
+        let plugin = Plugin::parse(spec)?;
+        let Some((first, source)) = seen.get(&Plugins::key(&plugin.name)) else {
+            seen.insert(Plugins::key(&plugin.name), (spec, plugin.source.clone()));
+            requested.push(plugin);
+            continue;
+        };
+        // GitHub names are case insensitive, only the ref can tell two specs apart
+        if !source.eq_ignore_ascii_case(&plugin.source) {
+            return Err(FinError::ConflictingSpecs {
+                first: first.to_string(),
+                second: spec.clone(),
+            });
+        }
+    }
+    Ok(requested)
+}
+
+/// Turn a plugin's `key_bindings.fish` into a `conf.d/<repo>_bindings.fish` snippet in staging
+///
+/// Several plugins shipping `fish_user_key_bindings` would overwrite each
+/// other, so every plugin gets a snippet of its own instead. It wraps the
+/// bindings in a function that runs on load and again whenever
+/// `fish_key_bindings` changes, since switching modes drops custom bindings.
+/// `bind` replaces earlier bindings of the same keys, so running it again
+/// is harmless.
+fn stage_key_bindings(fs: &dyn FileSystem, staging: &Path, plugin: &Plugin) -> Result<()> {
+    let src = staging.join(KEY_BINDINGS_FILENAME);
+    let Ok(mut file) = fs.open(&src) else {
+        return Ok(());
+    };
+    let mut bindings = String::new();
+    file.read_to_string(&mut bindings).with_path("read", &src)?;
+
+    let name: String = plugin
+        .basename()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    let function = format!("__fin_{name}_bindings");
+    let snippet = format!(
+        "# Key bindings of {}, installed by fin\n\
+         function {function} --on-variable fish_key_bindings\n\
+         {}\n\
+         end\n\
+         {function}\n",
+        plugin.name,
+        bindings.trim_end()
+    );
+
+    let confd = staging.join(CONFD_SUBDIR);
+    fs.create_dir_all(&confd).with_path("create", &confd)?;
+    let dest = confd.join(format!("{name}_bindings.fish"));
+    fs.write(&dest, snippet.as_bytes())
+        .with_path("write", &dest)
+}
+
+/// Plan moving the files of the standard plugin directories from staging into `fin_path`
+///
+/// The `conf.d` snippets get the plugin's load order prefix, and a
+/// `.disabled` suffix when they are disabled.
+fn plugin_file_moves(
+    fs: &dyn FileSystem,
+    fin_path: &Path,
+    staging: &Path,
+    plugin: &Plugin,
+    warnings: &mut Vec<Warning>,
+) -> Result<Vec<(PathBuf, PathBuf, Transfer)>> {
+    let mut transfers = Vec::new();
+    for component in PLUGIN_SUBDIRS {
+        let src_dir = staging.join(component);
+        if fs.is_dir(&src_dir) {
+            let dest_dir = fin_path.join(component);
+            for src_path in fs.read_dir(&src_dir).with_path("read", &src_dir)? {
+                let Some(file_name) = src_path.file_name() else {
+                    continue;
+                };
+                let skip = |reason| Warning::Skipped {
+                    file: format!("{component}/{}", file_name.to_string_lossy()),
+                    reason,
+                };
+                // Fish does not look into subdirectories of these
+                if fs.is_dir(&src_path) {
+                    warnings.push(skip("Fish does not load subdirectories"));
+                    continue;
+                }
+                // fin's own snippet, a plugin must not replace it
+                if *component == CONFD_SUBDIR && file_name == META_FILENAME {
+                    warnings.push(skip("the name is reserved for fin"));
+                    continue;
+                }
+                let mut file_name = file_name.to_os_string();
+                if *component == CONFD_SUBDIR {
+                    if let Some(prefix) = plugin.confd_prefix() {
+                        let mut prefixed = std::ffi::OsString::from(prefix);
+                        prefixed.push(&file_name);
+                        file_name = prefixed;
+                    }
+                    if plugin.confd_disabled {
+                        file_name.push(DISABLED_SUFFIX);
+                    }
+                }
+                let dest_path = dest_dir.join(file_name);
+                transfers.push((src_path, dest_path, Transfer::Move));
+            }
+        }
+    }
+    Ok(transfers)
+}
+
+/// `completions/<name>` for each of `files` that has the name of a completion Fish ships
+fn completion_shadows<'a>(
+    files: impl Iterator<Item = &'a Path>,
+    shipped: &HashSet<String>,
+) -> Vec<String> {
+    files
+        .filter(|file| file.parent().and_then(Path::file_name) == Some("completions".as_ref()))
+        .filter_map(|file| file.file_name()?.to_str())
+        .filter(|name| shipped.contains(*name))
+        .map(|name| format!("completions/{name}"))
+        .collect()
+}
+
+/// The whole content of a file
+fn read_bytes(fs: &dyn FileSystem, path: &Path) -> Result<Vec<u8>> {
+    let mut content = Vec::new();
+    fs.open(path)
+        .and_then(|mut file| file.read_to_end(&mut content))
+        .with_path("read", path)?;
+    Ok(content)
+}
+
+/// `content` as text, `None` for binary files
+fn as_text(content: &[u8]) -> Option<&str> {
+    std::str::from_utf8(content)
+        .ok()
+        .filter(|text| !text.contains('\0'))
+}
+
+/// Whether `plugin` ships `file`, in place or kept aside
+fn provides(plugin: &Plugin, file: &str) -> bool {
+    [&plugin.installed_files, &plugin.shadowed_files]
+        .into_iter()
+        .any(|files| files.as_ref().is_some_and(|f| f.contains(file)))
+}
+
+/// Plan moving everything in staging below `raw_dir`, keeping its layout
+fn raw_file_moves(
+    fs: &dyn FileSystem,
+    staging: &Path,
+    raw_dir: &Path,
+) -> Result<Vec<(PathBuf, PathBuf, Transfer)>> {
+    Ok(walk_files(fs, staging)?
+        .into_iter()
+        .map(|src_path| {
+            let dest_path = raw_dir.join(src_path.strip_prefix(staging).unwrap_or(&src_path));
+            (src_path, dest_path, Transfer::Move)
+        })
+        .collect())
+}
+
+/// What an install needs to know to settle file conflicts off the async workers
+struct Conflicts {
+    /// Installed files of other plugins, with their owner and priority
+    owners: HashMap<String, (String, i32)>,
+    /// Priority of the plugin being installed
+    priority: i32,
+    on_conflict: OnConflict,
+    /// Directory the lock file paths are relative to
+    base: PathBuf,
+    shadow_dir: PathBuf,
+}
+
+impl Conflicts {
+    /// The installed file `dest` would be, and its current owner and their priority
+    fn owner(&self, dest: &Path) -> Option<(String, &(String, i32))> {
+        let file = dest.strip_prefix(&self.base).ok()?;
+        let file = file.to_string_lossy().to_string();
+        let owner = self.owners.get(&file)?;
+        Some((file, owner))
+    }
+
+    /// Whether the plugin's copy going to `dest` is kept aside instead of installed
+    fn loses(&self, dest: &Path) -> bool {
+        self.owner(dest)
+            .is_some_and(|(_, (_, owner_priority))| match self.on_conflict {
+                OnConflict::Error => false,
+                OnConflict::Skip => true,
+                OnConflict::Overwrite => self.priority < *owner_priority,
+            })
+    }
+
+    /// Settle files that other plugins already provide
+    ///
+    /// When the plugin being installed wins, the current copy is kept aside
+    /// for its owner. When it loses, its own copy is redirected there
+    /// instead. Returns the files that lost, relative to `base`.
+    fn resolve(
+        &self,
+        fs: &dyn FileSystem,
+        name: &str,
+        transfers: &mut [(PathBuf, PathBuf, Transfer)],
+        warnings: &mut Vec<Warning>,
+    ) -> Result<HashSet<String>> {
+        let mut shadowed = HashSet::new();
+        for (_, dest, _) in transfers.iter_mut() {
+            let loses = self.loses(dest);
+            let Some((file, (owner, _))) = self.owner(dest) else {
+                continue;
+            };
+
+            if self.on_conflict == OnConflict::Error {
+                return Err(FinError::Conflict {
+                    file,
+                    owner: owner.clone(),
+                });
+            }
+            if self.on_conflict == OnConflict::Skip {
+                *dest = shadow_path(&self.shadow_dir, name).join(&file);
+                warnings.push(Warning::Conflict {
+                    file: file.clone(),
+                    owner: owner.clone(),
+                });
+                shadowed.insert(file);
+            } else if !loses {
+                let aside = shadow_path(&self.shadow_dir, owner).join(&file);
+                if let Some(parent) = aside.parent() {
+                    fs.create_dir_all(parent).with_path("create", parent)?;
+                }
+                // The owner's copy may already be gone, then there is nothing to keep
+                let _ = fs.copy(dest, &aside);
+                warnings.push(Warning::Overrode {
+                    file,
+                    owner: owner.clone(),
+                });
+            } else {
+                *dest = shadow_path(&self.shadow_dir, name).join(&file);
+                warnings.push(Warning::Shadowed {
+                    file: file.clone(),
+                    owner: owner.clone(),
+                });
+                shadowed.insert(file);
+            }
+        }
+        Ok(shadowed)
+    }
+
+    /// Checksum of the files `transfers` install, `None` when there are none
+    ///
+    /// Matches [`checksum`] of the same files once they are in place. Copies
+    /// that [`Self::resolve`] keeps aside in `shadow_dir` are not part of it,
+    /// it works on the transfers before or after they are resolved.
+    fn checksum(
+        &self,
+        fs: &dyn FileSystem,
+        transfers: &[(PathBuf, PathBuf, Transfer)],
+    ) -> Result<Option<String>> {
+        let files: Vec<(String, &PathBuf)> = transfers
+            .iter()
+            .filter(|(_, dest, _)| !dest.starts_with(&self.shadow_dir) && !self.loses(dest))
+            .map(|(src, dest, _)| {
+                let file = dest.strip_prefix(&self.base).unwrap_or(dest);
+                (file.to_string_lossy().to_string(), src)
+            })
+            .collect();
+        if files.is_empty() {
+            return Ok(None);
+        }
+        checksum_paths(
+            fs,
+            files
+                .iter()
+                .map(|(file, src)| (file.as_str(), src.to_path_buf())),
+        )
+        .map(Some)
+    }
+}
+
+/// Where copies kept aside for the plugin `name` go below `shadow_dir`
+///
+/// Local plugins are named by their absolute path, which must not replace
+/// `shadow_dir` when joined.
+fn shadow_path(shadow_dir: &Path, name: &str) -> PathBuf {
+    shadow_dir.join(name.trim_start_matches('/'))
+}
+
+/// Put every staged file in place, or none of them
+///
+/// Files that get replaced are parked in `backup_dir` first. When a
+/// transfer fails, the files placed so far are removed and the parked ones
+/// restored, so a full or read-only disk does not leave half a plugin
+/// behind. Returns the destinations.
+fn place_files(
+    fs: &dyn FileSystem,
+    transfers: &[(PathBuf, PathBuf, Transfer)],
+    backup_dir: &Path,
+) -> Result<Vec<PathBuf>> {
+    let mut placed: Vec<(&Path, Option<PathBuf>)> = Vec::new();
+
+    for (index, (src, dest, transfer)) in transfers.iter().enumerate() {
+        let result = place_file(
+            fs,
+            src,
+            dest,
+            *transfer,
+            &backup_dir.join(index.to_string()),
+        );
+        match result {
+            Ok(backup) => placed.push((dest, backup)),
+            Err(err) => {
+                for (dest, backup) in placed.into_iter().rev() {
+                    let _ = fs.remove_file(dest);
+                    if let Some(backup) = backup {
+                        let _ = fs.rename(&backup, dest);
+                    }
+                }
+                return Err(err);
+            }
+        }
+    }
+
+    Ok(transfers.iter().map(|(_, dest, _)| dest.clone()).collect())
+}
+
+/// Put one file in place, returning where the file it replaced was parked
+fn place_file(
+    fs: &dyn FileSystem,
+    src: &Path,
+    dest: &Path,
+    transfer: Transfer,
+    backup: &Path,
+) -> Result<Option<PathBuf>> {
+    if let Some(parent) = dest.parent() {
+        fs.create_dir_all(parent).with_path("create", parent)?;
+    }
+
+    let backup = if fs.file_size(dest).is_ok() && !fs.is_dir(dest) {
+        if let Some(parent) = backup.parent() {
+            fs.create_dir_all(parent).with_path("create", parent)?;
+        }
+        fs.rename(dest, backup).with_path("move", dest)?;
+        Some(backup.to_path_buf())
+    } else {
+        None
+    };
+
+    let result = match transfer {
+        Transfer::Move => fs.rename(src, dest).with_path("move", dest),
+        Transfer::Copy => fs.copy(src, dest).with_path("copy", dest),
+    };
+    if let Err(err) = result {
+        let _ = fs.remove_file(dest);
+        if let Some(backup) = &backup {
+            let _ = fs.rename(backup, dest);
+        }
+        return Err(err);
+    }
+
+    Ok(backup)
+}
+
+/// The `conf.d` snippets among `files`
+fn conf_files(files: impl Iterator<Item = PathBuf>) -> Vec<PathBuf> {
+    files
+        .filter(|path| {
+            path.parent().and_then(Path::file_name) == Some(CONFD_SUBDIR.as_ref())
+                && path.extension() == Some("fish".as_ref())
+        })
+        .collect()
+}
+
+/// Plan copying `man/` and `doc/` contents below `docs_dir`
+///
+/// Man pages keep the `manN` section layout so `docs_dir/man` can be put on
+/// `$MANPATH`, docs go to `docs_dir/doc/<repo>`.
+fn plugin_doc_copies(
+    fs: &dyn FileSystem,
+    plugin: &Plugin,
+    src: &Path,
+    docs_dir: &Path,
+) -> Result<Vec<(PathBuf, PathBuf, Transfer)>> {
+    let mut transfers = Vec::new();
+
+    let man_dir = src.join(MAN_SUBDIR);
+    if fs.is_dir(&man_dir) {
+        for file in walk_files(fs, &man_dir)? {
+            let relative = file.strip_prefix(&man_dir).unwrap_or(&file);
+            let dest = match relative.parent() {
+                // Loose pages like `man/foo.1` are sorted into their section
+                Some(parent) if parent.as_os_str().is_empty() => {
+                    // Not a man page without a section, leave it out
+                    let Some(section) = file.extension() else {
+                        continue;
+                    };
+                    let mut section_dir = std::ffi::OsString::from("man");
+                    section_dir.push(section);
+                    docs_dir.join(MAN_SUBDIR).join(section_dir).join(relative)
+                }
+                _ => docs_dir.join(MAN_SUBDIR).join(relative),
+            };
+            transfers.push((file, dest, Transfer::Copy));
+        }
+    }
+
+    let doc_dir = src.join(DOC_SUBDIR);
+    if fs.is_dir(&doc_dir) {
+        let dest_dir = docs_dir.join(DOC_SUBDIR).join(plugin.basename());
+        for file in walk_files(fs, &doc_dir)? {
+            let dest = dest_dir.join(file.strip_prefix(&doc_dir).unwrap_or(&file));
+            transfers.push((file, dest, Transfer::Copy));
+        }
+    }
+
+    Ok(transfers)
+}
+
+/// Plan copying the program a plugin's build produced at `binary` into `bin_dir`
+///
+/// `binary` must name a file inside the plugin's source, it keeps its file
+/// name and permissions.
+fn binary_copy(
+    fs: &dyn FileSystem,
+    plugin: &Plugin,
+    src: &Path,
+    binary: &str,
+    bin_dir: &Path,
+) -> Result<(PathBuf, PathBuf, Transfer)> {
+    let error = |reason: String| FinError::Build {
+        command: plugin.build.clone().unwrap_or_default(),
+        reason,
+    };
+    let relative = Path::new(binary);
+    let inside = relative
+        .components()
+        .all(|component| matches!(component, std::path::Component::Normal(_)));
+    let (true, Some(file_name)) = (inside, relative.file_name()) else {
+        return Err(error(format!(
+            "binary {binary} is not a path inside the plugin"
+        )));
+    };
+    let path = src.join(relative);
+    if fs.is_dir(&path) || fs.file_size(&path).is_err() {
+        return Err(error(format!("it did not produce {binary}")));
+    }
+    Ok((path, bin_dir.join(file_name), Transfer::Copy))
+}
+
+/// List all files below `dir` recursively
+fn walk_files(fs: &dyn FileSystem, dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for path in fs.read_dir(dir).with_path("read", dir)? {
+        if fs.is_dir(&path) {
+            files.extend(walk_files(fs, &path)?);
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        filesystem::{Fault, FaultyFs},
+        runner::RecordingRunner,
+        testing::Server,
+    };
+    use std::{fs, io};
+
+    /// A local plugin with `files`, by path relative to its root
+    fn local_plugin(files: &[(&str, &str)]) -> TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        for (path, contents) in files {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+        dir
+    }
+
+    fn spec(plugin: &TempDir) -> String {
+        plugin.path().display().to_string()
+    }
+
+    /// A fin bound to a new Fish config directory, never starting real programs
+    ///
+    /// Plugins are installed into `fin_path` when given, the config directory otherwise.
+    fn test_fin(
+        config: Config,
+        fin_path: Option<&Path>,
+        fs: Arc<dyn FileSystem>,
+    ) -> (TempDir, Fin) {
+        let config_dir = tempfile::tempdir().unwrap();
+        let config = Config {
+            fish_config_dir: Some(config_dir.path().to_path_buf()),
+            ..config
+        };
+        let fin = Fin::with_fs(fin_path.map(Path::to_path_buf), config, fs)
+            .unwrap()
+            .with_runner(Arc::new(RecordingRunner::default()));
+        (config_dir, fin)
+    }
+
+    fn install(fin: &mut Fin, plugins: &[&TempDir]) -> InstallReport {
+        let specs = plugins.iter().map(|plugin| spec(plugin)).collect();
+        fin.install(Some(specs), InstallOptions::default()).unwrap()
+    }
+
+    fn installed_files(fin: &Fin, plugin: &TempDir) -> Vec<String> {
+        let plugin = fin.plugin(&spec(plugin)).unwrap().unwrap();
+        let mut files: Vec<String> = plugin.installed_files.iter().flatten().cloned().collect();
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn install_places_only_plugin_directories_and_records_them() {
+        let plugin = local_plugin(&[
+            ("functions/hello.fish", "function hello; end"),
+            ("completions/hello.fish", "complete -c hello"),
+            ("README.md", "# hello"),
+            ("tests/hello.fish", "@test"),
+        ]);
+        let (config_dir, mut fin) = test_fin(Config::default(), None, Arc::new(StdFs));
+
+        let report = install(&mut fin, &[&plugin]);
+
+        assert!(report.failed.is_empty());
+        assert_eq!(
+            installed_files(&fin, &plugin),
+            ["completions/hello.fish", "functions/hello.fish"]
+        );
+        let installed = fs::read_to_string(config_dir.path().join("functions/hello.fish"));
+        assert_eq!(installed.unwrap(), "function hello; end");
+        assert!(!config_dir.path().join("README.md").exists());
+        assert!(!config_dir.path().join("tests").exists());
+        let lock = LockFile::read(&config_dir.path().join(LockFormat::Toml.filename())).unwrap();
+        assert!(lock.plugins.contains_name(&spec(&plugin)));
+    }
+
+    #[test]
+    fn install_leaves_nothing_behind_when_a_file_cannot_be_placed() {
+        let plugin = local_plugin(&[
+            ("functions/hello.fish", "function hello; end"),
+            ("completions/hello.fish", "complete -c hello"),
+        ]);
+        let fs = Arc::new(FaultyFs::default());
+        let (config_dir, mut fin) = test_fin(Config::default(), None, fs.clone());
+        fs.fail_below(&config_dir.path().join("completions"), Fault::ReadOnly);
+
+        let report = install(&mut fin, &[&plugin]);
+
+        assert_eq!(report.failed.len(), 1);
+        assert!(!config_dir.path().join("functions/hello.fish").exists());
+        assert!(fin.list().unwrap().is_empty());
+        let staging = fs::read_dir(config_dir.path())
+            .unwrap()
+            .filter_map(|entry| entry.ok())
+            .any(|entry| {
+                entry
+                    .file_name()
+                    .to_string_lossy()
+                    .starts_with(STAGING_PREFIX)
+            });
+        assert!(!staging);
+    }
+
+    #[test]
+    fn remove_deletes_only_the_plugins_own_files() {
+        let hello = local_plugin(&[("functions/hello.fish", "function hello; end")]);
+        let bye = local_plugin(&[("functions/bye.fish", "function bye; end")]);
+        let (config_dir, mut fin) = test_fin(Config::default(), None, Arc::new(StdFs));
+        install(&mut fin, &[&hello, &bye]);
+        let own = config_dir.path().join("functions/mine.fish");
+        fs::write(&own, "function mine; end").unwrap();
+
+        let report = fin
+            .remove(&[spec(&hello)], RemoveOptions::default())
+            .unwrap();
+
+        assert_eq!(report.removed.len(), 1);
+        assert!(report.missing_files.is_empty());
+        assert!(!config_dir.path().join("functions/hello.fish").exists());
+        assert!(config_dir.path().join("functions/bye.fish").exists());
+        assert!(own.exists());
+        assert_eq!(fin.list().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn remove_reports_files_that_were_already_gone() {
+        let plugin = local_plugin(&[
+            ("functions/hello.fish", "function hello; end"),
+            ("completions/hello.fish", "complete -c hello"),
+        ]);
+        let (config_dir, mut fin) = test_fin(Config::default(), None, Arc::new(StdFs));
+        install(&mut fin, &[&plugin]);
+        let gone = config_dir.path().join("completions/hello.fish");
+        fs::remove_file(&gone).unwrap();
+
+        let report = fin
+            .remove(&[spec(&plugin)], RemoveOptions::default())
+            .unwrap();
+
+        assert_eq!(report.missing_files, [gone]);
+        assert!(!config_dir.path().join("functions/hello.fish").exists());
+    }
+
+    /// Records which plugin each callback was about, in order, and every warning
+    #[derive(Default)]
+    struct RecordingObserver {
+        events: std::sync::Mutex<Vec<(&'static str, String)>>,
+        warnings: std::sync::Mutex<Vec<String>>,
+    }
+
+    impl RecordingObserver {
+        fn record(&self, event: &'static str, plugin: &Plugin) {
+            self.events
+                .lock()
+                .unwrap()
+                .push((event, plugin.name.clone()));
+        }
+    }
+
+    impl ProgressObserver for RecordingObserver {
+        fn plugin_started(&self, plugin: &Plugin) {
+            self.record("started", plugin);
+        }
+
+        fn phase_changed(&self, plugin: &Plugin, _phase: Phase) {
+            self.record("phase", plugin);
+        }
+
+        fn plugin_finished(&self, plugin: &Plugin, _result: Result<&Plugin, &FinError>) {
+            self.record("finished", plugin);
+        }
+
+        fn warning(&self, _plugin: &Plugin, warning: &Warning) {
+            self.warnings.lock().unwrap().push(warning.to_string());
+        }
+    }
+
+    #[test]
+    fn one_job_handles_plugins_strictly_one_after_another() {
+        let plugins: Vec<TempDir> = ["a", "b", "c"]
+            .iter()
+            .map(|name| local_plugin(&[(&format!("functions/{name}.fish"), "")]))
+            .collect();
+        let config = Config {
+            jobs: Some(1),
+            ..Default::default()
+        };
+        let (_config_dir, fin) = test_fin(config, None, Arc::new(StdFs));
+        let observer = Arc::new(RecordingObserver::default());
+        let mut fin = fin.with_observer(observer.clone());
+
+        install(&mut fin, &plugins.iter().collect::<Vec<_>>());
+
+        let events = observer.events.lock().unwrap();
+        let mut current: Option<&str> = None;
+        let mut finished = Vec::new();
+        for (event, name) in events.iter() {
+            match (*event, current) {
+                ("started", None) => current = Some(name),
+                ("started", Some(other)) => panic!("{name} started while {other} was running"),
+                (_, Some(running)) => assert_eq!(name, running, "{event} for another plugin"),
+                (_, None) => panic!("{event} for {name} outside of its install"),
+            }
+            if *event == "finished" {
+                finished.push(name.clone());
+                current = None;
+            }
+        }
+        assert_eq!(finished.len(), 3);
+    }
+
+    fn is_empty_dir(dir: &Path) -> bool {
+        fs::read_dir(dir).unwrap().next().is_none()
+    }
+
+    #[test]
+    fn read_only_install_path_fails_before_fetching_anything() {
+        let plugin = local_plugin(&[("functions/hello.fish", "function hello; end")]);
+        let fin_path = tempfile::tempdir().unwrap();
+        let fs = Arc::new(FaultyFs::default());
+        fs.fail_below(fin_path.path(), Fault::ReadOnly);
+        let (config_dir, fin) = test_fin(Config::default(), Some(fin_path.path()), fs);
+        let observer = Arc::new(RecordingObserver::default());
+        let mut fin = fin.with_observer(observer.clone());
+
+        let err = fin
+            .install(Some(vec![spec(&plugin)]), InstallOptions::default())
+            .unwrap_err();
+
+        assert!(matches!(&err, FinError::NotWritable { path, .. } if path == fin_path.path()));
+        assert!(
+            err.to_string()
+                .contains(&*fin_path.path().to_string_lossy())
+        );
+        assert!(observer.events.lock().unwrap().is_empty());
+        assert!(is_empty_dir(fin_path.path()));
+        assert!(is_empty_dir(config_dir.path()));
+    }
+
+    #[test]
+    fn read_only_lock_directory_fails_before_placing_files() {
+        let plugin = local_plugin(&[("functions/hello.fish", "function hello; end")]);
+        let fin_path = tempfile::tempdir().unwrap();
+        let fs = Arc::new(FaultyFs::default());
+        let (config_dir, mut fin) = test_fin(Config::default(), Some(fin_path.path()), fs.clone());
+        fs.fail_below(config_dir.path(), Fault::ReadOnly);
+
+        let err = fin
+            .install(Some(vec![spec(&plugin)]), InstallOptions::default())
+            .unwrap_err();
+
+        assert!(matches!(&err, FinError::NotWritable { path, .. } if path == config_dir.path()));
+        assert!(is_empty_dir(fin_path.path()));
+        assert!(is_empty_dir(config_dir.path()));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn install_into_a_read_only_directory_fails_cleanly() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let plugin = local_plugin(&[("functions/hello.fish", "function hello; end")]);
+        let fin_path = tempfile::tempdir().unwrap();
+        let set_mode = |mode| {
+            fs::set_permissions(fin_path.path(), fs::Permissions::from_mode(mode)).unwrap();
+        };
+        set_mode(0o555);
+        // Root writes regardless of permissions, there is nothing to test then
+        if fs::write(fin_path.path().join("probe"), "").is_ok() {
+            set_mode(0o755);
+            return;
+        }
+        let (config_dir, mut fin) =
+            test_fin(Config::default(), Some(fin_path.path()), Arc::new(StdFs));
+
+        let result = fin.install(Some(vec![spec(&plugin)]), InstallOptions::default());
+
+        set_mode(0o755);
+        assert!(
+            matches!(&result, Err(FinError::NotWritable { path, .. }) if path == fin_path.path()),
+            "{result:?}"
+        );
+        assert!(is_empty_dir(fin_path.path()));
+        assert!(is_empty_dir(config_dir.path()));
+    }
+
+    /// A local plugin whose function is a relative link to a shared file
+    ///
+    /// Its completion links to the absolute path of a file in `outside`.
+    #[cfg(unix)]
+    fn linked_plugin(outside: &TempDir) -> TempDir {
+        let plugin = local_plugin(&[("shared/greet.fish", "function greet; end")]);
+        fs::create_dir(plugin.path().join("functions")).unwrap();
+        fs::create_dir(plugin.path().join("completions")).unwrap();
+        std::os::unix::fs::symlink(
+            "../shared/greet.fish",
+            plugin.path().join("functions/greet.fish"),
+        )
+        .unwrap();
+        let completion = outside.path().join("greet.fish");
+        fs::write(&completion, "complete -c greet").unwrap();
+        std::os::unix::fs::symlink(&completion, plugin.path().join("completions/greet.fish"))
+            .unwrap();
+        plugin
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_in_local_plugins_are_recreated() {
+        let outside = TempDir::new().unwrap();
+        let plugin = linked_plugin(&outside);
+        let (config_dir, mut fin) = test_fin(Config::default(), None, Arc::new(StdFs));
+
+        install(&mut fin, &[&plugin]);
+
+        let installed = config_dir.path().join("functions/greet.fish");
+        assert!(installed.symlink_metadata().unwrap().is_symlink());
+        assert_eq!(
+            fs::canonicalize(&installed).unwrap(),
+            fs::canonicalize(plugin.path().join("shared/greet.fish")).unwrap()
+        );
+        let completion = config_dir.path().join("completions/greet.fish");
+        assert_eq!(
+            fs::read_link(&completion).unwrap(),
+            outside.path().join("greet.fish")
+        );
+        assert_eq!(
+            installed_files(&fin, &plugin),
+            ["completions/greet.fish", "functions/greet.fish"]
+        );
+
+        fin.remove(&[spec(&plugin)], RemoveOptions::default())
+            .unwrap();
+
+        assert!(installed.symlink_metadata().is_err());
+        assert!(completion.symlink_metadata().is_err());
+        assert!(plugin.path().join("shared/greet.fish").exists());
+        assert!(outside.path().join("greet.fish").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn dereference_copies_what_symlinks_point_to() {
+        let outside = TempDir::new().unwrap();
+        let plugin = linked_plugin(&outside);
+        let (config_dir, mut fin) = test_fin(Config::default(), None, Arc::new(StdFs));
+        let options = InstallOptions {
+            dereference: true,
+            ..Default::default()
+        };
+
+        fin.install(Some(vec![spec(&plugin)]), options).unwrap();
+
+        let installed = config_dir.path().join("functions/greet.fish");
+        assert!(installed.symlink_metadata().unwrap().is_file());
+        assert_eq!(
+            fs::read_to_string(&installed).unwrap(),
+            "function greet; end"
+        );
+        assert!(
+            config_dir
+                .path()
+                .join("completions/greet.fish")
+                .symlink_metadata()
+                .unwrap()
+                .is_file()
+        );
+        assert_eq!(
+            installed_files(&fin, &plugin),
+            ["completions/greet.fish", "functions/greet.fish"]
+        );
+        assert!(fin.plugin(&spec(&plugin)).unwrap().unwrap().dereference);
+
+        fin.remove(&[spec(&plugin)], RemoveOptions::default())
+            .unwrap();
+
+        assert!(!installed.exists());
+        assert!(plugin.path().join("shared/greet.fish").exists());
+    }
+
+    #[test]
+    fn update_without_changes_does_not_rewrite_the_lock_file() {
+        let plugin = local_plugin(&[("functions/hello.fish", "function hello; end")]);
+        let (config_dir, mut fin) = test_fin(Config::default(), None, Arc::new(StdFs));
+        install(&mut fin, &[&plugin]);
+        let lock_path = config_dir.path().join(LockFormat::Toml.filename());
+        let modified = fs::metadata(&lock_path).unwrap().modified().unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(20));
+
+        let report = fin.update(&[]).unwrap();
+
+        assert!(report.failed.is_empty());
+        assert_eq!(
+            fs::metadata(&lock_path).unwrap().modified().unwrap(),
+            modified
+        );
+    }
+
+    #[test]
+    fn the_lock_file_is_only_read_when_needed() {
+        let (config_dir, fin) = test_fin(Config::default(), None, Arc::new(StdFs));
+        let lock_path = config_dir.path().join(LockFormat::Toml.filename());
+        fs::write(&lock_path, "not a lock file").unwrap();
+
+        assert_eq!(fin.fish_config_dir(), config_dir.path());
+        assert!(fin.activation_commands(&[]).is_empty());
+        assert!(fin.list().is_err());
+    }
+
+    #[test]
+    fn a_failing_install_handler_warns_and_keeps_the_plugin() {
+        let plugin = local_plugin(&[("conf.d/hello.fish", "function _hello_install; end")]);
+        // The data directory and version lookups find no fish, the handler fails
+        let missing = || Err(io::Error::from(io::ErrorKind::NotFound));
+        let runner = RecordingRunner::default()
+            .respond(missing())
+            .respond(missing())
+            .respond(Ok(Output {
+                code: Some(1),
+                stderr: "hello: broken".into(),
+                ..Default::default()
+            }));
+        let runner = Arc::new(runner);
+        let observer = Arc::new(RecordingObserver::default());
+        let (config_dir, fin) = test_fin(Config::default(), None, Arc::new(StdFs));
+        let mut fin = fin
+            .with_runner(runner.clone())
+            .with_observer(observer.clone());
+
+        let report = install(&mut fin, &[&plugin]);
+
+        assert_eq!(report.installed.len(), 1);
+        assert!(config_dir.path().join("conf.d/hello.fish").exists());
+        let invocations = runner.invocations();
+        let emit = invocations.last().unwrap();
+        assert_eq!(emit.program, "fish");
+        assert!(emit.args[2].contains("emit 'hello_install'"), "{emit:?}");
+        let warnings = observer.warnings.lock().unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("hello: broken"), "{}", warnings[0]);
+    }
+
+    #[test]
+    fn remove_prunes_directories_it_left_empty_in_fin_path() {
+        let plugin = local_plugin(&[
+            ("functions/hello.fish", "function hello; end"),
+            ("completions/hello.fish", "complete -c hello"),
+        ]);
+        let fin_path = tempfile::tempdir().unwrap();
+        let (config_dir, mut fin) =
+            test_fin(Config::default(), Some(fin_path.path()), Arc::new(StdFs));
+        fs::create_dir(config_dir.path().join("functions")).unwrap();
+        install(&mut fin, &[&plugin]);
+        assert!(fin_path.path().join("functions/hello.fish").exists());
+
+        fin.remove(&[spec(&plugin)], RemoveOptions::default())
+            .unwrap();
+
+        assert!(!fin_path.path().join("functions").exists());
+        assert!(!fin_path.path().join("completions").exists());
+        assert!(fin_path.path().exists());
+        assert!(config_dir.path().join("functions").exists());
+    }
+
+    #[test]
+    fn remove_keeps_directories_with_files_of_other_plugins() {
+        let hello = local_plugin(&[
+            ("functions/hello.fish", "function hello; end"),
+            ("completions/hello.fish", "complete -c hello"),
+        ]);
+        let bye = local_plugin(&[("functions/bye.fish", "function bye; end")]);
+        let fin_path = tempfile::tempdir().unwrap();
+        let (_config_dir, mut fin) =
+            test_fin(Config::default(), Some(fin_path.path()), Arc::new(StdFs));
+        install(&mut fin, &[&hello, &bye]);
+
+        fin.remove(&[spec(&hello)], RemoveOptions::default())
+            .unwrap();
+
+        assert!(fin_path.path().join("functions/bye.fish").exists());
+        assert!(!fin_path.path().join("functions/hello.fish").exists());
+        assert!(!fin_path.path().join("completions").exists());
+    }
+
+    #[test]
+    fn a_failed_lock_write_leaves_the_previous_lock_file_intact() {
+        let hello = local_plugin(&[("functions/hello.fish", "function hello; end")]);
+        let bye = local_plugin(&[("functions/bye.fish", "function bye; end")]);
+        let fs = Arc::new(FaultyFs::default());
+        let (config_dir, mut fin) = test_fin(Config::default(), None, fs.clone());
+        install(&mut fin, &[&hello]);
+        let lock_path = config_dir.path().join(LockFormat::Toml.filename());
+        let before = fs::read_to_string(&lock_path).unwrap();
+        fs.fail_below(&lock_path, Fault::Full);
+
+        let result = fin.install(Some(vec![spec(&bye)]), InstallOptions::default());
+
+        assert!(matches!(result, Err(FinError::Io { .. })));
+        assert_eq!(fs::read_to_string(&lock_path).unwrap(), before);
+        let lock = LockFile::read(&lock_path).unwrap();
+        assert!(lock.plugins.contains_name(&spec(&hello)));
+        assert!(!lock.plugins.contains_name(&spec(&bye)));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn executable_plugin_files_stay_executable() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let plugin = local_plugin(&[
+            ("functions/foo.fish", "function foo; end"),
+            ("functions/bar.fish", "function bar; end"),
+        ]);
+        let foo = plugin.path().join("functions/foo.fish");
+        fs::set_permissions(&foo, fs::Permissions::from_mode(0o755)).unwrap();
+        let (config_dir, mut fin) = test_fin(Config::default(), None, Arc::new(StdFs));
+
+        install(&mut fin, &[&plugin]);
+
+        let mode = |file: &str| {
+            let path = config_dir.path().join(file);
+            fs::metadata(path).unwrap().permissions().mode() & 0o777
+        };
+        assert_eq!(mode("functions/foo.fish"), 0o755);
+        assert_eq!(mode("functions/bar.fish") & 0o111, 0);
+    }
+
+    #[test]
+    fn a_blocked_plugin_keeps_nothing_aside_for_the_files_it_would_replace() {
+        let owner = local_plugin(&[("functions/greet.fish", "function greet; end")]);
+        let blocked = local_plugin(&[("functions/greet.fish", "function greet; echo evil; end")]);
+        let (_scratch, mut scratch) = test_fin(Config::default(), None, Arc::new(StdFs));
+        install(&mut scratch, &[&blocked]);
+        let checksum = scratch
+            .plugin(&spec(&blocked))
+            .unwrap()
+            .unwrap()
+            .checksum
+            .clone();
+        let config = Config {
+            blocked_checksums: vec![checksum.unwrap()],
+            ..Default::default()
+        };
+        let (config_dir, mut fin) = test_fin(config, None, Arc::new(StdFs));
+        install(&mut fin, &[&owner]);
+
+        let report = install(&mut fin, &[&blocked]);
+
+        assert!(matches!(report.failed[..], [(_, FinError::Blocked { .. })]));
+        assert!(!config_dir.path().join(SHADOW_DIRNAME).exists());
+        let installed = fs::read_to_string(config_dir.path().join("functions/greet.fish"));
+        assert_eq!(installed.unwrap(), "function greet; end");
+    }
+
+    /// Two installed plugins, the last by name switched to an archive checksum of `url`
+    fn fin_with_archive_checksum(url: &str, archive: &[u8]) -> (Vec<TempDir>, TempDir, Fin) {
+        let plugins: Vec<TempDir> = ["a", "b"]
+            .iter()
+            .map(|name| local_plugin(&[(&format!("functions/{name}.fish"), "")]))
+            .collect();
+        let (config_dir, mut fin) = test_fin(Config::default(), None, Arc::new(StdFs));
+        install(&mut fin, &plugins.iter().collect::<Vec<_>>());
+        let last = fin.list().unwrap().last().unwrap().name.clone();
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("archive"), archive).unwrap();
+        let plugin = fin.loaded_lock_mut().plugins.get_mut(&last).unwrap();
+        plugin.source = format!("{url}/plugin.tar.gz");
+        plugin.checksum_scope = Some(ChecksumScope::Tarball);
+        plugin.checksum = Some(checksum_file(&dir.path().join("archive")).unwrap());
+        (plugins, config_dir, fin)
+    }
+
+    #[test]
+    fn verify_stays_offline_without_download() {
+        let server = Server::start(vec![(200, b"archive".to_vec())]);
+        let (_plugins, _config_dir, fin) = fin_with_archive_checksum(&server.url, b"archive");
+        let names: Vec<String> = fin.list().unwrap().iter().map(|p| p.name.clone()).collect();
+
+        let report = fin.verify(&[], false).unwrap();
+
+        assert_eq!(report.ok, names[..1]);
+        assert_eq!(report.unchecked, names[1..]);
+        assert!(report.failed.is_empty());
+        assert_eq!(server.hits(), 0);
+    }
+
+    #[test]
+    fn verify_downloads_archives_when_asked_and_reports_in_list_order() {
+        let server = Server::start(vec![(200, b"archive".to_vec())]);
+        let (_plugins, _config_dir, fin) = fin_with_archive_checksum(&server.url, b"archive");
+        let names: Vec<String> = fin.list().unwrap().iter().map(|p| p.name.clone()).collect();
+
+        let report = fin.verify(&[], true).unwrap();
+
+        assert_eq!(report.ok, names);
+        assert!(report.unchecked.is_empty() && report.failed.is_empty());
+        assert_eq!(server.hits(), 1);
+    }
+
+    fn license_text(fin: &Fin) -> Option<String> {
+        fin.licenses(true).unwrap().remove(0).text
+    }
+
+    #[test]
+    fn license_texts_are_kept_with_the_installed_plugins() {
+        let plugin = local_plugin(&[
+            ("functions/hello.fish", "function hello; end"),
+            ("LICENSE", "Do what you like"),
+        ]);
+        let (config_dir, mut fin) = test_fin(Config::default(), None, Arc::new(StdFs));
+        let elsewhere = tempfile::tempdir().unwrap();
+        let (_other_config_dir, other) = test_fin(Config::default(), None, Arc::new(StdFs));
+        let mut other = other.with_license_dir(elsewhere.path().to_path_buf());
+
+        install(&mut fin, &[&plugin]);
+        install(&mut other, &[&plugin]);
+
+        assert_eq!(license_text(&fin).as_deref(), Some("Do what you like"));
+        assert!(!is_empty_dir(&config_dir.path().join(LICENSES_DIRNAME)));
+        assert_eq!(license_text(&other).as_deref(), Some("Do what you like"));
+        assert!(!is_empty_dir(elsewhere.path()));
+
+        fin.remove(&[spec(&plugin)], RemoveOptions::default())
+            .unwrap();
+
+        assert!(is_empty_dir(&config_dir.path().join(LICENSES_DIRNAME)));
+        assert_eq!(license_text(&other).as_deref(), Some("Do what you like"));
+    }
+
+    #[test]
+    fn a_failed_update_keeps_the_installed_license_text() {
+        let plugin = local_plugin(&[
+            ("functions/hello.fish", "function hello; end"),
+            ("LICENSE", "First license"),
+        ]);
+        let fs = Arc::new(FaultyFs::default());
+        let (config_dir, mut fin) = test_fin(Config::default(), None, fs.clone());
+        install(&mut fin, &[&plugin]);
+        std::fs::write(plugin.path().join("LICENSE"), "Second license").unwrap();
+        fs.fail_below(&config_dir.path().join("functions"), Fault::ReadOnly);
+        let options = InstallOptions {
+            force: true,
+            ..Default::default()
+        };
+
+        let report = fin.install(Some(vec![spec(&plugin)]), options).unwrap();
+
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(license_text(&fin).as_deref(), Some("First license"));
+    }
 }
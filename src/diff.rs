@@ -0,0 +1,122 @@
+/// Unchanged lines shown around each change
+const CONTEXT: usize = 3;
+/// Largest line comparison table worked out, bigger changes are shown as a
+/// whole replacement instead of the smallest one
+const MAX_CELLS: usize = 4_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Equal,
+    Delete,
+    Insert,
+}
+
+/// Unified diff of two texts, empty when they have the same lines
+///
+/// `old_name` and `new_name` label the `---` and `+++` lines.
+pub fn unified(old: &str, new: &str, old_name: &str, new_name: &str) -> String {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    let edits = edits(&old, &new);
+    let changes: Vec<usize> = (0..edits.len())
+        .filter(|&i| edits[i].0 != Op::Equal)
+        .collect();
+    let Some(&first) = changes.first() else {
+        return String::new();
+    };
+
+    let mut diff = format!("--- {old_name}\n+++ {new_name}\n");
+    let mut hunk_start = first;
+    let mut hunk_end = first;
+    for &change in &changes[1..] {
+        // Close enough that the context of both would touch
+        if change - hunk_end <= 2 * CONTEXT {
+            hunk_end = change;
+            continue;
+        }
+        push_hunk(&mut diff, &edits, hunk_start, hunk_end);
+        hunk_start = change;
+        hunk_end = change;
+    }
+    push_hunk(&mut diff, &edits, hunk_start, hunk_end);
+    diff
+}
+
+/// Append the hunk of the changes from `first` to `last` with their context
+fn push_hunk(diff: &mut String, edits: &[(Op, &str)], first: usize, last: usize) {
+    let start = first.saturating_sub(CONTEXT);
+    let end = (last + CONTEXT + 1).min(edits.len());
+    let count = |range: &[(Op, &str)], skip: Op| range.iter().filter(|(op, _)| *op != skip).count();
+    let (old_before, new_before) = (
+        count(&edits[..start], Op::Insert),
+        count(&edits[..start], Op::Delete),
+    );
+    let (old_len, new_len) = (
+        count(&edits[start..end], Op::Insert),
+        count(&edits[start..end], Op::Delete),
+    );
+    // An empty side names the line before it
+    let line = |before: usize, len: usize| if len == 0 { before } else { before + 1 };
+    diff.push_str(&format!(
+        "@@ -{},{old_len} +{},{new_len} @@\n",
+        line(old_before, old_len),
+        line(new_before, new_len)
+    ));
+    for (op, text) in &edits[start..end] {
+        let marker = match op {
+            Op::Equal => ' ',
+            Op::Delete => '-',
+            Op::Insert => '+',
+        };
+        diff.push(marker);
+        diff.push_str(text);
+        diff.push('\n');
+    }
+}
+
+/// Turn `old` into `new` keeping the longest common subsequence of lines
+fn edits<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<(Op, &'a str)> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let a = &old[prefix..old.len() - suffix];
+    let b = &new[prefix..new.len() - suffix];
+
+    let mut edits: Vec<(Op, &str)> = old[..prefix].iter().map(|l| (Op::Equal, *l)).collect();
+    let (mut i, mut j) = (0, 0);
+    if a.len().saturating_mul(b.len()) <= MAX_CELLS {
+        // Longest common subsequence of a[i..] and b[j..] at i * width + j
+        let width = b.len() + 1;
+        let mut lcs = vec![0u32; (a.len() + 1) * width];
+        for i in (0..a.len()).rev() {
+            for j in (0..b.len()).rev() {
+                lcs[i * width + j] = if a[i] == b[j] {
+                    lcs[(i + 1) * width + j + 1] + 1
+                } else {
+                    lcs[(i + 1) * width + j].max(lcs[i * width + j + 1])
+                };
+            }
+        }
+        while i < a.len() && j < b.len() {
+            if a[i] == b[j] {
+                edits.push((Op::Equal, a[i]));
+                i += 1;
+                j += 1;
+            } else if lcs[(i + 1) * width + j] >= lcs[i * width + j + 1] {
+                edits.push((Op::Delete, a[i]));
+                i += 1;
+            } else {
+                edits.push((Op::Insert, b[j]));
+                j += 1;
+            }
+        }
+    }
+    edits.extend(a[i..].iter().map(|l| (Op::Delete, *l)));
+    edits.extend(b[j..].iter().map(|l| (Op::Insert, *l)));
+    edits.extend(old[old.len() - suffix..].iter().map(|l| (Op::Equal, *l)));
+    edits
+}
@@ -0,0 +1,186 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+/// Errors returned by fin operations
+#[derive(Debug, thiserror::Error)]
+pub enum FinError {
+    #[error("Invalid plugin \"{spec}\": {reason}")]
+    InvalidSpec { spec: String, reason: &'static str },
+
+    #[error("Failed to fetch {url}: {reason}")]
+    Fetch { url: String, reason: String },
+
+    #[error("Failed to fetch {url}: HTTP 404 Not Found")]
+    NotFound { url: String },
+
+    #[error("{spec} not found on GitHub, the repository or ref does not exist or is private")]
+    UnknownRef { spec: String },
+
+    #[error("Failed to load the checksum blocklist from {url}: {reason}")]
+    Blocklist { url: String, reason: String },
+
+    #[error("Refusing to install, the plugin matches blocked checksum {checksum} ({reason})")]
+    Blocked { checksum: String, reason: String },
+
+    #[error("Refusing to install {url}, the archive is {found} but {expected} is pinned")]
+    DigestMismatch {
+        url: String,
+        expected: String,
+        found: String,
+    },
+
+    #[error("Failed to extract {url}: {reason}")]
+    Extract { url: String, reason: String },
+
+    #[error("Failed to {action} {}", path.display())]
+    Io {
+        action: &'static str,
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("Cannot write to {}, is it read-only or full?", path.display())]
+    NotWritable {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("Lock file {} is broken", path.display())]
+    LockParse {
+        path: PathBuf,
+        #[source]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    #[error("Failed to serialize the lock file")]
+    LockSerialize(#[source] Box<dyn std::error::Error + Send + Sync>),
+
+    #[error("Invalid lock file format \"{0}\", expected toml or json")]
+    InvalidLockFormat(String),
+
+    #[error("Invalid plugin manifest {}", path.display())]
+    Manifest {
+        path: PathBuf,
+        #[source]
+        source: toml::de::Error,
+    },
+
+    #[error("Invalid config file {}", path.display())]
+    Config {
+        path: PathBuf,
+        #[source]
+        source: toml::de::Error,
+    },
+
+    #[error("\"{name}\" matches multiple plugins, use the full name:\n  {}", candidates.join("\n  "))]
+    Ambiguous {
+        name: String,
+        candidates: Vec<String>,
+    },
+
+    #[error("\"{first}\" and \"{second}\" ask for different versions of the same plugin")]
+    ConflictingSpecs { first: String, second: String },
+
+    #[error(
+        "{} would replace completions Fish ships, pass --allow-shadow-completions to install anyway",
+        files.join(", ")
+    )]
+    ShadowsCompletions { files: Vec<String> },
+
+    #[error("Requires fish {required} or newer, but fish {found} is installed")]
+    FishTooOld { required: String, found: String },
+
+    #[error("{0} is not installed")]
+    NotInstalled(String),
+
+    #[error("{0} is not installed, drop --reinstall to install it")]
+    NotReinstallable(String),
+
+    #[error("Failed to install {new}, {old} was left installed")]
+    Replace { old: String, new: String },
+
+    #[error("{0} does not ship a prompt")]
+    NotATheme(String),
+
+    #[error("{file} is already installed by {owner}")]
+    Conflict { file: String, owner: String },
+
+    #[error("Invalid conflict mode \"{0}\", expected error, overwrite or skip")]
+    InvalidConflictMode(String),
+
+    #[error("Frozen install failed for {}, the lock file was left untouched", failed.join(", "))]
+    Frozen { failed: Vec<String> },
+
+    #[error("Failed to load plugin registry {url}: {reason}")]
+    Registry { url: String, reason: String },
+
+    #[error("{repo} has no version-numbered releases to resolve @latest to")]
+    NoReleases { repo: String },
+
+    #[error("Registry index uses schema version {found}, this fin supports up to {supported}")]
+    RegistrySchema { found: u32, supported: u32 },
+
+    #[error("Failed to emit {event} events: {reason}")]
+    Event { event: &'static str, reason: String },
+
+    #[error("Hook `{command}` failed: {reason}")]
+    Hook { command: String, reason: String },
+
+    #[error("Build `{command}` failed: {reason}")]
+    Build { command: String, reason: String },
+
+    #[error("Failed to commit the lock file: {0}")]
+    Git(String),
+
+    #[error("Interrupted")]
+    Interrupted,
+
+    #[error("Failed to start the async runtime")]
+    Runtime(#[source] io::Error),
+
+    #[error("Failed to start the checksum threads")]
+    ThreadPool(#[source] rayon::ThreadPoolBuildError),
+
+    #[error("Failed to prompt for an answer")]
+    Prompt(#[source] io::Error),
+
+    #[error(
+        "Cannot locate the Fish configuration directory without a home directory, \
+         set FIN_FISH_CONFIG_DIR, __fish_config_dir or XDG_CONFIG_HOME, or pass --fish-config-dir"
+    )]
+    NoHomeDir,
+}
+
+impl FinError {
+    /// The message followed by all underlying causes
+    pub fn full_message(&self) -> String {
+        let mut message = self.to_string();
+        let mut source = std::error::Error::source(self);
+        while let Some(cause) = source {
+            message.push_str(&format!(": {cause}"));
+            source = cause.source();
+        }
+        message
+    }
+}
+
+pub type Result<T, E = FinError> = std::result::Result<T, E>;
+
+/// Attach the path an io operation worked on
+pub(crate) trait IoResultExt<T> {
+    fn with_path(self, action: &'static str, path: &Path) -> Result<T>;
+}
+
+impl<T> IoResultExt<T> for io::Result<T> {
+    fn with_path(self, action: &'static str, path: &Path) -> Result<T> {
+        self.map_err(|source| FinError::Io {
+            action,
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+}
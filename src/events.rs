@@ -0,0 +1,406 @@
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::{
+    error::{FinError, Result},
+    lock::Plugin,
+    runner::{CommandRunner, Invocation},
+};
+
+/// Plugin lifecycle events, emitted the same way fisher does
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    Install,
+    Update,
+    Uninstall,
+}
+
+impl Event {
+    pub(crate) fn suffix(self) -> &'static str {
+        match self {
+            Event::Install => "install",
+            Event::Update => "update",
+            Event::Uninstall => "uninstall",
+        }
+    }
+}
+
+/// Source each `conf.d` snippet and emit `<snippet>_<event>` for it
+///
+/// Runs in a separate `fish` without user config so snippets are only
+/// sourced once. Handler output goes to stderr to keep stdout clean. A
+/// missing `fish` binary is not an error since there is nothing to notify.
+/// `name_prefix` is a load order prefix fin put in front of the file names,
+/// events are named after the snippet without it.
+pub fn emit(
+    runner: &dyn CommandRunner,
+    event: Event,
+    fin_path: &Path,
+    conf_files: &[PathBuf],
+    name_prefix: Option<&str>,
+) -> Result<()> {
+    if conf_files.is_empty() {
+        return Ok(());
+    }
+
+    let invocation = Invocation::new(
+        "fish",
+        [
+            "--no-config".to_string(),
+            "-c".to_string(),
+            script(event, fin_path, conf_files, name_prefix, false),
+        ],
+    );
+    let error = |reason| FinError::Event {
+        event: event.suffix(),
+        reason,
+    };
+
+    match runner.run(&invocation) {
+        Ok(output) if output.success() => Ok(()),
+        Ok(output) => {
+            let status = match output.code {
+                Some(code) => format!("fish exited with status {code}"),
+                None => "fish was killed by a signal".to_string(),
+            };
+            match output.stderr.trim() {
+                "" => Err(error(status)),
+                stderr => Err(error(format!("{status}: {stderr}"))),
+            }
+        }
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(error(format!("failed to run fish: {err}"))),
+    }
+}
+
+/// Like [`emit`], but tell which events had handlers
+///
+/// Returns the names of the events some handler listened to, and what the
+/// handlers printed. Unlike [`emit`], a missing `fish` is an error since
+/// nothing ran.
+pub fn emit_reporting(
+    runner: &dyn CommandRunner,
+    event: Event,
+    fin_path: &Path,
+    conf_files: &[PathBuf],
+    name_prefix: Option<&str>,
+) -> Result<(Vec<String>, String)> {
+    let script = script(event, fin_path, conf_files, name_prefix, true);
+    let invocation = Invocation::new("fish", ["--no-config", "-c", script.as_str()]).captured();
+    let error = |reason| FinError::Event {
+        event: event.suffix(),
+        reason,
+    };
+
+    match runner.run(&invocation) {
+        Ok(output) if output.success() => {
+            let handled = output.stdout.lines().map(String::from).collect();
+            Ok((handled, output.stderr))
+        }
+        Ok(output) => {
+            let status = match output.code {
+                Some(code) => format!("fish exited with status {code}"),
+                None => "fish was killed by a signal".to_string(),
+            };
+            match output.stderr.trim() {
+                "" => Err(error(status)),
+                stderr => Err(error(format!("{status}: {stderr}"))),
+            }
+        }
+        Err(err) => Err(error(format!("failed to run fish: {err}"))),
+    }
+}
+
+/// Fish code sourcing each snippet and emitting its event
+///
+/// With `report`, the names of events that have handlers are printed on
+/// stdout and everything the handlers print goes to stderr.
+fn script(
+    event: Event,
+    fin_path: &Path,
+    conf_files: &[PathBuf],
+    name_prefix: Option<&str>,
+    report: bool,
+) -> String {
+    let functions = fin_path.join("functions");
+    let mut script = format!(
+        "set -p fish_function_path {}\n",
+        fish_quote(&functions.to_string_lossy())
+    );
+
+    for file in conf_files {
+        let Some(name) = file.file_stem() else {
+            continue;
+        };
+        let name = name.to_string_lossy();
+        let name = name_prefix
+            .and_then(|prefix| name.strip_prefix(prefix))
+            .unwrap_or(&name);
+        let file = fish_quote(&file.to_string_lossy());
+        let event = fish_quote(&format!("{name}_{}", event.suffix()));
+        if report {
+            script.push_str(&format!(
+                "source {file}\n\
+                 if functions --handlers-type event | string match -q -- {event}' *'\n    \
+                 echo {event}\n\
+                 end\n\
+                 begin; emit {event}; end >&2\n"
+            ));
+        } else {
+            script.push_str(&format!("source {file}; emit {event}\n"));
+        }
+    }
+
+    script
+}
+
+/// Run the uninstall hook a plugin declared in its `fin.toml`
+///
+/// The hook runs in a `fish` without user config that has the installed
+/// functions on its path and the plugin's `conf.d` snippets sourced, and is
+/// killed after `timeout`. Returns what it printed on stdout and stderr.
+pub fn run_uninstall_hook(
+    runner: &dyn CommandRunner,
+    hook: &str,
+    fin_path: &Path,
+    conf_files: &[PathBuf],
+    timeout: Duration,
+) -> Result<String> {
+    let functions = fin_path.join("functions");
+    let mut script = format!(
+        "set -p fish_function_path {}\n",
+        fish_quote(&functions.to_string_lossy())
+    );
+    for file in conf_files {
+        script.push_str(&format!("source {}\n", fish_quote(&file.to_string_lossy())));
+    }
+    script.push_str(hook);
+
+    let invocation = Invocation::new("fish", ["--no-config", "-c", script.as_str()])
+        .with_timeout(timeout)
+        .captured();
+    script_output(runner, &invocation, |reason| FinError::Hook {
+        command: hook.to_string(),
+        reason,
+    })
+}
+
+/// Run the build command a plugin declared in its `fin.toml`
+///
+/// The command runs in a `fish` without user config inside `dir`, the
+/// plugin's fetched source, and is killed after `timeout`. Returns what it
+/// printed on stdout and stderr.
+pub fn run_build(
+    runner: &dyn CommandRunner,
+    command: &str,
+    dir: &Path,
+    timeout: Duration,
+) -> Result<String> {
+    let invocation = Invocation::new("fish", ["--no-config", "-c", command])
+        .with_timeout(timeout)
+        .in_dir(dir)
+        .captured();
+    script_output(runner, &invocation, |reason| FinError::Build {
+        command: command.to_string(),
+        reason,
+    })
+}
+
+/// What a captured plugin script printed, or why it failed
+fn script_output(
+    runner: &dyn CommandRunner,
+    invocation: &Invocation,
+    error: impl Fn(String) -> FinError,
+) -> Result<String> {
+    match runner.run(invocation) {
+        Ok(output) if output.success() => Ok(output.stdout + &output.stderr),
+        Ok(output) if output.timed_out => {
+            let timeout = invocation.timeout.unwrap_or_default();
+            Err(error(format!("timed out after {timeout:?}")))
+        }
+        Ok(output) => {
+            let status = match output.code {
+                Some(code) => format!("exited with status {code}"),
+                None => "killed by a signal".to_string(),
+            };
+            match format!("{}{}", output.stdout, output.stderr).trim() {
+                "" => Err(error(status)),
+                printed => Err(error(format!("{status}: {printed}"))),
+            }
+        }
+        Err(err) => Err(error(format!("failed to run fish: {err}"))),
+    }
+}
+
+/// Run the user's `post_command` after `event` succeeded for `plugin`
+///
+/// `{plugin}`, `{source}` and `{action}` in `template` are replaced by
+/// shell-quoted values before it is run with `sh -c`. Output goes to
+/// stderr, a failing hook is reported but does not undo anything.
+pub fn run_hook(
+    runner: &dyn CommandRunner,
+    template: &str,
+    event: Event,
+    plugin: &Plugin,
+) -> Result<()> {
+    let command = template
+        .replace("{plugin}", &sh_quote(&plugin.name))
+        .replace("{source}", &sh_quote(&plugin.source))
+        .replace("{action}", event.suffix());
+    let error = |reason| FinError::Hook {
+        command: command.clone(),
+        reason,
+    };
+
+    match runner.run(&Invocation::new("sh", ["-c", command.as_str()])) {
+        Ok(output) if output.success() => Ok(()),
+        Ok(output) => Err(error(match output.code {
+            Some(code) => format!("exited with status {code}"),
+            None => "killed by a signal".to_string(),
+        })),
+        Err(err) => Err(error(format!("failed to run sh: {err}"))),
+    }
+}
+
+/// Quote a string for POSIX sh, where nothing is special in single quotes
+fn sh_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+/// Quote a string for fish, which only treats `\` and `'` specially in single quotes
+pub(crate) fn fish_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::{Output, RecordingRunner};
+    use std::io;
+
+    fn snippets() -> Vec<PathBuf> {
+        vec![PathBuf::from("/config/conf.d/hello.fish")]
+    }
+
+    fn failed(code: Option<i32>, stderr: &str) -> io::Result<Output> {
+        Ok(Output {
+            code,
+            stderr: stderr.to_string(),
+            ..Default::default()
+        })
+    }
+
+    fn reason(result: Result<()>) -> String {
+        match result {
+            Err(FinError::Event { reason, .. }) => reason,
+            other => panic!("expected an event error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn emit_starts_nothing_without_snippets() {
+        let runner = RecordingRunner::default();
+
+        emit(&runner, Event::Install, Path::new("/fin"), &[], None).unwrap();
+
+        assert!(runner.invocations().is_empty());
+    }
+
+    #[test]
+    fn emit_sources_the_snippets_in_a_fish_without_config() {
+        let runner = RecordingRunner::default();
+
+        emit(
+            &runner,
+            Event::Install,
+            Path::new("/fin"),
+            &snippets(),
+            None,
+        )
+        .unwrap();
+
+        let invocations = runner.invocations();
+        assert_eq!(invocations.len(), 1);
+        assert_eq!(invocations[0].program, "fish");
+        assert_eq!(invocations[0].args[..2], ["--no-config", "-c"]);
+        let script = &invocations[0].args[2];
+        assert!(script.contains("/config/conf.d/hello.fish"), "{script}");
+        assert!(script.contains("hello_install"), "{script}");
+    }
+
+    #[test]
+    fn emit_is_fine_without_fish() {
+        let runner =
+            RecordingRunner::default().respond(Err(io::Error::from(io::ErrorKind::NotFound)));
+
+        emit(
+            &runner,
+            Event::Install,
+            Path::new("/fin"),
+            &snippets(),
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(runner.invocations().len(), 1);
+    }
+
+    #[test]
+    fn emit_fails_when_fish_cannot_start() {
+        let runner = RecordingRunner::default()
+            .respond(Err(io::Error::from(io::ErrorKind::PermissionDenied)));
+
+        let result = emit(&runner, Event::Update, Path::new("/fin"), &snippets(), None);
+
+        assert!(reason(result).starts_with("failed to run fish"));
+    }
+
+    #[test]
+    fn emit_reports_a_failing_handler_with_its_stderr() {
+        let runner = RecordingRunner::default().respond(failed(Some(2), "hello: broken\n"));
+
+        let result = emit(
+            &runner,
+            Event::Install,
+            Path::new("/fin"),
+            &snippets(),
+            None,
+        );
+
+        assert_eq!(reason(result), "fish exited with status 2: hello: broken");
+    }
+
+    #[test]
+    fn emit_reports_fish_killed_by_a_signal() {
+        let runner = RecordingRunner::default().respond(failed(None, ""));
+
+        let result = emit(
+            &runner,
+            Event::Uninstall,
+            Path::new("/fin"),
+            &snippets(),
+            None,
+        );
+
+        assert_eq!(reason(result), "fish was killed by a signal");
+    }
+
+    #[test]
+    fn emit_reporting_needs_fish() {
+        let runner =
+            RecordingRunner::default().respond(Err(io::Error::from(io::ErrorKind::NotFound)));
+
+        let result = emit_reporting(
+            &runner,
+            Event::Install,
+            Path::new("/fin"),
+            &snippets(),
+            None,
+        );
+
+        assert!(matches!(result, Err(FinError::Event { .. })));
+    }
+}
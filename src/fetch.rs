@@ -0,0 +1,651 @@
+use flate2::read::GzDecoder;
+use reqwest::Client;
+use std::{
+    fs,
+    io::{self, BufReader},
+    path::{Component, Path, PathBuf},
+    time::Duration,
+};
+use tokio::{fs::File, io::AsyncWriteExt};
+
+use crate::error::{FinError, IoResultExt, Result};
+
+/// Build the HTTP client shared by all downloads of one operation
+pub(crate) fn client() -> Result<Client> {
+    Client::builder()
+        .user_agent(concat!("fin/", env!("CARGO_PKG_VERSION")))
+        .build()
+        .map_err(|err| FinError::Fetch {
+            url: String::new(),
+            reason: format!("failed to build HTTP client: {err}"),
+        })
+}
+
+/// First wait before trying a failed download again, doubled for every further try
+const RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// Download `url` into the file `dest`
+///
+/// Server errors, rate limiting and dropped connections are tried again
+/// up to `retries` times with exponential backoff, other client errors
+/// like 404 fail right away. Every try starts `dest` over. `on_progress`
+/// receives the bytes written so far and the total size when the server
+/// announced it.
+pub(crate) async fn download(
+    client: &Client,
+    url: &str,
+    dest: &Path,
+    retries: u32,
+    on_progress: impl Fn(u64, Option<u64>),
+) -> Result<()> {
+    let mut delay = RETRY_DELAY;
+    let mut attempt = 0;
+    loop {
+        match download_once(client, url, dest, &on_progress).await {
+            Err((_, true)) if attempt < retries => {
+                attempt += 1;
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+            }
+            result => return result.map_err(|(err, _)| err),
+        }
+    }
+}
+
+/// One try of [`download`], failing with whether trying again may help
+async fn download_once(
+    client: &Client,
+    url: &str,
+    dest: &Path,
+    on_progress: impl Fn(u64, Option<u64>),
+) -> std::result::Result<(), (FinError, bool)> {
+    let fetch_error = |reason: String| FinError::Fetch {
+        url: url.to_string(),
+        reason,
+    };
+
+    let mut response = client
+        .get(url)
+        .send()
+        .await
+        .map_err(|err| (fetch_error(err.to_string()), true))?;
+    let status = response.status();
+    if status == reqwest::StatusCode::NOT_FOUND {
+        let err = FinError::NotFound {
+            url: url.to_string(),
+        };
+        return Err((err, false));
+    }
+    if !status.is_success() {
+        let transient =
+            status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS;
+        return Err((fetch_error(format!("HTTP {status}")), transient));
+    }
+
+    let total = response.content_length();
+    let mut file = File::create(dest)
+        .await
+        .with_path("create", dest)
+        .map_err(|err| (err, false))?;
+    let mut received = 0;
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|err| (fetch_error(err.to_string()), true))?
+    {
+        file.write_all(&chunk)
+            .await
+            .with_path("write", dest)
+            .map_err(|err| (err, false))?;
+        received += chunk.len() as u64;
+        on_progress(received, total);
+    }
+    file.flush()
+        .await
+        .with_path("write", dest)
+        .map_err(|err| (err, false))?;
+
+    Ok(())
+}
+
+/// Unpack a gzipped tarball into `dest`, dropping `strip` leading path components
+///
+/// Only entries below one of the `keep` top-level directories are written,
+/// everything else in the archive is skipped while reading it, `None` keeps
+/// every entry. Entries that would escape `dest` are skipped too, including
+/// links pointing outside of it and files below a directory that leads
+/// outside through a link. Blocking, run it on a blocking thread.
+///
+/// Returns the commit the archive was made from when it says, like the
+/// ones `git archive` produces for GitHub and other forges.
+pub(crate) fn extract(
+    url: &str,
+    archive: &Path,
+    dest: &Path,
+    strip: usize,
+    keep: Option<&[&str]>,
+) -> Result<Option<String>> {
+    let extract_error = |err: io::Error| FinError::Extract {
+        url: url.to_string(),
+        reason: err.to_string(),
+    };
+
+    let root = fs::canonicalize(dest).with_path("open", dest)?;
+    let file = fs::File::open(archive).with_path("open", archive)?;
+    let mut tarball = tar::Archive::new(GzDecoder::new(BufReader::new(file)));
+    let mut commit = None;
+    for entry in tarball.entries().map_err(extract_error)? {
+        let mut entry = entry.map_err(extract_error)?;
+        if entry.header().entry_type().is_pax_global_extensions() {
+            commit = archive_commit(&mut entry).map_err(extract_error)?;
+            continue;
+        }
+        let path = entry.path().map_err(extract_error)?;
+        let Some(relative) = strip_path(&path, strip) else {
+            continue;
+        };
+        let kept = |keep: &[&str]| {
+            relative
+                .components()
+                .next()
+                .is_some_and(|top| keep.iter().any(|k| top.as_os_str() == *k))
+        };
+        if !keep.is_none_or(kept) {
+            continue;
+        }
+
+        let entry_type = entry.header().entry_type();
+        let link = match entry.link_name().map_err(extract_error)? {
+            Some(target) if entry_type.is_symlink() => {
+                if !link_stays_inside(&relative, &target) {
+                    continue;
+                }
+                None
+            }
+            // Hard links name another entry of the archive, stripped like it
+            Some(target) if entry_type.is_hard_link() => match strip_path(&target, strip) {
+                Some(target) => Some(dest.join(target)),
+                None => continue,
+            },
+            _ => None,
+        };
+
+        let dest_path = dest.join(&relative);
+        let parent = match entry_type.is_dir() {
+            true => dest_path.as_path(),
+            false => dest_path.parent().unwrap_or(dest),
+        };
+        if !stays_inside(&root, parent) {
+            continue;
+        }
+        fs::create_dir_all(parent).with_path("create", parent)?;
+        match link {
+            _ if entry_type.is_dir() => {}
+            Some(target) if target.is_file() && stays_inside(&root, &target) => {
+                let _ = fs::remove_file(&dest_path);
+                fs::hard_link(&target, &dest_path).with_path("extract", &dest_path)?;
+            }
+            Some(_) => {}
+            None => {
+                entry.unpack(&dest_path).with_path("extract", &dest_path)?;
+            }
+        }
+    }
+
+    Ok(commit)
+}
+
+/// The commit id `git archive` stores as the comment of a global pax header
+fn archive_commit<R: io::Read>(entry: &mut tar::Entry<R>) -> io::Result<Option<String>> {
+    let Some(extensions) = entry.pax_extensions()? else {
+        return Ok(None);
+    };
+    for extension in extensions {
+        let extension = extension?;
+        if extension.key() == Ok("comment")
+            && let Ok(value) = extension.value()
+            && value.len() == 40
+            && value.bytes().all(|b| b.is_ascii_hexdigit())
+        {
+            return Ok(Some(value.to_string()));
+        }
+    }
+    Ok(None)
+}
+
+/// `path` without its first `strip` components, `None` when nothing is left
+/// or the path is not a plain relative one
+fn strip_path(path: &Path, strip: usize) -> Option<PathBuf> {
+    let mut relative = PathBuf::new();
+    for (i, component) in path.components().enumerate() {
+        match component {
+            Component::Normal(part) if i >= strip => relative.push(part),
+            Component::Normal(_) | Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    (!relative.as_os_str().is_empty()).then_some(relative)
+}
+
+/// Whether a symlink at `link` pointing to `target` stays below the directory `link` is relative to
+///
+/// Only looks at the paths, the links checked before it make sure that
+/// none of the directories on the way lead elsewhere.
+fn link_stays_inside(link: &Path, target: &Path) -> bool {
+    let mut depth = link.components().count().saturating_sub(1);
+    for component in target.components() {
+        match component {
+            Component::Normal(_) => depth += 1,
+            Component::CurDir => {}
+            Component::ParentDir if depth > 0 => depth -= 1,
+            _ => return false,
+        }
+    }
+    true
+}
+
+/// Whether `path`, or the part of it that exists, resolves to somewhere below `root`
+///
+/// `root` has to be canonical already.
+fn stays_inside(root: &Path, path: &Path) -> bool {
+    // A dangling link counts as existing, and does not resolve
+    let existing = path
+        .ancestors()
+        .find(|ancestor| ancestor.symlink_metadata().is_ok());
+    existing
+        .and_then(|existing| fs::canonicalize(existing).ok())
+        .is_some_and(|resolved| resolved.starts_with(root))
+}
+
+/// How [`copy_dir`] treats symlinks and nested directories
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct CopyOptions {
+    /// Copy the targets of symlinks instead of recreating the links
+    pub dereference: bool,
+    /// Directory levels below the plugin root to descend into, unlimited when unset
+    pub max_depth: Option<usize>,
+}
+
+/// Copy the `keep` top-level directories and files of a local plugin into `dest`
+///
+/// `None` copies the whole directory.
+/// Symlinks are recreated pointing at the absolute path of their target,
+/// so they still resolve once moved out of `dest`. With `dereference` the
+/// target is copied instead. Directories deeper than `max_depth`, where
+/// `functions/` is level 1, are skipped. Blocking, run it on a blocking
+/// thread.
+pub(crate) fn copy_dir(
+    src: &Path,
+    dest: &Path,
+    keep: Option<&[&str]>,
+    options: CopyOptions,
+) -> Result<()> {
+    let Some(keep) = keep else {
+        return copy_tree(src, dest, options, 0);
+    };
+    for dir in keep {
+        let src_path = src.join(dir);
+        if src_path.is_dir() {
+            if options.max_depth.is_some_and(|max| max < 1) {
+                continue;
+            }
+            copy_tree(&src_path, &dest.join(dir), options, 1)?;
+        } else if src_path.is_file() {
+            fs::copy(&src_path, dest.join(dir)).with_path("copy", &src_path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Copy `src`, which is `depth` levels below the plugin root, to `dest`
+fn copy_tree(src: &Path, dest: &Path, options: CopyOptions, depth: usize) -> Result<()> {
+    fs::create_dir_all(dest).with_path("create", dest)?;
+    for entry in fs::read_dir(src).with_path("read", src)? {
+        let src_path = entry.with_path("read", src)?.path();
+        let dest_path = dest.join(src_path.file_name().unwrap_or_default());
+        let metadata = fs::symlink_metadata(&src_path).with_path("read", &src_path)?;
+
+        if metadata.is_symlink() && !options.dereference {
+            let target = fs::read_link(&src_path).with_path("read", &src_path)?;
+            symlink(&src.join(target), &dest_path).with_path("create", &dest_path)?;
+        } else if src_path.is_dir() {
+            if options.max_depth.is_some_and(|max| depth >= max) {
+                continue;
+            }
+            copy_tree(&src_path, &dest_path, options, depth + 1)?;
+        } else {
+            fs::copy(&src_path, &dest_path).with_path("copy", &src_path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Create a symlink at `link` pointing to `target`
+#[cfg(unix)]
+fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+/// Links need extra privileges on Windows, local plugins with links have to be dereferenced
+#[cfg(not(unix))]
+fn symlink(_target: &Path, _link: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "symlinks in local plugins need --dereference on this platform",
+    ))
+}
+
+/// Run blocking filesystem or process work off the async workers
+pub(crate) async fn blocking<T: Send + 'static>(
+    f: impl FnOnce() -> Result<T> + Send + 'static,
+) -> Result<T> {
+    match tokio::task::spawn_blocking(f).await {
+        Ok(result) => result,
+        Err(err) => std::panic::resume_unwind(err.into_panic()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{Entry, Server, tarball};
+
+    /// Extract `entries` the way a GitHub archive is, keeping `functions/`
+    fn extract_entries(entries: &[Entry]) -> (tempfile::TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = tarball(dir.path(), entries);
+        let dest = dir.path().join("staging");
+        fs::create_dir(&dest).unwrap();
+        extract("test", &archive, &dest, 1, Some(&["functions"])).unwrap();
+        (dir, dest)
+    }
+
+    #[test]
+    fn extract_keeps_only_the_requested_directories() {
+        let (_dir, dest) = extract_entries(&[
+            Entry::File("repo-main/functions/hello.fish", "function hello; end"),
+            Entry::File("repo-main/README.md", "# hello"),
+        ]);
+
+        let hello = fs::read_to_string(dest.join("functions/hello.fish")).unwrap();
+        assert_eq!(hello, "function hello; end");
+        assert!(!dest.join("README.md").exists());
+    }
+
+    #[test]
+    fn extract_does_not_write_through_a_symlink_leading_outside() {
+        let outside = tempfile::tempdir().unwrap();
+        let target = outside.path().to_str().unwrap();
+        let (_dir, dest) = extract_entries(&[
+            Entry::Symlink("repo-main/functions/evil", target),
+            Entry::File(
+                "repo-main/functions/evil/authorized_keys",
+                "ssh-ed25519 AAAA",
+            ),
+            Entry::Symlink("repo-main/functions/up", "../../.."),
+            Entry::File("repo-main/functions/up/escaped.fish", "echo escaped"),
+        ]);
+
+        assert_eq!(fs::read_dir(outside.path()).unwrap().count(), 0);
+        for link in ["functions/evil", "functions/up"] {
+            let metadata = dest.join(link).symlink_metadata();
+            assert!(!metadata.is_ok_and(|metadata| metadata.is_symlink()));
+        }
+        assert!(!dest.parent().unwrap().join("escaped.fish").exists());
+    }
+
+    #[test]
+    fn extract_does_not_hard_link_files_outside() {
+        let (dir, dest) = extract_entries(&[
+            Entry::File("repo-main/functions/hello.fish", "function hello; end"),
+            Entry::HardLink(
+                "repo-main/functions/hi.fish",
+                "repo-main/functions/hello.fish",
+            ),
+            Entry::HardLink(
+                "repo-main/functions/archive.fish",
+                "repo-main/../plugin.tar.gz",
+            ),
+        ]);
+
+        let hi = fs::read_to_string(dest.join("functions/hi.fish")).unwrap();
+        assert_eq!(hi, "function hello; end");
+        assert!(!dest.join("functions/archive.fish").exists());
+        assert!(dir.path().join("plugin.tar.gz").exists());
+    }
+
+    #[test]
+    fn extract_keeps_symlinks_inside_the_plugin() {
+        let (_dir, dest) = extract_entries(&[
+            Entry::File("repo-main/functions/hello.fish", "function hello; end"),
+            Entry::Symlink("repo-main/functions/hi.fish", "hello.fish"),
+        ]);
+
+        let link = dest.join("functions/hi.fish");
+        assert!(link.symlink_metadata().unwrap().is_symlink());
+        assert_eq!(fs::read_to_string(link).unwrap(), "function hello; end");
+    }
+
+    #[test]
+    fn strip_path_drops_leading_components() {
+        let path = Path::new("repo-main/functions/hello.fish");
+        assert_eq!(
+            strip_path(path, 1),
+            Some(PathBuf::from("functions/hello.fish"))
+        );
+        assert_eq!(strip_path(path, 0), Some(path.to_path_buf()));
+        // Like tar, a leading `.` counts as a component
+        assert_eq!(
+            strip_path(Path::new("./functions/hello.fish"), 1),
+            Some(PathBuf::from("functions/hello.fish"))
+        );
+    }
+
+    #[test]
+    fn strip_path_skips_what_is_stripped_away() {
+        assert_eq!(strip_path(Path::new("repo-main"), 1), None);
+        assert_eq!(strip_path(Path::new("repo-main/functions"), 3), None);
+    }
+
+    #[test]
+    fn strip_path_rejects_paths_leaving_the_destination() {
+        assert_eq!(strip_path(Path::new("repo/../../etc/passwd"), 1), None);
+        assert_eq!(strip_path(Path::new("/etc/passwd"), 0), None);
+        assert_eq!(strip_path(Path::new("../outside"), 1), None);
+    }
+
+    /// Download `url` with `retries`, returning where it went and the result
+    fn download_with_retries(url: &str, retries: u32) -> (tempfile::TempDir, Result<()>) {
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("plugin.tar.gz");
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let client = client().unwrap();
+        let result = runtime.block_on(download(&client, url, &dest, retries, |_, _| {}));
+        (dir, result)
+    }
+
+    #[test]
+    fn download_writes_the_response_body() {
+        let server = Server::start(vec![(200, b"archive".to_vec())]);
+
+        let (dir, result) = download_with_retries(&server.url, 2);
+
+        result.unwrap();
+        assert_eq!(
+            fs::read(dir.path().join("plugin.tar.gz")).unwrap(),
+            b"archive"
+        );
+        assert_eq!(server.hits(), 1);
+    }
+
+    #[test]
+    fn download_does_not_retry_a_missing_archive() {
+        let server = Server::start(vec![(404, Vec::new()), (200, Vec::new())]);
+
+        let (_dir, result) = download_with_retries(&server.url, 2);
+
+        assert!(matches!(result, Err(FinError::NotFound { .. })));
+        assert_eq!(server.hits(), 1);
+    }
+
+    #[test]
+    fn download_retries_server_errors_until_it_gives_up() {
+        let server = Server::start(vec![(500, Vec::new()), (503, Vec::new())]);
+
+        let (_dir, result) = download_with_retries(&server.url, 1);
+
+        match result {
+            Err(FinError::Fetch { reason, .. }) => assert!(reason.contains("503"), "{reason}"),
+            other => panic!("expected a fetch error, got {other:?}"),
+        }
+        assert_eq!(server.hits(), 2);
+    }
+
+    #[test]
+    fn download_succeeds_once_a_retry_does() {
+        let server = Server::start(vec![(500, Vec::new()), (200, b"archive".to_vec())]);
+
+        let (dir, result) = download_with_retries(&server.url, 1);
+
+        result.unwrap();
+        assert_eq!(
+            fs::read(dir.path().join("plugin.tar.gz")).unwrap(),
+            b"archive"
+        );
+    }
+
+    #[test]
+    fn download_fails_when_nothing_listens() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        drop(listener);
+
+        let (_dir, result) = download_with_retries(&url, 0);
+
+        assert!(matches!(result, Err(FinError::Fetch { .. })));
+    }
+
+    #[test]
+    fn extract_fails_on_a_corrupt_archive() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("plugin.tar.gz");
+        fs::write(&archive, "<html>not an archive</html>").unwrap();
+        let dest = dir.path().join("staging");
+        fs::create_dir(&dest).unwrap();
+
+        let result = extract("test", &archive, &dest, 1, None);
+
+        assert!(matches!(result, Err(FinError::Extract { .. })));
+    }
+
+    #[test]
+    fn extract_fails_on_a_truncated_archive() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = tarball(
+            dir.path(),
+            &[Entry::File(
+                "repo-main/functions/hello.fish",
+                &"x".repeat(4096),
+            )],
+        );
+        let bytes = fs::read(&archive).unwrap();
+        fs::write(&archive, &bytes[..bytes.len() / 2]).unwrap();
+        let dest = dir.path().join("staging");
+        fs::create_dir(&dest).unwrap();
+
+        let result = extract("test", &archive, &dest, 1, None);
+
+        assert!(matches!(result, Err(FinError::Extract { .. })));
+    }
+
+    /// Files below `root`, relative to it and sorted
+    fn files_below(root: &Path) -> Vec<String> {
+        let mut files = Vec::new();
+        let mut dirs = vec![root.to_path_buf()];
+        while let Some(dir) = dirs.pop() {
+            for entry in fs::read_dir(dir).unwrap() {
+                let path = entry.unwrap().path();
+                if path.is_dir() {
+                    dirs.push(path);
+                } else {
+                    let file = path.strip_prefix(root).unwrap();
+                    files.push(file.to_string_lossy().into_owned());
+                }
+            }
+        }
+        files.sort();
+        files
+    }
+
+    /// Copy a plugin nested three levels below `functions/` with `keep` and `max_depth`
+    fn copy_nested(keep: Option<&[&str]>, max_depth: Option<usize>) -> Vec<String> {
+        let src = tempfile::tempdir().unwrap();
+        for path in [
+            "README.md",
+            "functions/a.fish",
+            "functions/sub/b.fish",
+            "functions/sub/deeper/c.fish",
+        ] {
+            let path = src.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+        let dest = tempfile::tempdir().unwrap();
+        let options = CopyOptions {
+            dereference: false,
+            max_depth,
+        };
+
+        copy_dir(src.path(), dest.path(), keep, options).unwrap();
+
+        files_below(dest.path())
+    }
+
+    #[test]
+    fn copy_dir_stops_at_max_depth_below_kept_directories() {
+        let keep = Some(&["functions"][..]);
+
+        assert!(copy_nested(keep, Some(0)).is_empty());
+        assert_eq!(copy_nested(keep, Some(1)), ["functions/a.fish"]);
+        assert_eq!(
+            copy_nested(keep, Some(2)),
+            ["functions/a.fish", "functions/sub/b.fish"]
+        );
+        assert_eq!(
+            copy_nested(keep, None),
+            [
+                "functions/a.fish",
+                "functions/sub/b.fish",
+                "functions/sub/deeper/c.fish"
+            ]
+        );
+    }
+
+    #[test]
+    fn copy_dir_stops_at_max_depth_when_copying_everything() {
+        assert_eq!(copy_nested(None, Some(0)), ["README.md"]);
+        assert_eq!(
+            copy_nested(None, Some(1)),
+            ["README.md", "functions/a.fish"]
+        );
+        assert_eq!(copy_nested(None, None).len(), 4);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn extract_keeps_the_executable_bit() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let (_dir, dest) = extract_entries(&[
+            Entry::Executable("repo-main/functions/foo.fish", ""),
+            Entry::File("repo-main/functions/bar.fish", ""),
+        ]);
+
+        let mode = |path: &str| fs::metadata(dest.join(path)).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode("functions/foo.fish"), 0o755);
+        assert_eq!(mode("functions/bar.fish"), 0o644);
+    }
+}
@@ -0,0 +1,272 @@
+use futures_util::future::BoxFuture;
+use reqwest::Client;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    checksum::checksum_file,
+    error::{FinError, IoResultExt, Result},
+    fetch::{self, CopyOptions, blocking},
+    limit::HostLimiter,
+    lock::Plugin,
+    progress::{Phase, ProgressObserver},
+};
+
+/// Leading archive path components dropped unless a plugin says otherwise
+pub(crate) const DEFAULT_STRIP_COMPONENTS: usize = 1;
+const ARCHIVE_FILENAME: &str = ".fin-archive.tar.gz";
+
+/// What a fetch learned about the plugin besides its files
+#[derive(Debug, Clone, Default)]
+pub struct FetchInfo {
+    /// The commit the fetched files come from, when the source tells
+    pub commit_hash: Option<String>,
+    /// Checksum of the downloaded archive, for sources that are one
+    pub archive_checksum: Option<String>,
+}
+
+/// Shared state of the fetches of one operation
+pub struct FetchContext<'a> {
+    /// HTTP client reused across plugins
+    pub client: &'a Client,
+    /// Caps concurrent downloads per host, hold a permit while downloading
+    pub host_limiter: &'a HostLimiter,
+    /// Times a download is tried again after a transient failure
+    pub retries: u32,
+    pub observer: &'a dyn ProgressObserver,
+    /// Top-level plugin directories and files to fetch, everything else can be skipped
+    ///
+    /// `None` for [raw](Plugin::raw) installs, which keep everything.
+    pub keep: Option<&'a [&'static str]>,
+}
+
+/// Gets a plugin's files from its source
+///
+/// Each kind of source gets its own implementation, [`Fin`](crate::Fin)
+/// picks the first registered fetcher that handles a plugin. Implement it
+/// to install from sources fin does not know about.
+pub trait Fetcher: Send + Sync {
+    /// Whether this fetcher understands the source of `plugin`
+    fn handles(&self, plugin: &Plugin) -> bool;
+
+    /// Put the plugin directories and files listed in `ctx.keep` below `dest`
+    ///
+    /// `dest` is an empty staging directory next to the install location.
+    /// Anything else left in it is ignored.
+    fn fetch<'a>(
+        &'a self,
+        ctx: &'a FetchContext<'a>,
+        plugin: &'a Plugin,
+        dest: &'a Path,
+    ) -> BoxFuture<'a, Result<FetchInfo>>;
+}
+
+/// Plugins in a local directory, copied as they are
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LocalFetcher;
+
+impl Fetcher for LocalFetcher {
+    fn handles(&self, plugin: &Plugin) -> bool {
+        plugin.is_local()
+    }
+
+    fn fetch<'a>(
+        &'a self,
+        ctx: &'a FetchContext<'a>,
+        plugin: &'a Plugin,
+        dest: &'a Path,
+    ) -> BoxFuture<'a, Result<FetchInfo>> {
+        Box::pin(async move {
+            ctx.observer.phase_changed(plugin, Phase::Extract);
+            let src = PathBuf::from(&plugin.source);
+            let dest = dest.to_path_buf();
+            let keep = ctx.keep.map(<[_]>::to_vec);
+            let options = CopyOptions {
+                dereference: plugin.dereference,
+                max_depth: plugin.max_depth,
+            };
+            blocking(move || fetch::copy_dir(&src, &dest, keep.as_deref(), options)).await?;
+            Ok(FetchInfo::default())
+        })
+    }
+}
+
+/// Gzipped tarballs downloaded over HTTP, like GitHub archives
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ArchiveFetcher;
+
+impl Fetcher for ArchiveFetcher {
+    fn handles(&self, plugin: &Plugin) -> bool {
+        plugin.source.starts_with("https://") || plugin.source.starts_with("http://")
+    }
+
+    fn fetch<'a>(
+        &'a self,
+        ctx: &'a FetchContext<'a>,
+        plugin: &'a Plugin,
+        dest: &'a Path,
+    ) -> BoxFuture<'a, Result<FetchInfo>> {
+        Box::pin(async move {
+            let archive = dest.join(ARCHIVE_FILENAME);
+            {
+                let _permit = ctx.host_limiter.acquire(&plugin.source).await;
+                ctx.observer.phase_changed(plugin, Phase::Download);
+                fetch::download(
+                    ctx.client,
+                    &plugin.source,
+                    &archive,
+                    ctx.retries,
+                    |bytes, total| ctx.observer.download_progress(plugin, bytes, total),
+                )
+                .await?;
+            }
+
+            ctx.observer.phase_changed(plugin, Phase::Extract);
+            let url = plugin.source.clone();
+            let dest = dest.to_path_buf();
+            let strip = plugin.strip_components.unwrap_or(DEFAULT_STRIP_COMPONENTS);
+            let keep = ctx.keep.map(<[_]>::to_vec);
+            let digest = plugin.archive_digest.clone();
+            let (archive_checksum, commit_hash) = blocking(move || {
+                let archive_checksum = checksum_file(&archive)?;
+                if let Some(expected) = digest
+                    && expected != archive_checksum
+                {
+                    return Err(FinError::DigestMismatch {
+                        url,
+                        expected,
+                        found: archive_checksum,
+                    });
+                }
+                let commit_hash = fetch::extract(&url, &archive, &dest, strip, keep.as_deref())?;
+                fs::remove_file(&archive).with_path("remove", &archive)?;
+                Ok((archive_checksum, commit_hash))
+            })
+            .await?;
+
+            Ok(FetchInfo {
+                commit_hash,
+                archive_checksum: Some(archive_checksum),
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        progress::NoopObserver,
+        testing::{Entry, Server, tarball},
+    };
+    use std::collections::HashMap;
+    use tempfile::TempDir;
+
+    /// Fetch `plugin` into a fresh staging directory, keeping `functions/`
+    fn fetch_with(fetcher: &dyn Fetcher, plugin: &Plugin) -> (TempDir, Result<FetchInfo>) {
+        let staging = tempfile::tempdir().unwrap();
+        let client = fetch::client().unwrap();
+        let host_limiter = HostLimiter::new(1, HashMap::new());
+        let ctx = FetchContext {
+            client: &client,
+            host_limiter: &host_limiter,
+            retries: 0,
+            observer: &NoopObserver,
+            keep: Some(&["functions"]),
+        };
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let result = runtime.block_on(fetcher.fetch(&ctx, plugin, staging.path()));
+        (staging, result)
+    }
+
+    fn archive_plugin(url: &str) -> Plugin {
+        Plugin::from(format!("{url}/plugin.tar.gz").as_str())
+    }
+
+    fn archive_bytes(entries: &[Entry]) -> Vec<u8> {
+        let dir = tempfile::tempdir().unwrap();
+        fs::read(tarball(dir.path(), entries)).unwrap()
+    }
+
+    #[test]
+    fn each_fetcher_handles_only_its_own_sources() {
+        let local = tempfile::tempdir().unwrap();
+        let local = Plugin::parse(local.path().to_str().unwrap()).unwrap();
+        let github = Plugin::from("jorgebucaran/nvm.fish");
+        let archive = Plugin::from("http://example.com/plugin.tar.gz");
+
+        assert!(LocalFetcher.handles(&local));
+        assert!(!LocalFetcher.handles(&github));
+        assert!(!LocalFetcher.handles(&archive));
+
+        assert!(!ArchiveFetcher.handles(&local));
+        assert!(ArchiveFetcher.handles(&github));
+        assert!(ArchiveFetcher.handles(&archive));
+    }
+
+    #[test]
+    fn local_fetcher_copies_the_kept_directories() {
+        let source = tempfile::tempdir().unwrap();
+        fs::create_dir(source.path().join("functions")).unwrap();
+        fs::write(
+            source.path().join("functions/hello.fish"),
+            "function hello; end",
+        )
+        .unwrap();
+        fs::write(source.path().join("README.md"), "# hello").unwrap();
+        let plugin = Plugin::parse(source.path().to_str().unwrap()).unwrap();
+
+        let (staging, result) = fetch_with(&LocalFetcher, &plugin);
+
+        let info = result.unwrap();
+        assert!(info.commit_hash.is_none() && info.archive_checksum.is_none());
+        let hello = fs::read_to_string(staging.path().join("functions/hello.fish")).unwrap();
+        assert_eq!(hello, "function hello; end");
+        assert!(!staging.path().join("README.md").exists());
+    }
+
+    #[test]
+    fn archive_fetcher_downloads_and_extracts_the_kept_directories() {
+        let body = archive_bytes(&[
+            Entry::File("repo-main/functions/hello.fish", "function hello; end"),
+            Entry::File("repo-main/README.md", "# hello"),
+        ]);
+        let server = Server::start(vec![(200, body.clone())]);
+        let plugin = archive_plugin(&server.url);
+
+        let (staging, result) = fetch_with(&ArchiveFetcher, &plugin);
+
+        let info = result.unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("plugin.tar.gz");
+        fs::write(&archive, &body).unwrap();
+        assert_eq!(
+            info.archive_checksum,
+            Some(checksum_file(&archive).unwrap())
+        );
+        let hello = fs::read_to_string(staging.path().join("functions/hello.fish")).unwrap();
+        assert_eq!(hello, "function hello; end");
+        assert!(!staging.path().join("README.md").exists());
+        assert!(!staging.path().join(ARCHIVE_FILENAME).exists());
+    }
+
+    #[test]
+    fn archive_fetcher_rejects_an_archive_with_the_wrong_digest() {
+        let body = archive_bytes(&[Entry::File("repo-main/functions/hello.fish", "")]);
+        let server = Server::start(vec![(200, body)]);
+        let plugin = Plugin {
+            archive_digest: Some("0000".into()),
+            ..archive_plugin(&server.url)
+        };
+
+        let (staging, result) = fetch_with(&ArchiveFetcher, &plugin);
+
+        assert!(matches!(result, Err(FinError::DigestMismatch { .. })));
+        assert!(!staging.path().join("functions").exists());
+    }
+}
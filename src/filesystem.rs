@@ -0,0 +1,402 @@
+use std::{
+    fs,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+};
+
+/// The filesystem operations fin performs on the Fish configuration
+///
+/// [`Fin`](crate::Fin) goes through this trait instead of `std::fs` so the
+/// install and removal logic can run against something other than the
+/// real home directory.
+pub trait FileSystem: Send + Sync {
+    /// Paths of the entries directly inside `path`
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>>;
+
+    fn is_dir(&self, path: &Path) -> bool;
+
+    /// Open the file at `path` for streaming its contents
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + Send>>;
+
+    /// Size of the file at `path` in bytes
+    fn file_size(&self, path: &Path) -> io::Result<u64>;
+
+    fn copy(&self, from: &Path, to: &Path) -> io::Result<()>;
+
+    fn remove_file(&self, path: &Path) -> io::Result<()>;
+
+    /// Remove the directory at `path`, failing unless it is empty
+    fn remove_dir(&self, path: &Path) -> io::Result<()>;
+
+    /// Remove the directory at `path` with everything in it
+    fn remove_dir_all(&self, path: &Path) -> io::Result<()>;
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()>;
+
+    fn create_dir_all(&self, path: &Path) -> io::Result<()>;
+
+    fn write(&self, path: &Path, contents: &[u8]) -> io::Result<()>;
+
+    /// Replace the file at `path` with `contents` in one step
+    ///
+    /// An interrupted or failed write leaves the previous file intact
+    /// instead of a truncated one.
+    fn write_atomic(&self, path: &Path, contents: &[u8]) -> io::Result<()>;
+}
+
+/// The real filesystem
+#[derive(Debug, Default, Clone, Copy)]
+pub struct StdFs;
+
+impl FileSystem for StdFs {
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+        fs::read_dir(path)?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect()
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        path.is_dir()
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + Send>> {
+        Ok(Box::new(io::BufReader::new(fs::File::open(path)?)))
+    }
+
+    fn file_size(&self, path: &Path) -> io::Result<u64> {
+        fs::metadata(path).map(|m| m.len())
+    }
+
+    /// Clone the file where the filesystem supports it (btrfs, XFS, APFS),
+    /// which shares the data instead of copying it
+    ///
+    /// Falls back to a regular copy, which also covers an existing `to` that
+    /// reflinking refuses to replace.
+    fn copy(&self, from: &Path, to: &Path) -> io::Result<()> {
+        if reflink_copy::reflink(from, to).is_ok() {
+            return Ok(());
+        }
+        fs::copy(from, to).map(|_| ())
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        fs::remove_file(path)
+    }
+
+    fn remove_dir(&self, path: &Path) -> io::Result<()> {
+        fs::remove_dir(path)
+    }
+
+    fn remove_dir_all(&self, path: &Path) -> io::Result<()> {
+        fs::remove_dir_all(path)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        fs::rename(from, to)
+    }
+
+    fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        fs::create_dir_all(path)
+    }
+
+    fn write(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+        fs::write(path, contents)
+    }
+
+    /// Write a temporary file next to `path` and rename it over `path`
+    ///
+    /// A `path` that is a symlink, like a lock file linked from a dotfiles
+    /// repository, stays one and its target is replaced.
+    fn write_atomic(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+        let path = &fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        let dir = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        let mut file = tempfile::Builder::new().prefix(".fin-").tempfile_in(dir)?;
+        file.write_all(contents)?;
+        file.as_file().sync_all()?;
+        // Temporary files are private, the file keeps the permissions it had
+        let permissions = fs::metadata(path)
+            .map(|metadata| metadata.permissions())
+            .ok()
+            .or_else(default_permissions);
+        if let Some(permissions) = permissions {
+            let _ = fs::set_permissions(file.path(), permissions);
+        }
+        file.persist(path).map(|_| ()).map_err(|err| err.error)
+    }
+}
+
+/// Permissions of a new file, readable by everyone like one `fs::write` creates
+#[cfg(unix)]
+fn default_permissions() -> Option<fs::Permissions> {
+    use std::os::unix::fs::PermissionsExt;
+    Some(fs::Permissions::from_mode(0o644))
+}
+
+/// Temporary files are not restricted elsewhere
+#[cfg(not(unix))]
+fn default_permissions() -> Option<fs::Permissions> {
+    None
+}
+
+/// The real filesystem failing below chosen directories, for tests
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub(crate) struct FaultyFs {
+    faults: std::sync::Mutex<Vec<(PathBuf, Fault)>>,
+}
+
+/// How [`FaultyFs`] fails below a directory
+#[cfg(test)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Fault {
+    /// Every change is refused
+    ReadOnly,
+    /// The disk is full, a plain write gets half of its contents out first
+    Full,
+}
+
+#[cfg(test)]
+impl FaultyFs {
+    /// Fail changes to `dir` and anything below it from now on
+    pub(crate) fn fail_below(&self, dir: &Path, fault: Fault) {
+        self.faults.lock().unwrap().push((dir.to_path_buf(), fault));
+    }
+
+    fn fault(&self, path: &Path) -> Option<Fault> {
+        let faults = self.faults.lock().unwrap();
+        faults
+            .iter()
+            .find(|(dir, _)| path.starts_with(dir))
+            .map(|(_, fault)| *fault)
+    }
+
+    fn check(&self, path: &Path) -> io::Result<()> {
+        match self.fault(path) {
+            Some(Fault::ReadOnly) => Err(io::ErrorKind::ReadOnlyFilesystem.into()),
+            Some(Fault::Full) => Err(io::ErrorKind::StorageFull.into()),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+impl FileSystem for FaultyFs {
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+        StdFs.read_dir(path)
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        StdFs.is_dir(path)
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + Send>> {
+        StdFs.open(path)
+    }
+
+    fn file_size(&self, path: &Path) -> io::Result<u64> {
+        StdFs.file_size(path)
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> io::Result<()> {
+        self.check(to)?;
+        StdFs.copy(from, to)
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        self.check(path)?;
+        StdFs.remove_file(path)
+    }
+
+    fn remove_dir(&self, path: &Path) -> io::Result<()> {
+        self.check(path)?;
+        StdFs.remove_dir(path)
+    }
+
+    fn remove_dir_all(&self, path: &Path) -> io::Result<()> {
+        self.check(path)?;
+        StdFs.remove_dir_all(path)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        self.check(from)?;
+        self.check(to)?;
+        StdFs.rename(from, to)
+    }
+
+    fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        // Directories that already exist are fine, like on a read-only disk
+        if !path.is_dir() {
+            self.check(path)?;
+        }
+        StdFs.create_dir_all(path)
+    }
+
+    fn write(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+        if self.fault(path) == Some(Fault::Full) {
+            StdFs.write(path, &contents[..contents.len() / 2])?;
+        }
+        self.check(path)?;
+        StdFs.write(path, contents)
+    }
+
+    fn write_atomic(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+        self.check(path)?;
+        StdFs.write_atomic(path, contents)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Copy every file below `src` to the same place below `dest` with `fs`
+    fn copy_tree(fs: &dyn FileSystem, src: &Path, dest: &Path) {
+        for path in fs.read_dir(src).unwrap() {
+            let target = dest.join(path.file_name().unwrap());
+            if fs.is_dir(&path) {
+                fs.create_dir_all(&target).unwrap();
+                copy_tree(fs, &path, &target);
+            } else {
+                fs.copy(&path, &target).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn copy_keeps_contents_of_a_nested_tree() {
+        let src = tempfile::tempdir().unwrap();
+        let dest = tempfile::tempdir().unwrap();
+        let files: &[(&str, Vec<u8>)] = &[
+            ("functions/hello.fish", b"function hello; end".to_vec()),
+            (
+                "share/deep/nested/data.bin",
+                (0..=255).cycle().take(4 << 20).collect(),
+            ),
+            ("share/empty", Vec::new()),
+        ];
+        for (path, contents) in files {
+            let path = src.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+
+        copy_tree(&StdFs, src.path(), dest.path());
+
+        for (path, contents) in files {
+            assert_eq!(
+                &fs::read(dest.path().join(path)).unwrap(),
+                contents,
+                "{path}"
+            );
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn copy_keeps_permission_bits() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        for (name, mode) in [("script", 0o755), ("private", 0o600), ("plain", 0o644)] {
+            let src = dir.path().join(name);
+            fs::write(&src, name).unwrap();
+            fs::set_permissions(&src, fs::Permissions::from_mode(mode)).unwrap();
+            let dest = dir.path().join(format!("{name}.copy"));
+
+            StdFs.copy(&src, &dest).unwrap();
+
+            let copied = fs::metadata(&dest).unwrap().permissions().mode() & 0o777;
+            assert_eq!(copied, mode, "{name}");
+        }
+    }
+
+    #[test]
+    fn copy_replaces_an_existing_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let (src, dest) = (dir.path().join("new"), dir.path().join("old"));
+        fs::write(&src, "new contents").unwrap();
+        fs::write(&dest, "old contents that are longer").unwrap();
+
+        StdFs.copy(&src, &dest).unwrap();
+
+        assert_eq!(fs::read_to_string(&dest).unwrap(), "new contents");
+    }
+
+    /// Names of the entries in `dir`
+    fn entries(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn write_atomic_replaces_the_file_and_leaves_no_temporary_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("fin-lock.toml");
+        fs::write(&path, "old").unwrap();
+
+        StdFs.write_atomic(&path, b"new").unwrap();
+        StdFs
+            .write_atomic(&dir.path().join("fresh.toml"), b"fresh")
+            .unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        assert_eq!(
+            fs::read_to_string(dir.path().join("fresh.toml")).unwrap(),
+            "fresh"
+        );
+        assert_eq!(entries(dir.path()), ["fin-lock.toml", "fresh.toml"]);
+    }
+
+    #[test]
+    fn write_atomic_fails_without_leaving_anything_behind() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let result = StdFs.write_atomic(&dir.path().join("missing/fin-lock.toml"), b"new");
+
+        assert!(result.is_err());
+        assert!(entries(dir.path()).is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn write_atomic_keeps_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let private = dir.path().join("private.toml");
+        fs::write(&private, "old").unwrap();
+        fs::set_permissions(&private, fs::Permissions::from_mode(0o600)).unwrap();
+        let fresh = dir.path().join("fresh.toml");
+
+        StdFs.write_atomic(&private, b"new").unwrap();
+        StdFs.write_atomic(&fresh, b"new").unwrap();
+
+        let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(&private), 0o600);
+        assert_eq!(mode(&fresh), 0o644);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn write_atomic_replaces_the_target_of_a_symlink() {
+        let dotfiles = tempfile::tempdir().unwrap();
+        let target = dotfiles.path().join("fin-lock.toml");
+        fs::write(&target, "old").unwrap();
+        let config = tempfile::tempdir().unwrap();
+        let link = config.path().join("fin-lock.toml");
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        StdFs.write_atomic(&link, b"new").unwrap();
+
+        assert!(fs::symlink_metadata(&link).unwrap().is_symlink());
+        assert_eq!(fs::read_to_string(&target).unwrap(), "new");
+        assert_eq!(entries(dotfiles.path()), ["fin-lock.toml"]);
+    }
+}
@@ -0,0 +1,169 @@
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    error::{IoResultExt, Result},
+    filesystem::FileSystem,
+};
+
+/// fisher's own name in its plugin list
+pub const FISHER_PLUGIN_NAME: &str = "jorgebucaran/fisher";
+/// fisher's plugin list, in the Fish config directory
+const PLUGINS_FILENAME: &str = "fish_plugins";
+/// Fish's universal variables, where fisher records what it installed
+const VARIABLES_FILENAME: &str = "fish_variables";
+/// fisher's own files, relative to the plugin installation path
+const OWN_FILES: &[&str] = &["functions/fisher.fish", "completions/fisher.fish"];
+/// Separates list elements in `fish_variables`
+const LIST_SEPARATOR: char = '\x1e';
+
+/// What fisher left in a Fish config directory
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct FisherInstall {
+    /// fisher's own function and completion files
+    pub own_files: Vec<PathBuf>,
+    /// `fish_plugins`, when it exists
+    pub plugins_file: Option<PathBuf>,
+    /// Plugins fisher manages besides itself, as listed in `fish_plugins`
+    pub plugins: Vec<String>,
+    /// Files fisher installed for each plugin, when its universal variables say
+    pub plugin_files: HashMap<String, Vec<PathBuf>>,
+}
+
+impl FisherInstall {
+    /// Look for fisher's files, `None` when there are none
+    ///
+    /// `fish_plugins` and `fish_variables` are looked up in
+    /// `fish_config_dir`, fisher's functions and completions in `fin_path`,
+    /// where it installs by default.
+    pub fn detect(
+        fs: &dyn FileSystem,
+        fish_config_dir: &Path,
+        fin_path: &Path,
+    ) -> Result<Option<Self>> {
+        let own_files: Vec<PathBuf> = OWN_FILES
+            .iter()
+            .map(|file| fin_path.join(file))
+            .filter(|path| fs.file_size(path).is_ok())
+            .collect();
+
+        let plugins_path = fish_config_dir.join(PLUGINS_FILENAME);
+        let plugins_file = read_to_string(fs, &plugins_path)?;
+        let variables = read_to_string(fs, &fish_config_dir.join(VARIABLES_FILENAME))?
+            .map(|content| parse_variables(&content))
+            .unwrap_or_default();
+        if own_files.is_empty()
+            && plugins_file.is_none()
+            && !variables.contains_key("_fisher_plugins")
+        {
+            return Ok(None);
+        }
+
+        // fish_plugins is what `fisher update` installs, the variable what it did install
+        let plugins: Vec<String> = match &plugins_file {
+            Some(content) => content
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(String::from)
+                .collect(),
+            None => variables
+                .get("_fisher_plugins")
+                .cloned()
+                .unwrap_or_default(),
+        };
+        let plugins: Vec<String> = plugins
+            .into_iter()
+            .filter(|plugin| !plugin.eq_ignore_ascii_case(FISHER_PLUGIN_NAME))
+            .collect();
+        let plugin_files = plugins
+            .iter()
+            .filter_map(|plugin| {
+                let files = variables.get(&format!("_fisher_{}_files", var_escape(plugin)))?;
+                Some((plugin.clone(), files.iter().map(PathBuf::from).collect()))
+            })
+            .collect();
+
+        Ok(Some(Self {
+            own_files,
+            plugins_file: plugins_file.map(|_| plugins_path),
+            plugins,
+            plugin_files,
+        }))
+    }
+}
+
+fn read_to_string(fs: &dyn FileSystem, path: &Path) -> Result<Option<String>> {
+    let Ok(mut file) = fs.open(path) else {
+        return Ok(None);
+    };
+    let mut content = String::new();
+    file.read_to_string(&mut content).with_path("read", path)?;
+    Ok(Some(content))
+}
+
+/// Universal variables from `SETUVAR name:value` lines of `fish_variables`
+fn parse_variables(content: &str) -> HashMap<String, Vec<String>> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let rest = line.strip_prefix("SETUVAR ")?;
+            let rest = rest.strip_prefix("--export ").unwrap_or(rest);
+            let (name, value) = rest.split_once(':')?;
+            let value = unescape(value);
+            let list = match value.as_str() {
+                "" => Vec::new(),
+                value => value.split(LIST_SEPARATOR).map(String::from).collect(),
+            };
+            Some((name.to_string(), list))
+        })
+        .collect()
+}
+
+/// Undo the `\xNN` and `\\` escapes Fish writes values with
+fn unescape(value: &str) -> String {
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut rest = value.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        rest = tail;
+        if byte != b'\\' {
+            bytes.push(byte);
+            continue;
+        }
+        match rest {
+            [b'x', hi, lo, tail @ ..] => {
+                let hex = [*hi, *lo];
+                match std::str::from_utf8(&hex).map(|hex| u8::from_str_radix(hex, 16)) {
+                    Ok(Ok(decoded)) => {
+                        bytes.push(decoded);
+                        rest = tail;
+                    }
+                    _ => bytes.push(byte),
+                }
+            }
+            [b'\\', tail @ ..] => {
+                bytes.push(b'\\');
+                rest = tail;
+            }
+            _ => bytes.push(byte),
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// Escape like `string escape --style=var`, which fisher names its variables with
+fn var_escape(name: &str) -> String {
+    let mut escaped = String::new();
+    for byte in name.bytes() {
+        if byte.is_ascii_alphanumeric() {
+            escaped.push(byte as char);
+        } else {
+            escaped.push_str(&format!("_{byte:02X}_"));
+        }
+    }
+    escaped
+}
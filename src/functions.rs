@@ -0,0 +1,36 @@
+use std::{collections::BTreeSet, io::Read, path::Path};
+
+use crate::filesystem::FileSystem;
+
+/// Names of the functions a Fish script defines with `function <name>`
+///
+/// Only a line-level scan, not a parser: names built from variables or
+/// command substitutions are skipped, and so is a `function` word inside a
+/// string that happens to start a line.
+pub fn declared_functions(script: &str) -> BTreeSet<String> {
+    script
+        .lines()
+        .filter_map(|line| {
+            let rest = line.trim_start().strip_prefix("function")?;
+            if !rest.starts_with([' ', '\t']) {
+                return None;
+            }
+            let name = rest.split([' ', '\t', ';']).find(|word| !word.is_empty())?;
+            let name = name.trim_matches(['\'', '"']);
+            let computed = name.contains(['$', '(', '{']);
+            (!name.is_empty() && !name.starts_with('-') && !computed).then(|| name.to_string())
+        })
+        .collect()
+}
+
+/// Like [`declared_functions`] for a file, empty when it cannot be read
+pub(crate) fn declared_in_file(fs: &dyn FileSystem, path: &Path) -> BTreeSet<String> {
+    let mut script = String::new();
+    match fs
+        .open(path)
+        .and_then(|mut file| file.read_to_string(&mut script))
+    {
+        Ok(_) => declared_functions(&script),
+        Err(_) => BTreeSet::new(),
+    }
+}
@@ -0,0 +1,413 @@
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use std::{
+    fs,
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+use crate::{
+    error::{FinError, IoResultExt, Result},
+    fetch,
+    lock::Plugin,
+};
+
+const GITHUB_API_URL: &str = "https://api.github.com";
+const GITHUB_ARCHIVE_PREFIX: &str = "https://github.com/";
+/// Ref that resolves to the newest release tag at install time
+pub const LATEST_REF: &str = "latest";
+/// Releases and commits shown when nothing is known about the installed version
+const RECENT_LIMIT: usize = 10;
+/// Cached repository metadata younger than this is used without asking the API
+const REPO_CACHE_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+const REPO_CACHE_DIR: &str = "repos";
+
+/// A published GitHub release
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Release {
+    pub tag_name: String,
+    pub name: Option<String>,
+    pub published_at: Option<String>,
+    pub body: Option<String>,
+}
+
+/// A commit, reduced to what a changelog shows
+#[derive(Debug, Clone, Serialize)]
+pub struct Commit {
+    pub sha: String,
+    pub message: String,
+}
+
+/// What changed upstream since a plugin was installed
+///
+/// `releases` is preferred, `commits` is the fallback for repositories
+/// without releases.
+#[derive(Debug, Default, Serialize)]
+pub struct Changelog {
+    /// The installed commit the changelog starts at, if known
+    pub since: Option<String>,
+    pub releases: Vec<Release>,
+    pub commits: Vec<Commit>,
+}
+
+/// What the hosting API says about a plugin's upstream repository
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "lowercase")]
+pub enum RepoStatus {
+    Found(RepoInfo),
+    /// Deleted, made private or renamed without a redirect
+    Missing,
+}
+
+/// Upstream repository metadata
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RepoInfo {
+    pub stars: u64,
+    /// Pull requests count as issues
+    pub open_issues: u64,
+    pub archived: bool,
+    /// Committer date of the newest commit on the default branch
+    pub last_commit: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ApiRepo {
+    stargazers_count: u64,
+    open_issues_count: u64,
+    #[serde(default)]
+    archived: bool,
+}
+
+#[derive(Deserialize)]
+struct ApiRelease {
+    tag_name: String,
+    #[serde(default)]
+    draft: bool,
+    #[serde(default)]
+    prerelease: bool,
+}
+
+#[derive(Deserialize)]
+struct ApiCommit {
+    sha: String,
+    commit: ApiCommitDetail,
+}
+
+#[derive(Deserialize)]
+struct ApiCommitDetail {
+    message: String,
+    committer: Option<ApiSignature>,
+}
+
+#[derive(Deserialize)]
+struct ApiSignature {
+    date: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Comparison {
+    base_commit: ApiCommit,
+    commits: Vec<ApiCommit>,
+}
+
+impl From<ApiCommit> for Commit {
+    fn from(commit: ApiCommit) -> Self {
+        Self {
+            sha: commit.sha,
+            message: commit.commit.message,
+        }
+    }
+}
+
+/// `owner/repo` and the ref of a plugin installed from a GitHub archive
+pub fn repo_and_ref(plugin: &Plugin) -> Option<(&str, &str)> {
+    let path = plugin.source.strip_prefix(GITHUB_ARCHIVE_PREFIX)?;
+    let (repo, archive) = path.split_once("/archive/")?;
+    let ref_name = archive.strip_suffix(".tar.gz")?;
+    Some((repo, ref_name))
+}
+
+/// Whether a GitHub plugin's ref is a commit hash, which never moves
+pub fn is_pinned(plugin: &Plugin) -> bool {
+    repo_and_ref(plugin).is_some_and(|(_, ref_name)| {
+        (7..=40).contains(&ref_name.len()) && ref_name.bytes().all(|b| b.is_ascii_hexdigit())
+    })
+}
+
+/// Source URL of the archive of `repo` at `ref_name`
+pub fn archive_url(repo: &str, ref_name: &str) -> String {
+    format!("{GITHUB_ARCHIVE_PREFIX}{repo}/archive/{ref_name}.tar.gz")
+}
+
+/// `v1.2.3` or `1.2` as comparable numbers, `None` for tags that are not versions
+///
+/// Pre-release versions like `2.0.0-rc.1` do not count, build metadata
+/// after `+` is ignored as it has no precedence.
+fn version(tag: &str) -> Option<Vec<u64>> {
+    let version = tag.strip_prefix(['v', 'V']).unwrap_or(tag);
+    let version = version.split('+').next()?;
+    if version.contains('-') {
+        return None;
+    }
+    version.split('.').map(|part| part.parse().ok()).collect()
+}
+
+/// Read-only client for the GitHub REST API
+///
+/// Requests are authenticated with `GITHUB_TOKEN` when set, which raises
+/// the rate limit.
+#[derive(Debug, Clone)]
+pub struct GitHub {
+    api_url: String,
+    token: Option<String>,
+}
+
+impl Default for GitHub {
+    fn default() -> Self {
+        Self {
+            api_url: GITHUB_API_URL.to_string(),
+            token: std::env::var("GITHUB_TOKEN").ok().filter(|t| !t.is_empty()),
+        }
+    }
+}
+
+impl GitHub {
+    /// Releases and commits of `plugin` newer than its installed commit
+    ///
+    /// Without a recorded commit the most recent ones are returned. Falls
+    /// back to commit messages when the repository has no matching releases.
+    pub fn changelog(&self, plugin: &Plugin) -> Result<Changelog> {
+        let Some((repo, ref_name)) = repo_and_ref(plugin) else {
+            return Err(FinError::Fetch {
+                url: plugin.source.clone(),
+                reason: "changelogs are only available for GitHub plugins".to_string(),
+            });
+        };
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(FinError::Runtime)?;
+        let client = fetch::client()?;
+
+        runtime.block_on(async {
+            let mut changelog = Changelog {
+                since: plugin.commit_hash.clone(),
+                ..Default::default()
+            };
+            let mut releases: Vec<Release> = self
+                .get(&client, &format!("/repos/{repo}/releases?per_page=100"))
+                .await?;
+
+            if let Some(since) = &plugin.commit_hash {
+                let comparison: Comparison = self
+                    .get(
+                        &client,
+                        &format!("/repos/{repo}/compare/{since}...{ref_name}"),
+                    )
+                    .await?;
+                let installed_at = comparison.base_commit.commit.committer.and_then(|c| c.date);
+                // RFC 3339 timestamps in UTC compare correctly as strings
+                releases.retain(|r| r.published_at > installed_at);
+                changelog.commits = comparison.commits.into_iter().map(Commit::from).collect();
+            } else {
+                releases.truncate(RECENT_LIMIT);
+                if releases.is_empty() {
+                    let commits: Vec<ApiCommit> = self
+                        .get(
+                            &client,
+                            &format!(
+                                "/repos/{repo}/commits?sha={ref_name}&per_page={RECENT_LIMIT}"
+                            ),
+                        )
+                        .await?;
+                    changelog.commits = commits.into_iter().map(Commit::from).collect();
+                }
+            }
+
+            if !releases.is_empty() {
+                changelog.commits.clear();
+            }
+            changelog.releases = releases;
+            Ok(changelog)
+        })
+    }
+
+    /// The commit each plugin's ref currently points to upstream
+    ///
+    /// Looked up concurrently, one result per plugin in the same order.
+    pub fn latest_commits(&self, plugins: &[&Plugin]) -> Result<Vec<Result<String>>> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(FinError::Runtime)?;
+        let client = fetch::client()?;
+
+        let lookups = plugins.iter().map(|plugin| {
+            let client = &client;
+            async move {
+                let Some((repo, ref_name)) = repo_and_ref(plugin) else {
+                    return Err(FinError::Fetch {
+                        url: plugin.source.clone(),
+                        reason: "only GitHub plugins can be checked for updates".to_string(),
+                    });
+                };
+                let commit: ApiCommit = self
+                    .get(client, &format!("/repos/{repo}/commits/{ref_name}"))
+                    .await?;
+                Ok(commit.sha)
+            }
+        });
+        Ok(runtime.block_on(futures_util::future::join_all(lookups)))
+    }
+
+    /// Stars, open issues, archived status and last commit of `repo`
+    ///
+    /// Answers are cached for a day under the user cache directory, a
+    /// repository that no longer exists included.
+    pub fn repo_status(&self, repo: &str) -> Result<RepoStatus> {
+        let cache_path = dirs::cache_dir().map(|dir| {
+            dir.join("fin")
+                .join(REPO_CACHE_DIR)
+                .join(format!("{}.json", repo.replace('/', "__")))
+        });
+        if let Some(status) = cache_path.as_deref().and_then(read_repo_cache) {
+            return Ok(status);
+        }
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(FinError::Runtime)?;
+        let client = fetch::client()?;
+        let status = runtime.block_on(async {
+            let Some(api_repo) = self
+                .find::<ApiRepo>(&client, &format!("/repos/{repo}"))
+                .await?
+            else {
+                return Ok(RepoStatus::Missing);
+            };
+            // An empty repository has no commits to list
+            let commits: Vec<ApiCommit> = self
+                .find(&client, &format!("/repos/{repo}/commits?per_page=1"))
+                .await?
+                .unwrap_or_default();
+            let last_commit = commits
+                .into_iter()
+                .next()
+                .and_then(|c| c.commit.committer)
+                .and_then(|c| c.date);
+            Ok::<_, FinError>(RepoStatus::Found(RepoInfo {
+                stars: api_repo.stargazers_count,
+                open_issues: api_repo.open_issues_count,
+                archived: api_repo.archived,
+                last_commit,
+            }))
+        })?;
+
+        if let (Some(path), Ok(body)) = (&cache_path, serde_json::to_string(&status)) {
+            // A cache that cannot be written only costs a request next time
+            let _ = write_repo_cache(path, &body);
+        }
+        Ok(status)
+    }
+
+    /// The newest release tag of `repo` by version number and the commit it points to
+    ///
+    /// Drafts, prereleases and tags that are not version numbers are
+    /// skipped, so the most recently published release is not necessarily
+    /// the newest.
+    pub(crate) async fn newest_release(
+        &self,
+        client: &reqwest::Client,
+        repo: &str,
+    ) -> Result<(String, String)> {
+        let releases: Vec<ApiRelease> = self
+            .get(client, &format!("/repos/{repo}/releases?per_page=100"))
+            .await?;
+        let tag = releases
+            .into_iter()
+            .filter(|release| !release.draft && !release.prerelease)
+            .filter_map(|release| Some((version(&release.tag_name)?, release.tag_name)))
+            .max_by(|a, b| a.0.cmp(&b.0))
+            .map(|(_, tag)| tag)
+            .ok_or_else(|| FinError::NoReleases {
+                repo: repo.to_string(),
+            })?;
+        let commit: ApiCommit = self
+            .get(client, &format!("/repos/{repo}/commits/{tag}"))
+            .await?;
+        Ok((tag, commit.sha))
+    }
+
+    /// The commit `ref_name` of `repo` points to
+    pub(crate) async fn commit(
+        &self,
+        client: &reqwest::Client,
+        repo: &str,
+        ref_name: &str,
+    ) -> Result<String> {
+        let commit: ApiCommit = self
+            .get(client, &format!("/repos/{repo}/commits/{ref_name}"))
+            .await?;
+        Ok(commit.sha)
+    }
+
+    async fn get<T: DeserializeOwned>(&self, client: &reqwest::Client, path: &str) -> Result<T> {
+        self.find(client, path)
+            .await?
+            .ok_or_else(|| FinError::Fetch {
+                url: format!("{}{path}", self.api_url),
+                reason: format!("HTTP {}", reqwest::StatusCode::NOT_FOUND),
+            })
+    }
+
+    /// Like [`GitHub::get`], `None` when the API answers 404 Not Found
+    async fn find<T: DeserializeOwned>(
+        &self,
+        client: &reqwest::Client,
+        path: &str,
+    ) -> Result<Option<T>> {
+        let url = format!("{}{path}", self.api_url);
+        let error = |reason: String| FinError::Fetch {
+            url: url.clone(),
+            reason,
+        };
+
+        let mut request = client
+            .get(&url)
+            .header("Accept", "application/vnd.github+json");
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+        let response = request.send().await.map_err(|e| error(e.to_string()))?;
+        let status = response.status();
+        if status == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !status.is_success() {
+            return Err(error(format!("HTTP {status}")));
+        }
+
+        let body = response.text().await.map_err(|e| error(e.to_string()))?;
+        serde_json::from_str(&body).map_err(|e| error(e.to_string()))
+    }
+}
+
+/// The cached metadata at `path`, if present, parseable and fresh
+fn read_repo_cache(path: &Path) -> Option<RepoStatus> {
+    let modified = fs::metadata(path).and_then(|m| m.modified()).ok()?;
+    let age = SystemTime::now()
+        .duration_since(modified)
+        .unwrap_or_default();
+    if age > REPO_CACHE_MAX_AGE {
+        return None;
+    }
+    serde_json::from_str(&fs::read_to_string(path).ok()?).ok()
+}
+
+fn write_repo_cache(path: &Path, body: &str) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).with_path("create", parent)?;
+    }
+    fs::write(path, body).with_path("write", path)
+}
@@ -0,0 +1,56 @@
+//! A fast, lightweight plugin manager for Fish shell
+//!
+//! [`Fin`] drives installs, removals and updates against a Fish
+//! configuration directory and records them in a [`LockFile`].
+//!
+//! ```no_run
+//! use fin::{Config, Fin, InstallOptions};
+//!
+//! let mut fin = Fin::new(None, Config::load()?)?;
+//! let report = fin.install(Some(vec!["jethrokuan/z".into()]), InstallOptions::default())?;
+//! for plugin in &report.installed {
+//!     println!("Installed: {}", plugin.name);
+//! }
+//! # Ok::<(), fin::FinError>(())
+//! ```
+
+pub mod blocklist;
+pub mod checksum;
+pub mod config;
+pub mod core;
+mod diff;
+pub mod error;
+pub mod events;
+mod fetch;
+pub mod fetcher;
+pub mod filesystem;
+pub mod fisher;
+pub mod functions;
+pub mod github;
+pub mod license;
+pub mod limit;
+pub mod lock;
+pub mod manifest;
+pub mod progress;
+pub mod prompt;
+pub mod registry;
+pub mod report;
+pub mod runner;
+#[cfg(test)]
+mod testing;
+
+pub use self::config::Config;
+pub use self::core::{Fin, InstallOptions, NameMatch, OnConflict, RemoveOptions};
+pub use self::error::FinError;
+pub use self::fetcher::{FetchContext, FetchInfo, Fetcher};
+pub use self::filesystem::{FileSystem, StdFs};
+pub use self::fisher::FisherInstall;
+pub use self::lock::{ChecksumScope, LockFile, LockFormat, Plugin, Plugins};
+pub use self::progress::{Phase, ProgressObserver, Warning};
+pub use self::report::{
+    Deprecation, DiskUsage, DoctorReport, Drift, DuplicateFunction, FileInventory, FileState,
+    HookRun, HooksReport, InstallReport, Inventory, LicenseInfo, LockInventory, Outdated,
+    OutdatedReport, PluginInventory, PluginState, PreviewReport, RemoveReport, ReplaceReport,
+    StatusReport, Theme, UpdatePreview, UpdateReport, VerifyReport,
+};
+pub use self::runner::{CommandRunner, SystemRunner};
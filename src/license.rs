@@ -0,0 +1,83 @@
+use std::{io::Read, path::Path};
+
+use crate::filesystem::FileSystem;
+
+/// Files at the root of a plugin that hold its license, most common first
+pub const LICENSE_FILENAMES: &[&str] = &[
+    "LICENSE",
+    "LICENSE.md",
+    "LICENSE.txt",
+    "LICENCE",
+    "LICENCE.md",
+    "COPYING",
+    "COPYING.md",
+    "COPYING.txt",
+    "License.md",
+    "license",
+    "license.md",
+];
+
+/// Phrases that identify a license, checked in order, with its SPDX identifier
+///
+/// More specific texts come before the ones they contain, like the LGPL
+/// before the GPL it amends.
+const SIGNATURES: &[(&str, &[&str])] = &[
+    (
+        "AGPL-3.0",
+        &["gnu affero general public license", "version 3"],
+    ),
+    (
+        "LGPL-3.0",
+        &["gnu lesser general public license", "version 3"],
+    ),
+    (
+        "LGPL-2.1",
+        &["gnu lesser general public license", "version 2.1"],
+    ),
+    ("GPL-3.0", &["gnu general public license", "version 3"]),
+    ("GPL-2.0", &["gnu general public license", "version 2"]),
+    ("Apache-2.0", &["apache license", "version 2.0"]),
+    ("MPL-2.0", &["mozilla public license", "2.0"]),
+    ("Unlicense", &["this is free and unencumbered software"]),
+    ("WTFPL", &["do what the fuck you want to"]),
+    (
+        "ISC",
+        &["permission to use, copy, modify, and/or distribute"],
+    ),
+    ("MIT", &["permission is hereby granted, free of charge"]),
+    (
+        "BSD-3-Clause",
+        &[
+            "redistribution and use in source and binary forms",
+            "neither the name",
+        ],
+    ),
+    (
+        "BSD-2-Clause",
+        &["redistribution and use in source and binary forms"],
+    ),
+];
+
+/// The SPDX identifier of a well-known license `text`, `None` for anything else
+///
+/// A phrase match, not a legal analysis: a modified license still counts
+/// as the one it started from.
+pub fn identify(text: &str) -> Option<&'static str> {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    let text = text.to_lowercase();
+    SIGNATURES
+        .iter()
+        .find(|(_, phrases)| phrases.iter().all(|phrase| text.contains(phrase)))
+        .map(|(id, _)| *id)
+}
+
+/// The text of the first license file at the root of the plugin in `dir`
+pub(crate) fn read(fs: &dyn FileSystem, dir: &Path) -> Option<String> {
+    LICENSE_FILENAMES.iter().find_map(|name| {
+        let mut text = String::new();
+        fs.open(&dir.join(name))
+            .and_then(|mut file| file.read_to_string(&mut text))
+            .ok()?;
+        Some(text)
+    })
+}
@@ -0,0 +1,64 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Default number of simultaneous downloads from a single host
+pub const DEFAULT_HOST_JOBS: usize = 4;
+
+/// Caps concurrent downloads per host
+///
+/// Parallel installs spread over many hosts freely, while a single host
+/// (usually GitHub) only sees a bounded number of requests at once.
+#[derive(Debug)]
+pub struct HostLimiter {
+    default_limit: usize,
+    limits: HashMap<String, usize>,
+    semaphores: Mutex<HashMap<String, Arc<Semaphore>>>,
+}
+
+impl HostLimiter {
+    pub fn new(default_limit: usize, limits: HashMap<String, usize>) -> Self {
+        Self {
+            default_limit,
+            limits,
+            semaphores: Mutex::default(),
+        }
+    }
+
+    /// Wait until a download slot for the host of `url` is free
+    ///
+    /// Sources without a host (local paths) are never limited. The slot is
+    /// released when the returned permit is dropped.
+    pub async fn acquire(&self, url: &str) -> Option<OwnedSemaphorePermit> {
+        let host = host(url)?;
+        let semaphore = {
+            let mut semaphores = self.semaphores.lock().unwrap();
+            semaphores
+                .entry(host.to_string())
+                .or_insert_with(|| {
+                    let limit = self.limits.get(host).copied();
+                    Arc::new(Semaphore::new(limit.unwrap_or(self.default_limit).max(1)))
+                })
+                .clone()
+        };
+
+        semaphore.acquire_owned().await.ok()
+    }
+}
+
+impl Default for HostLimiter {
+    fn default() -> Self {
+        Self::new(DEFAULT_HOST_JOBS, HashMap::new())
+    }
+}
+
+/// The host part of a URL, `None` for anything that is not a URL
+pub fn host(url: &str) -> Option<&str> {
+    let (_, rest) = url.split_once("://")?;
+    let authority = rest.split('/').next()?;
+    // Drop credentials and port
+    let host = authority.rsplit('@').next()?;
+    host.split(':').next().filter(|h| !h.is_empty())
+}
@@ -1,52 +1,355 @@
 use chrono::{DateTime, Utc};
-use serde::{Deserialize, Serialize, Serializer};
-use std::{collections::HashSet, fs, path::PathBuf};
+use indexmap::IndexMap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use crate::{
+    error::{FinError, IoResultExt, Result},
+    github::{self, LATEST_REF},
+};
 
 #[derive(Debug, Serialize, Deserialize, Default, Clone, Eq)]
 pub struct Plugin {
     pub name: String,
     pub source: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub commit_hash: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub branch: Option<String>,
-    #[serde(serialize_with = "serialize_option_hashset_sorted")]
+    #[serde(
+        serialize_with = "serialize_option_hashset_sorted",
+        skip_serializing_if = "Option::is_none"
+    )]
     pub installed_files: Option<HashSet<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub checksum: Option<String>,
+    /// What `checksum` covers, the installed files when unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub checksum_scope: Option<ChecksumScope>,
+    /// Checksum the downloaded archive must have, checked before extracting it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub archive_digest: Option<String>,
+    /// Leading path components dropped when extracting, only recorded when not 1
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub strip_components: Option<usize>,
+    /// `conf.d` snippets are installed with a `.disabled` suffix so Fish skips them
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub confd_disabled: bool,
+    /// Wins file conflicts against plugins with a lower priority, 0 when unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub priority: Option<i32>,
+    /// Files this plugin ships that another plugin's copy overrides
+    ///
+    /// Kept aside and put back when the overriding plugin is removed.
+    #[serde(
+        serialize_with = "serialize_option_hashset_sorted",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub shadowed_files: Option<HashSet<String>>,
+    /// One-line summary from the plugin's `fin.toml`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub homepage: Option<String>,
+    /// Fish code from the plugin's `fin.toml` run before its files are removed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uninstall_hook: Option<String>,
+    /// Build command from the plugin's `fin.toml`, run before its files are copied
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub build: Option<String>,
+    /// Program the build produces, installed to fin's `bin` directory
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub binary: Option<String>,
+    /// Oldest Fish version the plugin works with, from its `fin.toml`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_fish_version: Option<String>,
+    /// SPDX identifier of the license file the plugin ships, when recognized
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub license: Option<String>,
+    /// The user allowed the build command to run
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub allow_scripts: bool,
+    /// Number the `conf.d` file names start with, Fish sources them in that order
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub load_order: Option<u8>,
+    /// Symlinks in a local plugin are installed as copies of their targets
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub dereference: bool,
+    /// Directory levels below a local plugin's root that are copied, unlimited when unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_depth: Option<usize>,
+    /// Allowed to install completions that replace ones Fish ships
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub shadow_completions: bool,
+    /// The whole archive is installed unstripped into a directory of its own,
+    /// Fish does not load anything from it
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub raw: bool,
+    /// Requested as `@latest`, every install and update resolves the newest release tag
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub latest: bool,
 }
 
+/// Two entries are the same install when name and commit match
 impl PartialEq for Plugin {
     fn eq(&self, other: &Self) -> bool {
         self.name == other.name && self.commit_hash == other.commit_hash
     }
 }
 
-impl std::hash::Hash for Plugin {
-    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-        self.name.hash(state);
+/// Lock file entries keyed by normalized plugin name
+///
+/// There is at most one entry per name, inserting replaces it. Entries
+/// keep insertion order in memory and are written sorted by name.
+#[derive(Debug, Default, Clone)]
+pub struct Plugins {
+    entries: IndexMap<String, Plugin>,
+    /// Duplicate entries collapsed while reading
+    duplicates: usize,
+}
+
+impl Plugins {
+    /// The name entries are keyed by, the same for every spelling of one plugin
+    ///
+    /// Forge names are case insensitive, `Owner/Repo` and `owner/repo` are
+    /// one plugin. Local directories are named by their path and archives by
+    /// their file, which case sensitive filesystems and servers tell apart.
+    pub(crate) fn key(name: &str) -> String {
+        let forge =
+            !name.starts_with('/') && !name.contains(['\\', ':']) && name.matches('/').count() == 1;
+        if forge {
+            name.to_ascii_lowercase()
+        } else {
+            name.to_string()
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Plugin> {
+        self.entries.get(&Self::key(name))
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Plugin> {
+        self.entries.get_mut(&Self::key(name))
+    }
+
+    /// Add or replace the entry for `plugin.name`, returning the replaced one
+    pub fn insert(&mut self, plugin: Plugin) -> Option<Plugin> {
+        self.entries.insert(Self::key(&plugin.name), plugin)
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Plugin> {
+        self.entries.shift_remove(&Self::key(name))
+    }
+
+    /// Whether the same plugin is recorded at the same commit
+    pub fn contains(&self, plugin: &Plugin) -> bool {
+        self.get(&plugin.name) == Some(plugin)
+    }
+
+    pub fn contains_name(&self, name: &str) -> bool {
+        self.entries.contains_key(&Self::key(name))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Plugin> {
+        self.entries.values()
+    }
+
+    pub fn retain(&mut self, mut keep: impl FnMut(&Plugin) -> bool) {
+        self.entries.retain(|_, plugin| keep(plugin));
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Entries sorted by name, the order they are written in
+    pub fn sorted(&self) -> Vec<&Plugin> {
+        let mut plugins: Vec<_> = self.iter().collect();
+        plugins.sort_by(|a, b| a.name.cmp(&b.name));
+        plugins
+    }
+
+    /// Add an entry read from a file, keeping the more complete of two duplicates
+    ///
+    /// Of equally complete duplicates the first one read is kept.
+    fn insert_read(&mut self, plugin: Plugin) {
+        let kept = self.get(&plugin.name).map(Plugin::completeness);
+        if kept.is_some() {
+            self.duplicates += 1;
+        }
+        if kept.is_none_or(|kept| kept < plugin.completeness()) {
+            self.insert(plugin);
+        }
+    }
+}
+
+impl Extend<Plugin> for Plugins {
+    fn extend<I: IntoIterator<Item = Plugin>>(&mut self, plugins: I) {
+        for plugin in plugins {
+            self.insert(plugin);
+        }
+    }
+}
+
+impl FromIterator<Plugin> for Plugins {
+    fn from_iter<I: IntoIterator<Item = Plugin>>(plugins: I) -> Self {
+        let mut collected = Self::default();
+        collected.extend(plugins);
+        collected
+    }
+}
+
+impl Serialize for Plugins {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.sorted().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Plugins {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut plugins = Self::default();
+        for plugin in Vec::<Plugin>::deserialize(deserializer)? {
+            plugins.insert_read(plugin);
+        }
+        Ok(plugins)
     }
 }
 
-pub trait PluginVecExt {
-    fn diff_mut(&mut self, other: &HashSet<Plugin>);
+/// Builds the source URL of the archive of a repo at a ref
+type ArchiveUrl = fn(&str, &str) -> String;
+
+/// Spec prefixes naming the host of a repo, GitHub when there is none
+const FORGES: &[(&str, ArchiveUrl)] = &[
+    ("github:", github::archive_url),
+    ("gitlab:", gitlab_archive_url),
+    ("bitbucket:", bitbucket_archive_url),
+];
+
+/// Source URL of the GitLab archive of `repo` at `ref_name`
+fn gitlab_archive_url(repo: &str, ref_name: &str) -> String {
+    let name = repo.rsplit('/').next().unwrap_or(repo);
+    format!("https://gitlab.com/{repo}/-/archive/{ref_name}/{name}-{ref_name}.tar.gz")
 }
 
-impl PluginVecExt for HashSet<Plugin> {
-    fn diff_mut(&mut self, other: &HashSet<Plugin>) {
-        let other_plugins: HashSet<_> = other.iter().collect();
-        self.retain(|p| !other_plugins.contains(p));
+/// Source URL of the Bitbucket archive of `repo` at `ref_name`
+fn bitbucket_archive_url(repo: &str, ref_name: &str) -> String {
+    format!("https://bitbucket.org/{repo}/get/{ref_name}.tar.gz")
+}
+
+/// Extensions of the archives a plugin URL can point to directly
+const ARCHIVE_EXTENSIONS: &[&str] = &[".tar.gz", ".tgz"];
+
+/// The name of a plugin at archive `url`, its file name without the extension
+///
+/// `None` when `url` is not an HTTP URL of an archive.
+fn archive_name(url: &str) -> Option<&str> {
+    if !url.starts_with("https://") && !url.starts_with("http://") {
+        return None;
+    }
+    let path = url.split(['?', '#']).next()?;
+    let file = path.rsplit('/').next()?;
+    ARCHIVE_EXTENSIONS
+        .iter()
+        .find_map(|ext| file.strip_suffix(ext))
+        .filter(|name| !name.is_empty())
+}
+
+/// `spec` without its host prefix, and how to build archive URLs on that host
+fn split_forge(spec: &str) -> (&str, ArchiveUrl) {
+    FORGES
+        .iter()
+        .find_map(|(prefix, url)| Some((spec.strip_prefix(prefix)?, *url)))
+        .unwrap_or((spec, github::archive_url))
+}
+
+impl Plugin {
+    /// Parse an `owner/repo[@ref]` spec or a local directory, rejecting malformed input
+    ///
+    /// Specs starting with `/`, `.` or `~` are local directories, named by
+    /// their absolute path. A `gitlab:` or `bitbucket:` prefix installs from
+    /// that host instead of GitHub, the name leaves the prefix out. An
+    /// `http(s)://` URL of a `.tar.gz` archive is downloaded as it is.
+    pub fn parse(spec: &str) -> Result<Self> {
+        let invalid = |reason| FinError::InvalidSpec {
+            spec: spec.to_string(),
+            reason,
+        };
+
+        if spec.starts_with("https://") || spec.starts_with("http://") {
+            return match archive_name(spec) {
+                Some(_) => Ok(Self::from(spec)),
+                None => Err(invalid("expected the URL of a .tar.gz or .tgz archive")),
+            };
+        }
+
+        if spec.starts_with(['/', '.', '~']) {
+            let path = match spec.strip_prefix('~') {
+                Some(rest) => dirs::home_dir()
+                    .ok_or(FinError::NoHomeDir)?
+                    .join(rest.trim_start_matches('/')),
+                None => PathBuf::from(spec),
+            };
+            let path = path
+                .canonicalize()
+                .map_err(|_| invalid("no such directory"))?;
+            if !path.is_dir() {
+                return Err(invalid("not a directory"));
+            }
+            let path = path.to_string_lossy().to_string();
+            return Ok(Self {
+                name: path.clone(),
+                source: path,
+                ..Default::default()
+            });
+        }
+
+        let (bare, _) = split_forge(spec);
+        let (repo, ref_name) = match bare.split_once('@') {
+            Some((repo, ref_name)) => (repo, Some(ref_name)),
+            None => (bare, None),
+        };
+        match repo.split_once('/') {
+            Some((owner, name)) if !owner.is_empty() && !name.is_empty() && !name.contains('/') => {
+            }
+            _ => return Err(invalid("expected owner/repo")),
+        }
+        if ref_name.is_some_and(|r| r.is_empty() || r.contains('@')) {
+            return Err(invalid("expected a single ref after @"));
+        }
+
+        Ok(Self::from(spec))
     }
 }
 
 impl From<&str> for Plugin {
     fn from(s: &str) -> Self {
+        if let Some(name) = archive_name(s) {
+            return Self {
+                name: name.to_string(),
+                source: s.to_string(),
+                ..Default::default()
+            };
+        }
+        let (s, archive_url) = split_forge(s);
         let mut parts = s.split('@');
         let repo = parts.next().unwrap_or("");
         let ref_name = parts.next().unwrap_or("HEAD");
 
-        let source: String = format!("https://github.com/{repo}/archive/{ref_name}.tar.gz");
-
         Self {
             name: String::from(repo),
-            source,
+            source: archive_url(repo, ref_name),
+            latest: ref_name == LATEST_REF,
             ..Default::default()
         }
     }
@@ -69,18 +372,43 @@ where
     }
 }
 
+impl Plugin {
+    /// Last component of the name, the repository name for `owner/repo`
+    pub fn basename(&self) -> &str {
+        self.name.rsplit('/').next().unwrap_or(&self.name)
+    }
+
+    /// What the installed `conf.d` file names start with, like `30_` for load order 30
+    pub fn confd_prefix(&self) -> Option<String> {
+        self.load_order.map(|order| format!("{order:02}_"))
+    }
+
+    /// Whether the plugin is installed from a local directory instead of downloaded
+    pub fn is_local(&self) -> bool {
+        !self.source.contains("://")
+    }
+
+    /// How much recorded information this entry carries
+    fn completeness(&self) -> usize {
+        [&self.commit_hash, &self.branch, &self.checksum]
+            .iter()
+            .filter(|field| field.is_some())
+            .count()
+            + self.installed_files.as_ref().map_or(0, HashSet::len)
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct LockFile {
     pub version: String,
     pub generated_at: DateTime<Utc>,
-    pub plugins: HashSet<Plugin>,
+    pub plugins: Plugins,
 }
 
 impl LockFile {
-    pub fn load(path: &PathBuf) -> anyhow::Result<Self> {
-        if let Ok(content) = fs::read_to_string(path) {
-            let lock: LockFile = toml::from_str(&content)?;
-            return Ok(lock);
+    pub fn load(path: &PathBuf) -> Result<Self> {
+        if path.exists() {
+            return Self::read(path);
         }
 
         // First install
@@ -88,13 +416,269 @@ impl LockFile {
         Ok(LockFile {
             version: String::from("1.0"),
             generated_at: Utc::now(),
-            plugins: HashSet::new(),
+            plugins: Plugins::default(),
+        })
+    }
+
+    /// Read a lock file that must exist, in either format
+    pub fn read(path: &PathBuf) -> Result<Self> {
+        let content = fs::read_to_string(path).with_path("read", path)?;
+        Self::parse(&content, LockFormat::detect(path, &content)).map_err(|source| {
+            FinError::LockParse {
+                path: path.clone(),
+                source,
+            }
+        })
+    }
+
+    pub(crate) fn parse(content: &str, format: LockFormat) -> Result<Self, BoxError> {
+        Ok(match format {
+            LockFormat::Toml => toml::from_str(content)?,
+            LockFormat::Json => serde_json::from_str(content)?,
+        })
+    }
+
+    /// A copy without machine-local data, suitable for sharing
+    ///
+    /// Only what is needed to reproduce an install is kept, installed and
+    /// shadowed file lists differ per machine and are dropped.
+    pub fn to_portable(&self) -> Self {
+        Self {
+            version: self.version.clone(),
+            generated_at: Utc::now(),
+            plugins: self
+                .plugins
+                .iter()
+                .map(|plugin| Plugin {
+                    installed_files: None,
+                    shadowed_files: None,
+                    ..plugin.clone()
+                })
+                .collect(),
+        }
+    }
+
+    pub fn to_toml_string(&self) -> Result<String> {
+        self.to_string_as(LockFormat::Toml)
+    }
+
+    /// The lock file written out in `format`
+    pub fn to_string_as(&self, format: LockFormat) -> Result<String> {
+        let content = match format {
+            LockFormat::Toml => toml::to_string_pretty(&self).map_err(BoxError::from),
+            LockFormat::Json => serde_json::to_string_pretty(&self)
+                .map(|json| json + "\n")
+                .map_err(BoxError::from),
+        };
+        content.map_err(FinError::LockSerialize)
+    }
+
+    /// Number of entries read for a plugin that already had one, see [`Self::dedup`]
+    pub fn duplicates(&self) -> usize {
+        self.plugins.duplicates
+    }
+
+    /// Drop the duplicate entries older versions left in the file
+    ///
+    /// Duplicates are already collapsed into the most complete entry while
+    /// reading, saving the lock file writes that out. Returns the number of
+    /// dropped entries.
+    pub fn dedup(&mut self) -> usize {
+        std::mem::take(&mut self.plugins.duplicates)
+    }
+}
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// What a plugin's checksum is computed over
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChecksumScope {
+    /// Names and contents of the installed files, stable when an archive is
+    /// repackaged and checkable offline
+    #[default]
+    Content,
+    /// Bytes of the downloaded archive, changes with any repackaging
+    Tarball,
+}
+
+/// How the lock file is serialized
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LockFormat {
+    #[default]
+    Toml,
+    Json,
+}
+
+impl LockFormat {
+    /// Name of the lock file in the Fish configuration directory
+    pub fn filename(self) -> &'static str {
+        match self {
+            Self::Toml => "fin-lock.toml",
+            Self::Json => "fin-lock.json",
+        }
+    }
+
+    /// The format going by the extension of `path`, `None` for other extensions
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "toml" => Some(Self::Toml),
+            "json" => Some(Self::Json),
+            _ => None,
+        }
+    }
+
+    /// The format of a lock file, sniffing `content` when the extension does not tell
+    fn detect(path: &Path, content: &str) -> Self {
+        Self::from_path(path).unwrap_or(if content.trim_start().starts_with('{') {
+            Self::Json
+        } else {
+            Self::Toml
         })
     }
+}
+
+impl FromStr for LockFormat {
+    type Err = FinError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "toml" => Ok(Self::Toml),
+            "json" => Ok(Self::Json),
+            _ => Err(FinError::InvalidLockFormat(s.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn named(name: &str) -> Plugin {
+        Plugin {
+            name: name.to_string(),
+            source: name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn forge_names_are_one_plugin_in_any_case() {
+        let mut plugins = Plugins::default();
+        plugins.insert(named("PatrickF1/fzf.fish"));
+
+        assert!(plugins.contains_name("patrickf1/FZF.fish"));
+        assert!(plugins.insert(named("patrickf1/fzf.fish")).is_some());
+        assert_eq!(plugins.len(), 1);
+    }
+
+    #[test]
+    fn local_paths_and_archive_names_keep_their_case() {
+        let mut plugins = Plugins::default();
+        for name in ["/src/Foo", "/src/foo", "Theme", "theme"] {
+            assert!(plugins.insert(named(name)).is_none(), "{name}");
+        }
+
+        assert_eq!(plugins.len(), 4);
+        assert!(!plugins.contains_name("/SRC/foo"));
+    }
+
+    #[test]
+    fn reading_keeps_local_plugins_differing_in_case() {
+        let toml = r#"
+            version = "1.0"
+            generated_at = "2024-01-01T00:00:00Z"
+
+            [[plugins]]
+            name = "/src/Foo"
+            source = "/src/Foo"
+
+            [[plugins]]
+            name = "/src/foo"
+            source = "/src/foo"
+        "#;
+
+        let lock = LockFile::parse(toml, LockFormat::Toml).unwrap();
+
+        assert_eq!(lock.plugins.len(), 2);
+        assert_eq!(lock.duplicates(), 0);
+    }
+
+    /// A plugin with every field set to something other than its default
+    fn populated() -> Plugin {
+        let files = |names: &[&str]| Some(names.iter().map(|name| name.to_string()).collect());
+        Plugin {
+            name: "owner/repo".into(),
+            source: "https://github.com/owner/repo/archive/main.tar.gz".into(),
+            commit_hash: Some("0123abcd".into()),
+            branch: Some("main".into()),
+            installed_files: files(&["functions/repo.fish", "conf.d/repo.fish"]),
+            checksum: Some("sha256:feed".into()),
+            checksum_scope: Some(ChecksumScope::Tarball),
+            archive_digest: Some("sha256:beef".into()),
+            strip_components: Some(2),
+            confd_disabled: true,
+            priority: Some(-3),
+            shadowed_files: files(&["completions/repo.fish"]),
+            description: Some("A plugin".into()),
+            author: Some("Someone".into()),
+            homepage: Some("https://example.com".into()),
+            uninstall_hook: Some("echo bye".into()),
+            build: Some("make".into()),
+            binary: Some("repo".into()),
+            min_fish_version: Some("3.6".into()),
+            license: Some("MIT".into()),
+            allow_scripts: true,
+            load_order: Some(30),
+            dereference: true,
+            max_depth: Some(4),
+            shadow_completions: true,
+            raw: true,
+            latest: true,
+        }
+    }
+
+    #[test]
+    fn toml_and_json_hold_the_same_data() {
+        let lock = LockFile {
+            version: "1.0".into(),
+            generated_at: "2024-01-01T00:00:00Z".parse().unwrap(),
+            plugins: [populated()].into_iter().collect(),
+        };
+        let toml = lock.to_string_as(LockFormat::Toml).unwrap();
+
+        let json = LockFile::parse(&toml, LockFormat::Toml)
+            .unwrap()
+            .to_string_as(LockFormat::Json)
+            .unwrap();
+        let back = LockFile::parse(&json, LockFormat::Json)
+            .unwrap()
+            .to_string_as(LockFormat::Toml)
+            .unwrap();
+
+        assert_eq!(back, toml);
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        let fields = value["plugins"][0].as_object().unwrap();
+        assert_eq!(
+            fields.len(),
+            27,
+            "a field of the populated plugin was dropped"
+        );
+    }
+
+    #[test]
+    fn json_leaves_out_unset_fields_like_toml() {
+        let lock = LockFile {
+            version: "1.0".into(),
+            generated_at: "2024-01-01T00:00:00Z".parse().unwrap(),
+            plugins: [named("owner/repo")].into_iter().collect(),
+        };
+
+        let json = lock.to_string_as(LockFormat::Json).unwrap();
 
-    pub fn save(&self, path: &PathBuf) -> anyhow::Result<()> {
-        let toml_str = toml::to_string_pretty(&self)?;
-        fs::write(path, toml_str)?;
-        Ok(())
+        assert!(!json.contains("null"), "{json}");
+        let plugin = LockFile::parse(&json, LockFormat::Json).unwrap().plugins;
+        assert!(plugin.get("owner/repo").unwrap().installed_files.is_none());
     }
 }
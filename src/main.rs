@@ -1,11 +1,21 @@
-pub mod core;
-pub mod lock;
+mod bug_report;
+mod console;
+mod shell;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use clap::{Parser, Subcommand};
-use std::{env, path::PathBuf};
+use std::{env, fs, path::PathBuf, sync::Arc};
 
-use core::Fin;
+use console::{ConsoleObserver, Timings, Warnings};
+
+use fin::{
+    ChecksumScope, Config, Deprecation, DoctorReport, Fin, FinError, InstallOptions, LockFile,
+    LockFormat, OnConflict, Plugin, PluginState, RemoveOptions, github::RepoStatus, prompt::Prompt,
+    report::Failure,
+};
+
+/// Exit status after Ctrl-C, 128 + SIGINT like shells use
+const INTERRUPTED_EXIT_CODE: i32 = 130;
 
 #[derive(Debug, Parser)]
 #[clap(name = "fin", version = env!("CARGO_PKG_VERSION"), about = "A plugin manager for Fish")]
@@ -16,44 +26,1500 @@ struct Cli {
     /// Plugin installation path (default: Fish config directory)
     #[clap(long)]
     fin_path: Option<PathBuf>,
+
+    /// Fish configuration directory, where the lock file lives
+    #[clap(long)]
+    fish_config_dir: Option<PathBuf>,
+
+    /// Install plugin man pages and docs into this directory
+    #[clap(long)]
+    docs_dir: Option<PathBuf>,
+
+    /// Lock file format to write: toml (fin-lock.toml) or json (fin-lock.json)
+    #[clap(long, value_name = "FORMAT")]
+    lock_format: Option<LockFormat>,
+
+    /// Number of plugins to fetch at the same time (default 8), 1 for one after another
+    #[clap(long, short, global = true, value_name = "N")]
+    jobs: Option<usize>,
+
+    /// Times to try a download again after a server error or dropped connection (default 3)
+    #[clap(long, global = true, value_name = "N")]
+    retries: Option<u32>,
+
+    /// Assume yes for all prompts
+    #[clap(long, short, global = true, default_value_t = false)]
+    yes: bool,
 }
 
 #[derive(Debug, Subcommand)]
 enum Commands {
     /// Install plugins
     Install {
-        /// Plugins to install (repository URLs or local paths)
+        /// Plugins to install (owner/repo, registry names like `z`, or local paths)
         plugins: Option<Vec<String>>,
 
         /// Install plugins from the Fish plugin registry
         #[clap(long, short, default_value_t = false)]
         force: bool,
+
+        /// Print `source` commands that load the plugins into the current shell
+        #[clap(long, default_value_t = false)]
+        activate: bool,
+
+        /// Install the plugins recorded in a lock file, e.g. from `fin export --lock`
+        #[clap(long, value_name = "FILE", conflicts_with = "plugins")]
+        from_lock: Option<PathBuf>,
+
+        /// Fail without touching the lock file unless every plugin installs
+        #[clap(long, requires = "from_lock", default_value_t = false)]
+        frozen: bool,
+
+        /// Fetch an installed plugin again at its recorded ref, keeping its settings (repeatable)
+        #[clap(long, value_name = "PLUGIN", conflicts_with = "from_lock")]
+        reinstall: Vec<String>,
+
+        /// Also remove installed plugins the fin.toml manifest does not list
+        #[clap(long, conflicts_with_all = ["plugins", "from_lock", "reinstall"], default_value_t = false)]
+        sync: bool,
+
+        /// Install one plugin at a time, in the order given (lock files go by name)
+        #[clap(long, default_value_t = false)]
+        ordered: bool,
+
+        /// Emit install events after all plugins are installed, in the requested order
+        #[clap(long, default_value_t = false)]
+        defer_events: bool,
+
+        /// What to do with files another plugin already installed: error, overwrite or skip
+        #[clap(long, value_name = "MODE", default_value = "overwrite")]
+        on_conflict: OnConflict,
+
+        /// Conflict priority of these plugins, the higher priority's file wins (default 0)
+        #[clap(long, value_name = "N", allow_negative_numbers = true)]
+        priority: Option<i32>,
+
+        /// Leading path components to drop from the archives (default 1), kept for updates
+        #[clap(long, value_name = "N")]
+        strip_components: Option<usize>,
+
+        /// Copy the files symlinks in local plugins point to instead of linking them
+        #[clap(long, default_value_t = false)]
+        dereference: bool,
+
+        /// Install completions even where they replace the ones Fish ships, kept for updates
+        #[clap(long, default_value_t = false)]
+        allow_shadow_completions: bool,
+
+        /// Copy at most N directory levels of local plugins (functions/ is 1), kept for updates
+        #[clap(long, value_name = "N")]
+        max_depth: Option<usize>,
+
+        /// Refuse later downloads whose archive differs from this one, kept for updates
+        #[clap(long, default_value_t = false)]
+        pin_digest: bool,
+
+        /// Warn about functions these plugins define that other installed plugins define too
+        #[clap(long, default_value_t = false)]
+        check_functions: bool,
+
+        /// Run the build commands plugins declare in their fin.toml, kept for updates
+        #[clap(long, alias = "allow-build", default_value_t = false)]
+        allow_scripts: bool,
+
+        /// Install the whole archive as it is into a directory of its own, for inspection
+        #[clap(long, conflicts_with = "strip_components", default_value_t = false)]
+        no_strip: bool,
+
+        /// Fail plugins that need a newer fish than the installed one instead of warning
+        #[clap(long, default_value_t = false)]
+        strict: bool,
+
+        /// Print how long each plugin spent in each phase, slowest first
+        #[clap(long, default_value_t = false)]
+        timings: bool,
+
+        /// Record plugins whose files are already in place and match the lock file's checksum
+        #[clap(long, requires = "from_lock", default_value_t = false)]
+        ignore_existing: bool,
     },
 
     /// Remove installed plugins
     Remove {
         /// Plugins to remove
         plugins: Vec<String>,
+
+        /// Do not run the uninstall hooks plugins declare in their fin.toml
+        #[clap(long, default_value_t = false)]
+        no_hooks: bool,
     },
 
     /// Update installed plugins
     Update {
         /// Plugins to update (leave empty to update all)
         plugins: Vec<String>,
+
+        /// Check which plugins have new commits and pick the ones to update
+        #[clap(long, short, default_value_t = false)]
+        interactive: bool,
+
+        /// Show which files the update would add, remove or change, without updating
+        #[clap(long, default_value_t = false, conflicts_with = "interactive")]
+        dry_run: bool,
+
+        /// With --dry-run, also show the changes inside text files
+        #[clap(long, default_value_t = false, requires = "dry_run")]
+        diff: bool,
+
+        /// Print how long each plugin spent in each phase, slowest first
+        #[clap(long, default_value_t = false)]
+        timings: bool,
+    },
+
+    /// List plugins with new commits upstream, without updating them
+    Outdated {
+        /// Plugins to check (leave empty to check all)
+        plugins: Vec<String>,
+
+        /// Print the report as JSON
+        #[clap(long, default_value_t = false)]
+        json: bool,
     },
 
     /// List installed plugins
-    List {},
+    List {
+        /// Show the disk usage of each plugin, largest first
+        #[clap(long, default_value_t = false)]
+        sizes: bool,
+
+        /// Show full plugin names and each plugin's description
+        #[clap(long, short, default_value_t = false, conflicts_with = "sizes")]
+        verbose: bool,
+
+        /// Show each plugin's commit, branch, number of installed files and source in columns
+        #[clap(long, short, default_value_t = false, conflicts_with_all = ["sizes", "verbose"])]
+        long: bool,
+
+        /// plain for one name per line, json or toml for every plugin's lock file entry
+        #[clap(
+            long,
+            value_name = "FORMAT",
+            value_parser = ["plain", "json", "toml"],
+            default_value = "plain",
+            conflicts_with_all = ["sizes", "verbose", "long"]
+        )]
+        format: String,
+
+        /// Same as --format json
+        #[clap(long, default_value_t = false, conflicts_with_all = ["sizes", "verbose", "long", "format"])]
+        json: bool,
+
+        /// Include each plugin's installed files and whether they exist in the JSON or TOML
+        #[clap(long, default_value_t = false)]
+        include_files: bool,
+    },
+
+    /// Show the license of each installed plugin
+    Licenses {
+        /// Print the licenses as JSON
+        #[clap(long, default_value_t = false)]
+        json: bool,
+
+        /// Include the text of each license file
+        #[clap(long, default_value_t = false)]
+        full: bool,
+    },
+
+    /// Show details about an installed plugin
+    Info {
+        /// Plugin to show
+        plugin: String,
+
+        /// List the installed files
+        #[clap(long, default_value_t = false)]
+        files: bool,
+
+        /// Show the total size of the installed files
+        #[clap(long, default_value_t = false)]
+        on_disk_size: bool,
+
+        /// Show upstream release notes, or commits, since the installed version
+        #[clap(long, default_value_t = false)]
+        changelog: bool,
+
+        /// Skip looking up stars, issues and archived status of the upstream repository
+        #[clap(long, default_value_t = false)]
+        offline: bool,
+    },
+
+    /// Check installed files against the checksums in the lock file
+    Verify {
+        /// Plugins to verify (leave empty to verify all)
+        plugins: Vec<String>,
+
+        /// Only compare recorded checksums, skipping plugins that have none
+        #[clap(long, default_value_t = false)]
+        checksum_only: bool,
+
+        /// Download archives again to check plugins whose checksum covers the archive
+        #[clap(long, default_value_t = false, conflicts_with = "checksum_only")]
+        download: bool,
+
+        /// Print how long hashing each plugin's files took
+        #[clap(long, short, default_value_t = false)]
+        verbose: bool,
+    },
+
+    /// Load a plugin's conf.d snippets again
+    EnableConfd {
+        /// Plugin to enable
+        plugin: String,
+    },
+
+    /// Keep a plugin installed but stop Fish from loading its conf.d snippets
+    DisableConfd {
+        /// Plugin to disable
+        plugin: String,
+    },
+
+    /// Make Fish source a plugin's conf.d snippets before or after other plugins
+    SetLoadOrder {
+        /// Plugin to reorder
+        plugin: String,
+
+        /// Two-digit position, snippets with lower numbers load first (leave out to clear)
+        #[clap(value_parser = clap::value_parser!(u8).range(0..100))]
+        order: Option<u8>,
+    },
+
+    /// Print the fish wrapper function and completions, e.g. `fin init | source`
+    Init {
+        /// Write them to the fish functions and completions directories instead
+        #[clap(long, default_value_t = false)]
+        install: bool,
+    },
+
+    /// Remove installed plugins that none of the given lock files list
+    Gc {
+        /// Lock files declaring the plugins to keep, e.g. from `fin export --lock`
+        #[clap(long = "from-lock", value_name = "FILE", required = true)]
+        from_lock: Vec<PathBuf>,
+    },
+
+    /// Compare the lock file with the installed files, changing nothing
+    Status,
+
+    /// List files in the plugin directories that no installed plugin owns
+    Clean {
+        /// Delete the files
+        #[clap(long, default_value_t = false, conflicts_with = "dry_run")]
+        force: bool,
+
+        /// Only list the files (the default)
+        #[clap(long, default_value_t = false)]
+        dry_run: bool,
+    },
+
+    /// Export installation state
+    Export {
+        /// Write a lock file without machine-local data, for sharing
+        #[clap(long, required_unless_present = "format", conflicts_with = "format")]
+        lock: bool,
+
+        /// Write an inventory of the lock file, plugins, files and drift instead (json)
+        #[clap(long, value_name = "FORMAT", value_parser = ["json"])]
+        format: Option<String>,
+
+        /// Write to a file instead of stdout
+        #[clap(long, short)]
+        output: Option<PathBuf>,
+    },
+
+    /// List, switch and preview prompt themes
+    Theme {
+        #[clap(subcommand)]
+        command: ThemeCommand,
+    },
+
+    /// Install a plugin in place of another one, e.g. the successor of a deprecated plugin
+    Replace {
+        /// Installed plugin to remove
+        old: String,
+
+        /// Plugin to install instead
+        new: String,
+    },
+
+    /// Find plugins in the plugin registry by name or description
+    Search {
+        /// Text to look for
+        term: String,
+    },
+
+    /// Emit plugins' install events again, e.g. after restoring their files without fin
+    RunHooks {
+        /// Plugins whose events to emit (leave empty for all)
+        plugins: Vec<String>,
+    },
+
+    /// Check the installation for problems, like fisher managing the same files
+    Doctor,
+
+    /// Print a redacted report of fin's setup to paste into a bug report
+    BugReport {
+        /// Show local plugins' paths instead of numbering them
+        #[clap(long, default_value_t = false)]
+        include_paths: bool,
+    },
+
+    /// Move the plugins fisher manages over to fin and remove fisher
+    Takeover,
+
+    /// Clean up the lock file (sorts and deduplicates when no flag is given)
+    Lock {
+        /// Sort plugins and their installed files
+        #[clap(long, default_value_t = false)]
+        sort: bool,
+
+        /// Remove duplicate plugin entries, keeping the most complete one
+        #[clap(long, default_value_t = false)]
+        dedup: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum ThemeCommand {
+    /// List installed plugins that ship a prompt, marking the active one
+    List,
+
+    /// Use a plugin's prompt, setting the current one aside without uninstalling it
+    Set {
+        /// Plugin whose prompt to use
+        plugin: String,
+    },
+
+    /// Open a nested fish showing a plugin's prompt, without changing anything
+    Preview {
+        /// Plugin whose prompt to show
+        plugin: String,
+    },
 }
 
 fn main() -> Result<()> {
     let cli = Cli::parse();
-    let mut fin = Fin::new(cli.fin_path)?;
+    let mut config = Config::load()?;
+    if cli.docs_dir.is_some() {
+        config.docs_dir = cli.docs_dir;
+    }
+    if cli.fish_config_dir.is_some() {
+        config.fish_config_dir = cli.fish_config_dir;
+    }
+    if cli.jobs.is_some() {
+        config.jobs = cli.jobs;
+    }
+    if cli.retries.is_some() {
+        config.retries = cli.retries;
+    }
+    if cli.lock_format.is_some() {
+        config.lock_format = cli.lock_format;
+    }
+
+    let mut observer = match cli.command {
+        Commands::Install { activate, .. } => ConsoleObserver::new("Installed", activate),
+        Commands::Replace { .. } => ConsoleObserver::new("Installed", false),
+        _ => ConsoleObserver::new("Updated", false),
+    };
+    let timings = match cli.command {
+        Commands::Install { timings, .. } | Commands::Update { timings, .. } => {
+            timings.then(|| Arc::new(Timings::default()))
+        }
+        _ => None,
+    };
+    if let Some(timings) = &timings {
+        observer = observer.with_timings(timings.clone());
+    }
+    let warnings = Arc::new(Warnings::default());
+    observer = observer.with_warnings(warnings.clone());
+    let mut fin = Fin::new(cli.fin_path, config)?.with_observer(Arc::new(observer));
+    let prompt = Prompt::new(cli.yes);
+
+    let result = run(cli.command, &mut fin, &prompt, timings.as_deref());
+    warnings.print();
+    result
+}
+
+fn run(command: Commands, fin: &mut Fin, prompt: &Prompt, timings: Option<&Timings>) -> Result<()> {
+    match command {
+        Commands::Install {
+            plugins,
+            force,
+            activate,
+            from_lock,
+            frozen,
+            reinstall,
+            sync,
+            ordered,
+            defer_events,
+            strip_components,
+            on_conflict,
+            priority,
+            dereference,
+            allow_shadow_completions,
+            max_depth,
+            pin_digest,
+            check_functions,
+            allow_scripts,
+            no_strip,
+            strict,
+            timings: _,
+            ignore_existing,
+        } => {
+            let options = InstallOptions {
+                force,
+                frozen,
+                ordered,
+                defer_events,
+                strip_components,
+                on_conflict,
+                priority,
+                dereference,
+                allow_shadow_completions,
+                max_depth,
+                pin_digest,
+                check_functions,
+                allow_scripts,
+                no_strip,
+                strict,
+                ignore_existing,
+                reinstall: fin.resolve_names(&reinstall, prompt)?,
+            };
+            if fin.list()?.is_empty() && fin.detect_fisher()?.is_some() {
+                eprintln!(
+                    "Warning: fisher manages plugins here too, run `fin takeover` to move them to fin"
+                );
+            }
+            let mut listed = None;
+            let report = match from_lock {
+                Some(path) => fin.install_from_lock(&LockFile::read(&path)?, options)?,
+                None => {
+                    let plugins = match plugins {
+                        Some(specs) => Some(expand_registry_names(fin, specs, prompt)?),
+                        // Only the named plugins, not everything in the lock file
+                        None if !options.reinstall.is_empty() => Some(Vec::new()),
+                        None => match fin.install_manifest()? {
+                            Some(manifest) => {
+                                let specs = expand_registry_names(fin, manifest.specs(), prompt)?;
+                                listed = Some(specs.clone());
+                                Some(specs)
+                            }
+                            None if sync => anyhow::bail!(
+                                "--sync needs a fin.toml listing the plugins in {}",
+                                fin.fish_config_dir().display()
+                            ),
+                            None => None,
+                        },
+                    };
+                    fin.install(plugins, options)?
+                }
+            };
+            let unlisted = match listed {
+                Some(specs) if sync && !report.interrupted => fin.unlisted(&specs)?,
+                _ => Vec::new(),
+            };
+            let removed = match unlisted.is_empty() {
+                true => Default::default(),
+                false => fin.remove(&unlisted, RemoveOptions::default())?,
+            };
+            // stdout is reserved for the activation script so it can be sourced
+            let status = |message: &str| {
+                if activate {
+                    eprintln!("{message}");
+                } else {
+                    println!("{message}");
+                }
+            };
+            print_build_output(&report.installed, &report.build_output);
+            for name in &report.repaired {
+                status(&format!("Repaired: {name} (restored missing files)"));
+            }
+            for plugin in report.installed.iter().filter(|p| p.raw) {
+                status(&format!(
+                    "Raw: {} is in {}",
+                    plugin.name,
+                    fin.raw_dir(plugin).display()
+                ));
+            }
+            for name in &report.adopted {
+                status(&format!("Adopted: {name} (files already in place)"));
+            }
+            for plugin in &removed.removed {
+                status(&format!("Removed: {} (not in fin.toml)", plugin.name));
+            }
+            if report.installed.is_empty()
+                && report.adopted.is_empty()
+                && report.failed.is_empty()
+                && removed.removed.is_empty()
+            {
+                status("All plugins are already installed");
+            }
+
+            if activate {
+                for command in fin.activation_commands(&report.installed) {
+                    println!("{command}");
+                }
+            }
+            print_hook_failures(&report.hook_failed);
+            print_hook_failures(&removed.hook_failed);
+            if let Some(timings) = &timings {
+                timings.print();
+            }
+            if report.interrupted {
+                exit_interrupted("Installed", report.installed.len(), &report.failed);
+            }
+            // The plugins that did install stay recorded, but scripts need to notice
+            if !report.failed.is_empty() {
+                anyhow::bail!("{} plugins failed to install", report.failed.len());
+            }
+        }
+        Commands::Remove { plugins, no_hooks } => {
+            let plugins = fin.resolve_names(&plugins, prompt)?;
+            let report = fin.remove(&plugins, RemoveOptions { no_hooks })?;
+            for (name, output) in &report.hook_output {
+                for line in output.lines() {
+                    eprintln!("{name}: {line}");
+                }
+            }
+            for file in &report.missing_files {
+                println!("File not found: {}", file.display());
+            }
+            for plugin in &report.removed {
+                println!("Removed: {}", plugin.name);
+            }
+            for name in &report.not_installed {
+                println!("Not installed: {name}");
+            }
+            println!("Removed {} plugins total", report.removed.len());
+            print_hook_failures(&report.hook_failed);
+        }
+        Commands::Update {
+            plugins,
+            dry_run: true,
+            diff,
+            ..
+        } => {
+            let plugins = fin.resolve_names(&plugins, prompt)?;
+            let report = fin.preview_update(&plugins, diff)?;
+            for name in &report.not_installed {
+                println!("Not installed: {name}");
+            }
+            for (plugin, err) in &report.failed {
+                eprintln!("Cannot check {}: {}", plugin.name, err.full_message());
+            }
+            for preview in &report.plugins {
+                let changes = [
+                    ("Add", &preview.added),
+                    ("Remove", &preview.removed),
+                    ("Change", &preview.modified),
+                ];
+                if changes.iter().all(|(_, files)| files.is_empty()) {
+                    println!("{}: no changes", preview.name);
+                    continue;
+                }
+                println!("{}:", preview.name);
+                for (verb, files) in changes {
+                    for file in files {
+                        println!("  {verb}: {file}");
+                    }
+                }
+                for (_, diff) in &preview.diffs {
+                    print!("{diff}");
+                }
+            }
+        }
+        Commands::Update {
+            plugins,
+            interactive,
+            timings: _,
+            ..
+        } => {
+            let mut plugins = fin.resolve_names(&plugins, prompt)?;
+            if interactive {
+                if !prompt.is_interactive() {
+                    anyhow::bail!(
+                        "--interactive needs a terminal, name the plugins to update instead"
+                    );
+                }
+                let outdated = fin.outdated(&plugins)?;
+                for (plugin, err) in &outdated.failed {
+                    eprintln!("Cannot check {}: {}", plugin.name, err.full_message());
+                }
+                if outdated.outdated.is_empty() {
+                    if outdated.failed.is_empty() {
+                        println!("All plugins are up to date");
+                    } else {
+                        println!("No plugins to update");
+                    }
+                    return Ok(());
+                }
+
+                let options: Vec<String> = outdated
+                    .outdated
+                    .iter()
+                    .map(|o| {
+                        let current = o.current.as_deref().map_or("unknown", short_sha);
+                        format!("{} {current} -> {}", o.name, short_sha(&o.latest))
+                    })
+                    .collect();
+                let picked = prompt.select("Outdated plugins", &options)?;
+                if picked.is_empty() {
+                    println!("Nothing selected");
+                    return Ok(());
+                }
+                plugins = picked
+                    .into_iter()
+                    .map(|i| outdated.outdated[i].name.clone())
+                    .collect();
+            }
+            let report = fin.update(&plugins)?;
+            print_build_output(&report.updated, &report.build_output);
+            for name in &report.changed {
+                println!("Contents changed: {name}");
+            }
+            for name in &report.up_to_date {
+                println!("Up to date: {name}");
+            }
+            if report.updated.is_empty() && report.failed.is_empty() && report.up_to_date.is_empty()
+            {
+                println!("No plugins to update");
+            }
+            for name in &report.not_installed {
+                println!("Not installed: {name}");
+            }
+            print_hook_failures(&report.hook_failed);
+            print_deprecations(&report.deprecated);
+            if let Some(timings) = &timings {
+                timings.print();
+            }
+            if report.interrupted {
+                exit_interrupted("Updated", report.updated.len(), &report.failed);
+            }
+            if !report.failed.is_empty() {
+                anyhow::bail!("{} plugins failed to update", report.failed.len());
+            }
+        }
+        Commands::Outdated { plugins, json } => {
+            let plugins = fin.resolve_names(&plugins, prompt)?;
+            let report = fin.outdated(&plugins)?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+                return Ok(());
+            }
+            for (plugin, err) in &report.failed {
+                eprintln!("Cannot check {}: {}", plugin.name, err.full_message());
+            }
+            let rows: Vec<(&str, &str, &str)> = report
+                .outdated
+                .iter()
+                .map(|o| {
+                    let current = o.current.as_deref().map_or("unknown", short_sha);
+                    (o.name.as_str(), current, short_sha(&o.latest))
+                })
+                .chain(report.pinned.iter().map(|name| {
+                    let current = fin
+                        .plugin(name)
+                        .ok()
+                        .flatten()
+                        .and_then(|p| p.commit_hash.as_deref())
+                        .map_or("unknown", short_sha);
+                    (name.as_str(), current, "pinned")
+                }))
+                .collect();
+            if rows.is_empty() {
+                if report.failed.is_empty() {
+                    println!("All plugins are up to date");
+                }
+            } else {
+                let width = rows.iter().map(|(name, ..)| name.len()).max().unwrap_or(0);
+                println!("{:width$}  {:7}  Available", "Plugin", "Current");
+                for (name, current, available) in rows {
+                    println!("{name:width$}  {current:7}  {available}");
+                }
+            }
+            print_deprecations(&report.deprecated);
+        }
+        Commands::List {
+            format,
+            json,
+            include_files,
+            ..
+        } if json || format != "plain" => {
+            let states = fin.states(include_files)?;
+            if format == "toml" {
+                // TOML documents are tables, the entries go in an array like in the lock file
+                #[derive(serde::Serialize)]
+                struct Listing {
+                    plugins: Vec<PluginState>,
+                }
+                print!("{}", toml::to_string_pretty(&Listing { plugins: states })?);
+            } else {
+                println!("{}", serde_json::to_string_pretty(&states)?);
+            }
+        }
+        Commands::List {
+            include_files: true,
+            ..
+        } => anyhow::bail!("--include-files needs --format json or --format toml"),
+        Commands::List { long: true, .. } => {
+            let header = ["Plugin", "Commit", "Branch", "Files", "Source"].map(String::from);
+            let rows: Vec<[String; 5]> = fin
+                .list()?
+                .into_iter()
+                .map(|plugin| {
+                    let files = plugin.installed_files.as_ref().map_or(0, |f| f.len());
+                    [
+                        plugin.name.clone(),
+                        plugin
+                            .commit_hash
+                            .as_deref()
+                            .map_or("-", short_sha)
+                            .to_string(),
+                        plugin.branch.clone().unwrap_or_else(|| "-".to_string()),
+                        files.to_string(),
+                        plugin.source.clone(),
+                    ]
+                })
+                .collect();
+            let mut widths = [0; 4];
+            for row in std::iter::once(&header).chain(&rows) {
+                for (width, cell) in widths.iter_mut().zip(row) {
+                    *width = (*width).max(cell.len());
+                }
+            }
+            for [name, commit, branch, files, source] in std::iter::once(&header).chain(&rows) {
+                println!(
+                    "{name:0$}  {commit:1$}  {branch:2$}  {files:>3$}  {source}",
+                    widths[0], widths[1], widths[2], widths[3]
+                );
+            }
+        }
+        Commands::List {
+            sizes: false,
+            verbose,
+            ..
+        } => {
+            for plugin in fin.list()? {
+                match &plugin.description {
+                    _ if !verbose => println!("{}", fin.display_name(plugin)?),
+                    Some(description) => println!("{}  {description}", plugin.name),
+                    None => println!("{}", plugin.name),
+                }
+            }
+        }
+        Commands::List { sizes: true, .. } => {
+            let mut plugins: Vec<_> = fin
+                .list()?
+                .into_iter()
+                .map(|plugin| (fin.disk_usage(plugin).bytes, plugin))
+                .collect();
+            plugins.sort_by_key(|&(bytes, _)| std::cmp::Reverse(bytes));
+            for (bytes, plugin) in plugins {
+                println!(
+                    "{:>10}  {}",
+                    console::format_size(bytes),
+                    fin.display_name(plugin)?
+                );
+            }
+        }
+        Commands::Licenses { json: true, full } => {
+            let licenses = fin.licenses(full)?;
+            println!("{}", serde_json::to_string_pretty(&licenses)?);
+        }
+        Commands::Licenses { json: false, full } => {
+            let licenses = fin.licenses(full)?;
+            let width = licenses.iter().map(|l| l.name.len()).max().unwrap_or(0);
+            for license in &licenses {
+                let id = license.license.as_deref().unwrap_or("unknown");
+                println!("{:width$}  {id}", license.name);
+                if let Some(text) = &license.text {
+                    println!();
+                    for line in text.lines() {
+                        match line.is_empty() {
+                            true => println!(),
+                            false => println!("    {line}"),
+                        }
+                    }
+                    println!();
+                }
+            }
+        }
+        Commands::Info {
+            plugin,
+            files,
+            on_disk_size,
+            changelog,
+            offline,
+        } => {
+            let name = fin.resolve_names(&[plugin], prompt)?.remove(0);
+            let plugin = fin
+                .plugin(&name)?
+                .with_context(|| format!("Not installed: {name}"))?;
+
+            println!("Name: {}", plugin.name);
+            if let Some(description) = &plugin.description {
+                println!("Description: {description}");
+            }
+            println!("Source: {}", plugin.source);
+            if plugin.latest {
+                println!("Tracks: newest release");
+            }
+            if let Some(author) = &plugin.author {
+                println!("Author: {author}");
+            }
+            if let Some(homepage) = &plugin.homepage {
+                println!("Homepage: {homepage}");
+            }
+            if let Some(branch) = &plugin.branch {
+                println!("Branch: {branch}");
+            }
+            if let Some(commit) = &plugin.commit_hash {
+                println!("Commit: {commit}");
+            }
+            if let Some(checksum) = &plugin.checksum {
+                match plugin.checksum_scope {
+                    Some(ChecksumScope::Tarball) => println!("Checksum: {checksum} (archive)"),
+                    _ => println!("Checksum: {checksum}"),
+                }
+            }
+            if let Some(digest) = &plugin.archive_digest {
+                println!("Pinned archive: {digest}");
+            }
+            if on_disk_size {
+                let usage = fin.disk_usage(plugin);
+                println!("Size: {}", console::format_size(usage.bytes));
+                for file in &usage.missing_files {
+                    println!("File not found: {}", file.display());
+                }
+            }
+            if !offline {
+                // The rest of the info does not depend on reaching the API
+                match fin.repo_status(plugin) {
+                    Ok(Some(status)) => print_repo_status(&status),
+                    Ok(None) => {}
+                    Err(err) => eprintln!("Warning: {}", err.full_message()),
+                }
+            }
+            if files {
+                println!("Files:");
+                for file in fin.installed_paths(plugin) {
+                    println!("  {}", file.display());
+                }
+            }
+            if changelog {
+                print_changelog(&fin.changelog(plugin)?);
+            }
+        }
+        Commands::Verify {
+            plugins,
+            checksum_only,
+            download,
+            verbose,
+        } => {
+            let plugins = fin.resolve_names(&plugins, prompt)?;
+            let report = fin.verify(&plugins, download)?;
+            if verbose {
+                for (name, elapsed) in &report.timings {
+                    eprintln!("Hashed {name} in {:.1?}", elapsed);
+                }
+            }
+            for name in &report.ok {
+                println!("OK: {name}");
+            }
+            for name in &report.mismatched {
+                println!("Modified: {name}");
+            }
+            if !checksum_only {
+                for name in &report.unchecked {
+                    let archive = fin
+                        .plugin(name)?
+                        .is_some_and(|p| p.checksum_scope == Some(ChecksumScope::Tarball));
+                    if archive {
+                        println!("Not downloaded: {name} (pass --download to check its archive)");
+                    } else {
+                        println!("No checksum: {name}");
+                    }
+                }
+            }
+            for (plugin, err) in &report.failed {
+                println!("Cannot check: {} - {}", plugin.name, err.full_message());
+            }
+
+            let failed = report.mismatched.len()
+                + report.failed.len()
+                + if checksum_only {
+                    0
+                } else {
+                    report.unchecked.len()
+                };
+            if failed > 0 {
+                anyhow::bail!("{failed} plugins failed verification");
+            }
+        }
+        Commands::EnableConfd { plugin } => set_confd_enabled(fin, prompt, plugin, true)?,
+        Commands::DisableConfd { plugin } => set_confd_enabled(fin, prompt, plugin, false)?,
+        Commands::SetLoadOrder { plugin, order } => {
+            let name = fin.resolve_names(&[plugin], prompt)?.remove(0);
+            if fin.plugin(&name)?.is_none() {
+                println!("Not installed: {name}");
+                return Ok(());
+            }
+            for file in fin.set_load_order(&name, order)? {
+                println!("Renamed: {}", file.display());
+            }
+        }
+        Commands::Gc { from_lock } => {
+            let declared = from_lock
+                .iter()
+                .map(LockFile::read)
+                .collect::<fin::error::Result<Vec<_>>>()?;
+            let orphans = fin.orphans(&declared)?;
+            if orphans.is_empty() {
+                println!("No orphaned plugins");
+                return Ok(());
+            }
+
+            for name in &orphans {
+                println!("Orphaned: {name}");
+            }
+            if !prompt.confirm(&format!("Remove {} plugins?", orphans.len()))? {
+                println!("Nothing removed, pass --yes to remove them without asking");
+                return Ok(());
+            }
+            let report = fin.remove(&orphans, RemoveOptions::default())?;
+            for plugin in &report.removed {
+                println!("Removed: {}", plugin.name);
+            }
+        }
+        Commands::Status => {
+            let report = fin.status()?;
+            for (name, missing) in &report.incomplete {
+                for file in missing {
+                    println!("Missing: {} ({name})", file.display());
+                }
+            }
+            for file in &report.orphaned_files {
+                println!("Orphaned: {}", file.display());
+            }
+            let missing: usize = report.incomplete.iter().map(|(_, files)| files.len()).sum();
+            println!(
+                "{} plugins OK, {} plugins missing {missing} files, {} orphaned files",
+                report.ok.len(),
+                report.incomplete.len(),
+                report.orphaned_files.len()
+            );
+        }
+        Commands::Clean { force, dry_run: _ } => {
+            let mut orphaned = fin.orphaned_files()?;
+            if orphaned.is_empty() {
+                println!("No orphaned files");
+                return Ok(());
+            }
 
-    match cli.command {
-        Commands::Install { plugins, force } => fin.install(plugins, force),
-        Commands::Remove { plugins } => fin.remove(&plugins),
-        Commands::Update { plugins } => fin.update(&plugins),
-        Commands::List {} => fin.list(),
+            for file in &orphaned {
+                println!("Orphaned: {}", file.display());
+            }
+            if !force {
+                println!("Nothing deleted, pass --force to delete them");
+                return Ok(());
+            }
+            let count = orphaned.len();
+            orphaned.retain(|file| fs::remove_file(file).is_err());
+            println!("Deleted {} files", count - orphaned.len());
+            for file in &orphaned {
+                eprintln!("Failed to delete: {}", file.display());
+            }
+            if !orphaned.is_empty() {
+                anyhow::bail!("{} files could not be deleted", orphaned.len());
+            }
+        }
+        Commands::Export {
+            lock: _,
+            format,
+            output,
+        } => {
+            let content = match format {
+                Some(_) => format!("{}\n", serde_json::to_string_pretty(&fin.inventory()?)?),
+                None => fin.lock_file()?.to_portable().to_toml_string()?,
+            };
+            match output {
+                Some(path) => fs::write(&path, content)
+                    .with_context(|| format!("Failed to write {}", path.display()))?,
+                None => print!("{content}"),
+            }
+        }
+        Commands::Init { install: false } => {
+            print!("{}{}", shell::WRAPPER_FUNCTION, shell::completions());
+        }
+        Commands::Init { install: true } => {
+            let glue = [
+                ("functions", shell::WRAPPER_FUNCTION.to_string()),
+                ("completions", shell::completions()),
+            ];
+            for (subdir, content) in glue {
+                let path = fin.fish_config_dir().join(subdir).join("fin.fish");
+                fs::create_dir_all(path.parent().unwrap_or(&path))
+                    .and_then(|()| fs::write(&path, content))
+                    .with_context(|| format!("Failed to write {}", path.display()))?;
+                println!("Wrote: {}", path.display());
+            }
+        }
+        Commands::Theme {
+            command: ThemeCommand::List,
+        } => {
+            let themes = fin.themes()?;
+            if themes.is_empty() {
+                println!("No installed plugin ships a prompt");
+            }
+            for theme in themes {
+                let marker = if theme.active { "*" } else { " " };
+                println!("{marker} {} ({})", theme.name, theme.prompts.join(", "));
+            }
+        }
+        Commands::Theme {
+            command: ThemeCommand::Set { plugin },
+        } => {
+            let name = fin.resolve_names(&[plugin], prompt)?.remove(0);
+            if fin.plugin(&name)?.is_none() {
+                println!("Not installed: {name}");
+                return Ok(());
+            }
+            for previous in fin.set_theme(&name)? {
+                println!("Deactivated: {previous}");
+            }
+            println!("Theme: {name}, open a new shell to see it");
+        }
+        Commands::Theme {
+            command: ThemeCommand::Preview { plugin },
+        } => {
+            let name = fin.resolve_names(&[plugin], prompt)?.remove(0);
+            if fin.plugin(&name)?.is_none() {
+                println!("Not installed: {name}");
+                return Ok(());
+            }
+            eprintln!("Previewing {name}, type `exit` to return");
+            fin.preview_theme(&name)?;
+        }
+        Commands::Replace { old, new } => {
+            let old = fin.resolve_names(&[old], prompt)?.remove(0);
+            let new = expand_registry_names(fin, vec![new], prompt)?.remove(0);
+            let report = fin.replace(&old, &new)?;
+            print_build_output(
+                std::slice::from_ref(&report.installed),
+                &report.build_output,
+            );
+            for file in &report.missing_files {
+                println!("File not found: {}", file.display());
+            }
+            println!(
+                "Replaced: {} with {}",
+                report.removed.name, report.installed.name
+            );
+            print_hook_failures(&report.hook_failed);
+        }
+        Commands::Search { term } => {
+            let loaded = fin.registry().load()?;
+            if let Some(warning) = &loaded.warning {
+                eprintln!("Warning: {warning}");
+            }
+            let matches = loaded.index.search(&term);
+            if matches.is_empty() {
+                println!("No plugins match \"{term}\"");
+            }
+            for entry in matches {
+                match &entry.description {
+                    Some(description) => {
+                        println!("{} ({}) - {description}", entry.name, entry.repo)
+                    }
+                    None => println!("{} ({})", entry.name, entry.repo),
+                }
+                let facts: Vec<String> = [
+                    entry.stars.map(|stars| format!("{stars} stars")),
+                    entry.updated_at.as_ref().map(|at| format!("updated {at}")),
+                ]
+                .into_iter()
+                .flatten()
+                .collect();
+                if !facts.is_empty() {
+                    println!("  {}", facts.join(", "));
+                }
+                println!("  fin install {}", entry.repo);
+            }
+        }
+        Commands::RunHooks { plugins } => {
+            let plugins = fin.resolve_names(&plugins, prompt)?;
+            let report = fin.run_hooks(&plugins)?;
+            for name in &report.not_installed {
+                println!("Not installed: {name}");
+            }
+            for run in &report.plugins {
+                for line in run.output.lines() {
+                    eprintln!("{}: {line}", run.name);
+                }
+                match run.handled.as_slice() {
+                    [] => println!("No handlers: {}", run.name),
+                    events => println!("Ran: {} ({})", run.name, events.join(", ")),
+                }
+            }
+            for (plugin, err) in &report.failed {
+                eprintln!("Failed: {} - {}", plugin.name, err.full_message());
+            }
+            if !report.failed.is_empty() {
+                anyhow::bail!("{} plugins failed", report.failed.len());
+            }
+        }
+        Commands::Doctor => {
+            let report = fin.doctor()?;
+            for line in doctor_findings(&report) {
+                println!("{line}");
+            }
+            match report.problems() {
+                0 => println!("No problems found"),
+                problems => anyhow::bail!("{problems} problems found"),
+            }
+        }
+        Commands::BugReport { include_paths } => {
+            print!("{}", bug_report::render(fin, include_paths)?);
+        }
+        Commands::Takeover => takeover(fin, prompt)?,
+        Commands::Lock { sort, dedup } => {
+            let all = !sort && !dedup;
+            let removed = fin.lock(dedup || all)?;
+            if dedup || all {
+                println!("Removed {removed} duplicate entries");
+            }
+            if sort || all {
+                println!("Sorted {} plugins", fin.list()?.len());
+            }
+        }
     }
+
+    Ok(())
+}
+
+/// Import fisher's plugins, reinstall them under fin and remove fisher
+///
+/// The lock file is backed up before anything changes, and every step that
+/// replaces or deletes files asks first. Whatever could not be moved over is
+/// listed at the end.
+fn takeover(fin: &mut Fin, prompt: &Prompt) -> Result<()> {
+    let Some(fisher) = fin.detect_fisher()? else {
+        println!("No fisher installation found");
+        return Ok(());
+    };
+    if let Some(backup) = fin.backup_lock()? {
+        println!("Backed up the lock file to {}", backup.display());
+    }
+
+    let (specs, mut unmapped): (Vec<String>, Vec<String>) = fisher
+        .plugins
+        .iter()
+        .cloned()
+        .partition(|spec| Plugin::parse(spec).is_ok());
+    let mut installed = Vec::new();
+    if !specs.is_empty() {
+        for spec in &specs {
+            println!("fisher plugin: {spec}");
+        }
+        if prompt.confirm(&format!(
+            "Reinstall {} plugins with fin, replacing fisher's copies?",
+            specs.len()
+        ))? {
+            let options = InstallOptions {
+                force: true,
+                ..Default::default()
+            };
+            let report = fin.install(Some(specs.clone()), options)?;
+            print_hook_failures(&report.hook_failed);
+            for (plugin, err) in &report.failed {
+                unmapped.push(format!("{} ({})", plugin.name, err.full_message()));
+            }
+            installed = specs
+                .into_iter()
+                .filter(|spec| {
+                    let name = Plugin::parse(spec).map(|plugin| plugin.name);
+                    !report
+                        .failed
+                        .iter()
+                        .any(|(plugin, _)| name.as_ref().is_ok_and(|name| name == &plugin.name))
+                })
+                .collect();
+        } else {
+            unmapped.extend(specs);
+        }
+    }
+
+    let mut left_behind = fin.fisher_leftovers(&fisher, &installed)?;
+    if !left_behind.is_empty() {
+        for file in &left_behind {
+            println!("Untracked: {}", file.display());
+        }
+        if prompt.confirm(&format!(
+            "Delete {} files fisher installed that fin's versions do not ship?",
+            left_behind.len()
+        ))? {
+            left_behind.retain(|file| fs::remove_file(file).is_err());
+        }
+    }
+
+    let fisher_files: Vec<&PathBuf> = fisher
+        .own_files
+        .iter()
+        .chain(&fisher.plugins_file)
+        .collect();
+    let mut fisher_removed = false;
+    if !fisher_files.is_empty() {
+        for file in &fisher_files {
+            println!("fisher file: {}", file.display());
+        }
+        if prompt.confirm("Remove fisher?")? {
+            if !fin.remove_fisher(&fisher)? {
+                eprintln!(
+                    "Could not erase fisher's universal variables, run: \
+                     set -eU (set -nU | string match '_fisher_*')"
+                );
+            }
+            fisher_removed = true;
+        }
+    }
+
+    println!("Took over {} plugins from fisher", installed.len());
+    for name in &unmapped {
+        println!("Not taken over: {name}");
+    }
+    for file in &left_behind {
+        println!("Left in place: {}", file.display());
+    }
+    if !fisher_removed && !fisher_files.is_empty() {
+        println!("fisher is still installed, run `fin takeover` again to remove it");
+    }
+    Ok(())
+}
+
+fn set_confd_enabled(fin: &mut Fin, prompt: &Prompt, plugin: String, enabled: bool) -> Result<()> {
+    let name = fin.resolve_names(&[plugin], prompt)?.remove(0);
+    if fin.plugin(&name)?.is_none() {
+        println!("Not installed: {name}");
+        return Ok(());
+    }
+
+    let verb = if enabled { "Enabled" } else { "Disabled" };
+    for file in fin.set_confd_enabled(&name, enabled)? {
+        println!("{verb}: {}", file.display());
+    }
+    Ok(())
+}
+
+fn print_changelog(changelog: &fin::github::Changelog) {
+    let since = match &changelog.since {
+        Some(commit) => format!("since {}", short_sha(commit)),
+        None => "recent, installed version unknown".to_string(),
+    };
+
+    if !changelog.releases.is_empty() {
+        println!("Releases ({since}):");
+        for release in &changelog.releases {
+            let date = release.published_at.as_deref().unwrap_or_default();
+            println!("  {} {}", release.tag_name, date.get(..10).unwrap_or(date));
+            for line in release.body.as_deref().unwrap_or_default().lines() {
+                println!("    {line}");
+            }
+        }
+    } else if !changelog.commits.is_empty() {
+        println!("Commits ({since}):");
+        for commit in &changelog.commits {
+            let summary = commit.message.lines().next().unwrap_or_default();
+            println!("  {} {summary}", short_sha(&commit.sha));
+        }
+    } else {
+        println!("No changes ({since})");
+    }
+}
+
+/// Replace bare names like `z` with the registry plugin they stand for, `jethrokuan/z`
+///
+/// Specs with a `/` or that start like a path are left alone, and so are
+/// names the registry does not know, so they fail as usual. The index is
+/// only loaded when there is a name to look up.
+fn expand_registry_names(fin: &Fin, specs: Vec<String>, prompt: &Prompt) -> Result<Vec<String>> {
+    let is_bare = |spec: &str| !spec.contains('/') && !spec.starts_with(['.', '~']);
+    if !specs.iter().any(|spec| is_bare(spec)) {
+        return Ok(specs);
+    }
+    let loaded = fin.registry().load()?;
+    if let Some(warning) = &loaded.warning {
+        eprintln!("Warning: {warning}");
+    }
+
+    let mut expanded = Vec::with_capacity(specs.len());
+    for spec in specs {
+        let (name, ref_suffix) = match spec.split_once('@') {
+            Some((name, ref_name)) => (name, format!("@{ref_name}")),
+            None => (spec.as_str(), String::new()),
+        };
+        if !is_bare(&spec) {
+            expanded.push(spec);
+            continue;
+        }
+        match loaded.index.named(name).as_slice() {
+            [] => expanded.push(spec),
+            [entry] => {
+                let repo = format!("{}{ref_suffix}", entry.repo);
+                if !prompt.confirm(&format!("Install {repo} for \"{name}\"?"))? {
+                    anyhow::bail!(
+                        "Not installing \"{name}\", pass --yes or install {} by its full name",
+                        entry.repo
+                    );
+                }
+                eprintln!("{name}: {repo}");
+                expanded.push(repo);
+            }
+            candidates => {
+                let candidates: Vec<String> = candidates
+                    .iter()
+                    .map(|entry| format!("{}{ref_suffix}", entry.repo))
+                    .collect();
+                return Err(FinError::Ambiguous {
+                    name: name.to_string(),
+                    candidates,
+                }
+                .into());
+            }
+        }
+    }
+    Ok(expanded)
+}
+
+/// The `Remote:` section of `fin info`
+fn print_repo_status(status: &RepoStatus) {
+    println!("Remote:");
+    match status {
+        RepoStatus::Found(info) => {
+            if info.archived {
+                println!("  Archived: read-only upstream, no longer maintained");
+            }
+            println!("  Stars: {}", info.stars);
+            println!("  Open issues: {}", info.open_issues);
+            if let Some(date) = &info.last_commit {
+                println!("  Last commit: {date}");
+            }
+        }
+        RepoStatus::Missing => {
+            println!("  Not found: the repository was deleted, renamed or made private")
+        }
+    }
+}
+
+/// The problems `fin doctor` found, one line each with indented details
+fn doctor_findings(report: &DoctorReport) -> Vec<String> {
+    let mut lines = Vec::new();
+    if let Some(fisher) = &report.fisher {
+        lines.push("fisher is installed, it and fin overwrite each other's files:".to_string());
+        for file in fisher.own_files.iter().chain(&fisher.plugins_file) {
+            lines.push(format!("  {}", file.display()));
+        }
+        lines.push(format!(
+            "  Run `fin takeover` to move its {} plugins to fin",
+            fisher.plugins.len()
+        ));
+    }
+    for (plugin, file) in &report.shadowed_completions {
+        lines.push(format!(
+            "{plugin}: {file} replaces the completions Fish ships"
+        ));
+    }
+    for duplicate in &report.duplicate_functions {
+        lines.push(format!(
+            "Function {} is defined by several plugins:",
+            duplicate.name
+        ));
+        for (plugin, file) in &duplicate.definitions {
+            lines.push(format!("  {plugin}: {file}"));
+        }
+    }
+    lines.extend(deprecation_notices(&report.deprecated));
+    if let Some(found) = &report.fish_version {
+        for (plugin, required) in &report.fish_too_old {
+            lines.push(format!(
+                "{plugin}: requires fish {required} or newer, fish {found} is installed"
+            ));
+        }
+    }
+    lines
+}
+
+/// Tell which plugins the registry marks as deprecated and what replaces them
+fn print_deprecations(deprecations: &[Deprecation]) {
+    for line in deprecation_notices(deprecations) {
+        println!("{line}");
+    }
+}
+
+fn deprecation_notices(deprecations: &[Deprecation]) -> Vec<String> {
+    let mut lines = Vec::new();
+    for deprecation in deprecations {
+        match &deprecation.reason {
+            Some(reason) => lines.push(format!("Deprecated: {} ({reason})", deprecation.name)),
+            None => lines.push(format!("Deprecated: {}", deprecation.name)),
+        }
+        if let Some(replacement) = &deprecation.replacement {
+            lines.push(format!(
+                "  Replaced by {replacement}, run `fin replace {} {replacement}`",
+                deprecation.name
+            ));
+        }
+    }
+    lines
+}
+
+/// Show each build command that ran and what it printed, on stderr
+fn print_build_output(plugins: &[Plugin], build_output: &[(String, String)]) {
+    for (name, output) in build_output {
+        let command = plugins
+            .iter()
+            .find(|plugin| plugin.name == *name)
+            .and_then(|plugin| plugin.build.as_deref())
+            .unwrap_or_default();
+        eprintln!("Built {name} with `{command}`");
+        for line in output.lines() {
+            eprintln!("{name}: {line}");
+        }
+    }
+}
+
+/// Hooks never fail the operation, their failures are only shown
+fn print_hook_failures(failed: &[Failure]) {
+    for (plugin, err) in failed {
+        eprintln!("Hook failed: {} - {}", plugin.name, err.full_message());
+    }
+}
+
+/// Summarize an operation stopped by Ctrl-C and exit like shells do after SIGINT
+///
+/// Finished plugins are already recorded in the lock file.
+fn exit_interrupted(done_verb: &str, done: usize, failed: &[Failure]) -> ! {
+    let unfinished: Vec<&str> = failed
+        .iter()
+        .filter(|(_, err)| matches!(err, FinError::Interrupted))
+        .map(|(plugin, _)| plugin.name.as_str())
+        .collect();
+    eprintln!(
+        "Interrupted: {done_verb} {done} plugins, {} not finished: {}",
+        unfinished.len(),
+        unfinished.join(", ")
+    );
+    std::process::exit(INTERRUPTED_EXIT_CODE);
+}
+
+/// Abbreviated commit hash like git shows it
+fn short_sha(sha: &str) -> &str {
+    &sha[..sha.len().min(7)]
 }
@@ -0,0 +1,106 @@
+use serde::Deserialize;
+use std::{io::Read, path::Path};
+
+use crate::{
+    error::{FinError, IoResultExt, Result},
+    filesystem::FileSystem,
+};
+
+/// File at the root of a plugin describing it, optional
+pub const PLUGIN_MANIFEST_FILENAME: &str = "fin.toml";
+
+/// What a plugin says about itself in its `fin.toml`
+///
+/// Every field is optional, unknown fields are ignored so plugins can
+/// target newer versions of fin.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct PluginManifest {
+    pub description: Option<String>,
+    pub author: Option<String>,
+    pub homepage: Option<String>,
+    /// Fish code run by `fin remove` before the plugin's files are deleted,
+    /// e.g. to erase universal variables it set
+    ///
+    /// The plugin's functions and `conf.d` snippets are loaded, so this can
+    /// be the name of one of its functions.
+    pub uninstall: Option<String>,
+    /// Fish code that builds files the plugin installs, like a helper
+    /// binary or generated completions
+    ///
+    /// Runs in the plugin's fetched source before its files are copied, and
+    /// only when the user allows scripts.
+    pub build: Option<String>,
+    /// Program the build produces, relative to the plugin's root, installed
+    /// to fin's `bin` directory
+    pub binary: Option<String>,
+    /// Oldest Fish version the plugin works with, like `3.6.0`
+    pub min_fish_version: Option<String>,
+}
+
+impl PluginManifest {
+    /// Read the manifest at the root of the plugin in `dir`, `None` when it ships none
+    pub fn read(fs: &dyn FileSystem, dir: &Path) -> Result<Option<Self>> {
+        let path = dir.join(PLUGIN_MANIFEST_FILENAME);
+        let Ok(mut file) = fs.open(&path) else {
+            return Ok(None);
+        };
+
+        let mut content = String::new();
+        file.read_to_string(&mut content).with_path("read", &path)?;
+        toml::from_str(&content)
+            .map(Some)
+            .map_err(|source| FinError::Manifest { path, source })
+    }
+}
+
+/// The plugins a user wants installed, from `fin.toml` in the Fish config directory
+///
+/// Meant to be checked in next to the Fish config, while the lock file
+/// records what those plugins resolved to.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct InstallManifest {
+    pub plugins: Vec<ManifestPlugin>,
+}
+
+/// A plugin listed in an [`InstallManifest`]
+#[derive(Debug, Clone, Deserialize)]
+pub struct ManifestPlugin {
+    /// Install spec without a ref, e.g. `jorgebucaran/nvm.fish` or `z`
+    pub name: String,
+    /// Tag, branch or commit to install, the default branch when unset
+    #[serde(default, rename = "ref", alias = "branch")]
+    pub ref_name: Option<String>,
+}
+
+impl ManifestPlugin {
+    /// The spec `fin install` takes for this plugin, like `owner/repo@v1`
+    pub fn spec(&self) -> String {
+        match &self.ref_name {
+            Some(ref_name) => format!("{}@{ref_name}", self.name),
+            None => self.name.clone(),
+        }
+    }
+}
+
+impl InstallManifest {
+    /// Read the manifest in the Fish config directory `dir`, `None` when there is none
+    pub fn read(fs: &dyn FileSystem, dir: &Path) -> Result<Option<Self>> {
+        let path = dir.join(PLUGIN_MANIFEST_FILENAME);
+        let Ok(mut file) = fs.open(&path) else {
+            return Ok(None);
+        };
+
+        let mut content = String::new();
+        file.read_to_string(&mut content).with_path("read", &path)?;
+        toml::from_str(&content)
+            .map(Some)
+            .map_err(|source| FinError::Manifest { path, source })
+    }
+
+    /// Install specs of the listed plugins, in order
+    pub fn specs(&self) -> Vec<String> {
+        self.plugins.iter().map(ManifestPlugin::spec).collect()
+    }
+}
@@ -0,0 +1,127 @@
+use std::fmt;
+
+use crate::{error::FinError, lock::Plugin};
+
+/// Stage of a single plugin operation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    /// Downloading the archive
+    Download,
+    /// Unpacking the downloaded archive
+    Extract,
+    /// Running the plugin's build command
+    Build,
+    /// Copying plugin files into place
+    Copy,
+    /// Sourcing `conf.d` snippets to emit install or update events
+    Events,
+}
+
+/// Something worth knowing that did not fail the operation
+#[derive(Debug)]
+pub enum Warning {
+    /// The plugin's copy of `file` replaced `owner`'s, which is kept aside
+    Overrode { file: String, owner: String },
+    /// `owner`'s copy of `file` stays, the plugin's is kept aside
+    Shadowed { file: String, owner: String },
+    /// `owner` already provides `file` and keeps it, the plugin's copy is kept aside
+    Conflict { file: String, owner: String },
+    /// A file in the plugin that fin does not install and why
+    Skipped { file: String, reason: &'static str },
+    /// `file` replaces the completions Fish ships for the same command
+    ShadowsCompletion { file: String },
+    /// The plugin's build `command` did not run because scripts are not allowed
+    BuildSkipped { command: String },
+    /// The plugin declares it needs fish `required`, older than the installed `found`
+    FishTooOld { required: String, found: String },
+    /// `owner` defines `function` too, in `owner_file`
+    DuplicateFunction {
+        function: String,
+        file: String,
+        owner: String,
+        owner_file: String,
+    },
+    /// Event handlers failed, the plugin's files stay in place
+    Events(FinError),
+    /// The lock file has more than one entry for some plugins, only the
+    /// most complete one of each is used
+    DuplicateEntries(usize),
+    /// The saved lock file could not be committed to git
+    Git(FinError),
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Warning::Overrode { file, owner } => {
+                write!(f, "{file} replaces the copy from {owner}")
+            }
+            Warning::Shadowed { file, owner } => {
+                write!(f, "{file} from {owner} has priority, this copy is not used")
+            }
+            Warning::Conflict { file, owner } => {
+                write!(
+                    f,
+                    "{file} is already owned by {owner}, this copy is not used"
+                )
+            }
+            Warning::Skipped { file, reason } => write!(f, "skipped {file}, {reason}"),
+            Warning::ShadowsCompletion { file } => {
+                write!(f, "{file} replaces the completions Fish ships")
+            }
+            Warning::DuplicateFunction {
+                function,
+                file,
+                owner,
+                owner_file,
+            } => write!(
+                f,
+                "function {function} in {file} is also defined by {owner} in {owner_file}"
+            ),
+            Warning::FishTooOld { required, found } => write!(
+                f,
+                "requires fish {required} or newer, but fish {found} is installed"
+            ),
+            Warning::BuildSkipped { command } => {
+                write!(
+                    f,
+                    "build `{command}` not run, pass --allow-scripts to run it"
+                )
+            }
+            Warning::DuplicateEntries(count) => write!(
+                f,
+                "{count} duplicate entries, only the most complete one of each plugin is used; \
+                 run `fin lock --dedup` to drop the others"
+            ),
+            Warning::Events(err) | Warning::Git(err) => write!(f, "{}", err.full_message()),
+        }
+    }
+}
+
+/// Observes long running operations
+///
+/// Callbacks may fire concurrently from worker threads while plugins are
+/// processed in parallel. All methods default to doing nothing.
+pub trait ProgressObserver: Send + Sync {
+    fn plugin_started(&self, _plugin: &Plugin) {}
+
+    fn phase_changed(&self, _plugin: &Plugin, _phase: Phase) {}
+
+    /// Bytes received so far, `total` when the size is known upfront
+    fn download_progress(&self, _plugin: &Plugin, _bytes: u64, _total: Option<u64>) {}
+
+    fn plugin_finished(&self, _plugin: &Plugin, _result: Result<&Plugin, &FinError>) {}
+
+    /// Something about `plugin` went wrong without failing it
+    fn warning(&self, _plugin: &Plugin, _warning: &Warning) {}
+
+    /// Something went wrong with the lock file after it was saved, without
+    /// failing the operation
+    fn lock_warning(&self, _warning: &Warning) {}
+}
+
+/// Observer that ignores every event
+#[derive(Debug, Default)]
+pub struct NoopObserver;
+
+impl ProgressObserver for NoopObserver {}
@@ -0,0 +1,125 @@
+use std::io::{self, BufRead, IsTerminal, Write};
+
+use crate::error::{FinError, Result};
+
+/// Prompts the user on the terminal
+///
+/// Every interactive question goes through here so TTY detection and
+/// `--yes` behave the same way across commands.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Prompt {
+    assume_yes: bool,
+}
+
+impl Prompt {
+    pub fn new(assume_yes: bool) -> Self {
+        Self { assume_yes }
+    }
+
+    /// Whether questions can be asked on the terminal
+    pub fn is_interactive(&self) -> bool {
+        !self.assume_yes && io::stdin().is_terminal() && io::stderr().is_terminal()
+    }
+
+    /// Ask a yes/no question, defaulting to no
+    ///
+    /// `--yes` answers yes, non-interactive sessions answer no.
+    pub fn confirm(&self, message: &str) -> Result<bool> {
+        if self.assume_yes {
+            return Ok(true);
+        }
+        if !self.is_interactive() {
+            return Ok(false);
+        }
+
+        let answer = self.ask(&format!("{message} [y/N] "))?;
+        Ok(matches!(answer.to_lowercase().as_str(), "y" | "yes"))
+    }
+
+    /// Present a numbered list and return the index of the picked option
+    ///
+    /// Fails in non-interactive sessions since there is no sensible default.
+    pub fn choose(&self, message: &str, options: &[String]) -> Result<usize> {
+        if !self.is_interactive() {
+            return Err(FinError::Prompt(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("{message}: not an interactive session"),
+            )));
+        }
+
+        eprintln!("{message}:");
+        for (i, option) in options.iter().enumerate() {
+            eprintln!("  {}) {option}", i + 1);
+        }
+
+        loop {
+            let answer = self.ask(&format!("Choose [1-{}]: ", options.len()))?;
+            match answer.parse::<usize>() {
+                Ok(n) if (1..=options.len()).contains(&n) => return Ok(n - 1),
+                _ => eprintln!("Invalid choice: {answer}"),
+            }
+        }
+    }
+
+    /// Present a numbered list and return the indices of the picked options
+    ///
+    /// Answers are numbers and ranges like `1 3-4`, `all`, or nothing to
+    /// pick none. Fails in non-interactive sessions like [`Self::choose`].
+    pub fn select(&self, message: &str, options: &[String]) -> Result<Vec<usize>> {
+        if !self.is_interactive() {
+            return Err(FinError::Prompt(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("{message}: not an interactive session"),
+            )));
+        }
+
+        eprintln!("{message}:");
+        for (i, option) in options.iter().enumerate() {
+            eprintln!("  {}) {option}", i + 1);
+        }
+
+        loop {
+            let answer = self.ask(&format!("Select [1-{}, all]: ", options.len()))?;
+            match parse_selection(&answer, options.len()) {
+                Some(picked) => return Ok(picked),
+                None => eprintln!("Invalid selection: {answer}"),
+            }
+        }
+    }
+
+    fn ask(&self, question: &str) -> Result<String> {
+        eprint!("{question}");
+        io::stderr().flush().map_err(FinError::Prompt)?;
+
+        let mut answer = String::new();
+        let read = io::stdin()
+            .lock()
+            .read_line(&mut answer)
+            .map_err(FinError::Prompt)?;
+        if read == 0 {
+            return Err(FinError::Prompt(io::ErrorKind::UnexpectedEof.into()));
+        }
+
+        Ok(answer.trim().to_string())
+    }
+}
+
+/// Indices picked by an answer to [`Prompt::select`], `None` when it is malformed
+fn parse_selection(answer: &str, len: usize) -> Option<Vec<usize>> {
+    if answer.eq_ignore_ascii_case("all") {
+        return Some((0..len).collect());
+    }
+
+    let mut picked = Vec::new();
+    for part in answer.split([' ', ',']).filter(|p| !p.is_empty()) {
+        let (start, end) = part.split_once('-').unwrap_or((part, part));
+        let (start, end) = (start.parse::<usize>().ok()?, end.parse::<usize>().ok()?);
+        if start == 0 || start > end || end > len {
+            return None;
+        }
+        picked.extend(start - 1..end);
+    }
+    picked.sort_unstable();
+    picked.dedup();
+    Some(picked)
+}
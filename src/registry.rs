@@ -0,0 +1,251 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+
+use crate::{
+    error::{FinError, IoResultExt, Result},
+    fetch,
+};
+
+/// Newest index format this version of fin understands
+pub const REGISTRY_SCHEMA_VERSION: u32 = 1;
+pub const DEFAULT_REGISTRY_URL: &str =
+    "https://raw.githubusercontent.com/yuusheng/fin-registry/main/index.json";
+
+const REGISTRY_CACHE_FILENAME: &str = "registry.json";
+const DEFAULT_RETRIES: u32 = 3;
+/// Cached indexes younger than this are used without asking the network
+const CACHE_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// A plugin registry index
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegistryIndex {
+    pub schema_version: u32,
+    #[serde(default)]
+    pub plugins: Vec<RegistryEntry>,
+}
+
+/// A plugin listed in the registry
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegistryEntry {
+    /// Short well-known name, e.g. `z`
+    pub name: String,
+    /// Install spec, e.g. `jethrokuan/z`
+    pub repo: String,
+    pub description: Option<String>,
+    pub stars: Option<u64>,
+    pub updated_at: Option<String>,
+    /// Why the plugin should no longer be used, set when it is deprecated
+    pub deprecated: Option<String>,
+    /// Install spec of the plugin that takes its place, e.g. after a rename
+    pub replaced_by: Option<String>,
+}
+
+impl RegistryIndex {
+    /// Plugins registered under exactly `name`, ignoring case
+    pub fn named(&self, name: &str) -> Vec<&RegistryEntry> {
+        self.plugins
+            .iter()
+            .filter(|entry| entry.name.eq_ignore_ascii_case(name))
+            .collect()
+    }
+
+    /// The entry listing `repo`, like `jethrokuan/z`, ignoring case and any ref
+    pub fn by_repo(&self, repo: &str) -> Option<&RegistryEntry> {
+        self.plugins.iter().find(|entry| {
+            let listed = entry.repo.split('@').next().unwrap_or_default();
+            listed.eq_ignore_ascii_case(repo)
+        })
+    }
+
+    /// Plugins whose name, repository or description contain `term`, ignoring case
+    ///
+    /// Name matches come first, an exact one before the rest, then
+    /// repository and description matches. Ties go to the most stars.
+    pub fn search(&self, term: &str) -> Vec<&RegistryEntry> {
+        let term = term.to_lowercase();
+        let rank = |entry: &RegistryEntry| {
+            let name = entry.name.to_lowercase();
+            if name == term {
+                Some(0)
+            } else if name.contains(&term) {
+                Some(1)
+            } else if entry.repo.to_lowercase().contains(&term) {
+                Some(2)
+            } else if entry
+                .description
+                .as_ref()
+                .is_some_and(|d| d.to_lowercase().contains(&term))
+            {
+                Some(3)
+            } else {
+                None
+            }
+        };
+        let mut matches: Vec<(u8, &RegistryEntry)> = self
+            .plugins
+            .iter()
+            .filter_map(|entry| Some((rank(entry)?, entry)))
+            .collect();
+        matches.sort_by_key(|&(rank, entry)| (rank, std::cmp::Reverse(entry.stars.unwrap_or(0))));
+        matches.into_iter().map(|(_, entry)| entry).collect()
+    }
+}
+
+/// Only the part of the index every schema version agrees on
+#[derive(Deserialize)]
+struct SchemaHeader {
+    schema_version: u32,
+}
+
+/// An index together with where it came from
+#[derive(Debug)]
+pub struct LoadedIndex {
+    pub index: RegistryIndex,
+    /// Set when a cached copy was used because the remote one was unusable
+    pub warning: Option<String>,
+}
+
+/// Fetches the registry index, caching it on disk
+#[derive(Debug, Clone)]
+pub struct Registry {
+    url: String,
+    cache_path: Option<PathBuf>,
+    retries: u32,
+}
+
+impl Registry {
+    pub fn new(url: Option<String>) -> Self {
+        Self {
+            url: url.unwrap_or_else(|| DEFAULT_REGISTRY_URL.to_string()),
+            cache_path: dirs::cache_dir().map(|p| p.join("fin").join(REGISTRY_CACHE_FILENAME)),
+            retries: DEFAULT_RETRIES,
+        }
+    }
+
+    /// Load the index, preferring a fresh cache over the network
+    ///
+    /// When the remote index cannot be fetched or uses a newer schema than
+    /// this version supports, a cached copy is used with a warning.
+    pub fn load(&self) -> Result<LoadedIndex> {
+        if let Some(index) = self.read_cache(Some(CACHE_MAX_AGE)) {
+            return Ok(LoadedIndex {
+                index,
+                warning: None,
+            });
+        }
+
+        let remote = self
+            .fetch()
+            .and_then(|body| self.parse(&body).map(|i| (i, body)));
+        match remote {
+            Ok((index, body)) => {
+                // A cache that cannot be written only costs a refetch next time
+                let _ = self.write_cache(&body);
+                Ok(LoadedIndex {
+                    index,
+                    warning: None,
+                })
+            }
+            Err(err) => match self.read_cache(None) {
+                Some(index) => Ok(LoadedIndex {
+                    index,
+                    warning: Some(format!("{}, using the cached index", err.full_message())),
+                }),
+                None => Err(err),
+            },
+        }
+    }
+
+    /// Download the raw index, retrying with exponential backoff
+    fn fetch(&self) -> Result<String> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(FinError::Runtime)?;
+        let client = fetch::client()?;
+
+        runtime.block_on(async {
+            let mut delay = Duration::from_millis(500);
+            let mut attempt = 0;
+            loop {
+                attempt += 1;
+                match self.fetch_once(&client).await {
+                    Ok(body) => return Ok(body),
+                    Err(err) if attempt > self.retries => return Err(err),
+                    Err(_) => {
+                        tokio::time::sleep(delay).await;
+                        delay *= 2;
+                    }
+                }
+            }
+        })
+    }
+
+    async fn fetch_once(&self, client: &reqwest::Client) -> Result<String> {
+        let error = |reason: String| FinError::Registry {
+            url: self.url.clone(),
+            reason,
+        };
+
+        let response = client
+            .get(&self.url)
+            .send()
+            .await
+            .map_err(|err| error(err.to_string()))?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(error(format!("HTTP {status}")));
+        }
+
+        response.text().await.map_err(|err| error(err.to_string()))
+    }
+
+    /// Parse an index, checking the schema before anything else
+    fn parse(&self, body: &str) -> Result<RegistryIndex> {
+        let error = |reason: String| FinError::Registry {
+            url: self.url.clone(),
+            reason,
+        };
+
+        let header: SchemaHeader = serde_json::from_str(body).map_err(|e| error(e.to_string()))?;
+        if header.schema_version > REGISTRY_SCHEMA_VERSION {
+            return Err(FinError::RegistrySchema {
+                found: header.schema_version,
+                supported: REGISTRY_SCHEMA_VERSION,
+            });
+        }
+
+        serde_json::from_str(body).map_err(|e| error(e.to_string()))
+    }
+
+    /// The cached index, if present, parseable and no older than `max_age`
+    fn read_cache(&self, max_age: Option<Duration>) -> Option<RegistryIndex> {
+        let path = self.cache_path.as_ref()?;
+        if let Some(max_age) = max_age {
+            let modified = fs::metadata(path).and_then(|m| m.modified()).ok()?;
+            let age = SystemTime::now()
+                .duration_since(modified)
+                .unwrap_or_default();
+            if age > max_age {
+                return None;
+            }
+        }
+
+        let body = fs::read_to_string(path).ok()?;
+        self.parse(&body).ok()
+    }
+
+    fn write_cache(&self, body: &str) -> Result<()> {
+        let Some(path) = &self.cache_path else {
+            return Ok(());
+        };
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).with_path("create", parent)?;
+        }
+        fs::write(path, body).with_path("write", path)
+    }
+}
@@ -0,0 +1,354 @@
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Serializer, ser::SerializeSeq};
+use std::{path::PathBuf, time::Duration};
+
+use crate::{error::FinError, fisher::FisherInstall, lock::Plugin};
+
+/// A plugin that could not be processed and why
+pub type Failure = (Plugin, FinError);
+
+/// Outcome of [`Fin::install`](crate::Fin::install)
+#[derive(Debug, Default, Serialize)]
+pub struct InstallReport {
+    /// Plugins that were installed and recorded in the lock file
+    pub installed: Vec<Plugin>,
+    /// Names of requested plugins that were already installed
+    pub skipped: Vec<String>,
+    /// Names of installed plugins that were reinstalled because files were missing
+    pub repaired: Vec<String>,
+    /// Names of plugins whose files were already in place, recorded without fetching
+    pub adopted: Vec<String>,
+    #[serde(serialize_with = "serialize_failures")]
+    pub failed: Vec<Failure>,
+    /// Ctrl-C stopped the install, plugins that did not finish are in `failed`
+    pub interrupted: bool,
+    /// Installed plugins whose `post_command` hook failed
+    #[serde(serialize_with = "serialize_failures")]
+    pub hook_failed: Vec<Failure>,
+    /// What plugins' build commands printed, by plugin name
+    pub build_output: Vec<(String, String)>,
+}
+
+/// Outcome of [`Fin::remove`](crate::Fin::remove)
+#[derive(Debug, Default, Serialize)]
+pub struct RemoveReport {
+    /// Plugins that were removed from the lock file
+    pub removed: Vec<Plugin>,
+    /// Requested names that are not installed
+    pub not_installed: Vec<String>,
+    /// Tracked files that were already gone from disk
+    pub missing_files: Vec<PathBuf>,
+    /// Removed plugins whose uninstall hook or `post_command` hook failed
+    #[serde(serialize_with = "serialize_failures")]
+    pub hook_failed: Vec<Failure>,
+    /// What plugins' uninstall hooks printed, by plugin name
+    pub hook_output: Vec<(String, String)>,
+}
+
+/// Outcome of [`Fin::update`](crate::Fin::update)
+#[derive(Debug, Default, Serialize)]
+pub struct UpdateReport {
+    /// Plugins that were fetched and reinstalled
+    pub updated: Vec<Plugin>,
+    /// Updated plugins whose checksum differs from the one recorded before
+    pub changed: Vec<String>,
+    /// GitHub plugins left alone because their ref still points to the installed commit
+    pub up_to_date: Vec<String>,
+    /// Requested names that are not installed
+    pub not_installed: Vec<String>,
+    #[serde(serialize_with = "serialize_failures")]
+    pub failed: Vec<Failure>,
+    /// Ctrl-C stopped the update, plugins that did not finish are in `failed`
+    pub interrupted: bool,
+    /// Updated plugins whose `post_command` hook failed
+    #[serde(serialize_with = "serialize_failures")]
+    pub hook_failed: Vec<Failure>,
+    /// What plugins' build commands printed, by plugin name
+    pub build_output: Vec<(String, String)>,
+    /// Requested plugins the registry marks as deprecated or renamed
+    pub deprecated: Vec<Deprecation>,
+}
+
+/// Outcome of [`Fin::replace`](crate::Fin::replace)
+#[derive(Debug, Serialize)]
+pub struct ReplaceReport {
+    /// The new plugin as recorded in the lock file
+    pub installed: Plugin,
+    /// The replaced plugin's lock entry
+    pub removed: Plugin,
+    /// What the new plugin's build command printed, by plugin name
+    pub build_output: Vec<(String, String)>,
+    /// Files of the replaced plugin that were already gone
+    pub missing_files: Vec<PathBuf>,
+    /// The new plugin's `post_command` hook failed
+    #[serde(serialize_with = "serialize_failures")]
+    pub hook_failed: Vec<Failure>,
+}
+
+/// How updating a plugin would change its files, see [`PreviewReport`]
+///
+/// Files are named as in the lock file.
+#[derive(Debug, Default, Serialize)]
+pub struct UpdatePreview {
+    pub name: String,
+    /// Files the update would install that are not installed now
+    pub added: Vec<String>,
+    /// Installed files the update would remove
+    pub removed: Vec<String>,
+    /// Installed files whose content would change
+    pub modified: Vec<String>,
+    /// Unified diffs of the modified text files, when asked for, by file
+    pub diffs: Vec<(String, String)>,
+}
+
+/// Outcome of [`Fin::preview_update`](crate::Fin::preview_update)
+#[derive(Debug, Default, Serialize)]
+pub struct PreviewReport {
+    /// Plugins by name, including those the update would not change
+    pub plugins: Vec<UpdatePreview>,
+    /// Requested names that are not installed
+    pub not_installed: Vec<String>,
+    /// Plugins that could not be fetched again
+    #[serde(serialize_with = "serialize_failures")]
+    pub failed: Vec<Failure>,
+}
+
+/// A plugin whose install event was emitted again, see [`HooksReport`]
+#[derive(Debug, Default, Serialize)]
+pub struct HookRun {
+    pub name: String,
+    /// Events some handler listened to, like `z_install`
+    pub handled: Vec<String>,
+    /// What the handlers printed
+    pub output: String,
+}
+
+/// Outcome of [`Fin::run_hooks`](crate::Fin::run_hooks)
+#[derive(Debug, Default, Serialize)]
+pub struct HooksReport {
+    /// Plugins with `conf.d` snippets, in the order their events were emitted
+    pub plugins: Vec<HookRun>,
+    /// Requested names that are not installed
+    pub not_installed: Vec<String>,
+    /// Plugins whose event could not be emitted
+    #[serde(serialize_with = "serialize_failures")]
+    pub failed: Vec<Failure>,
+}
+
+/// Disk space taken by a plugin, from [`Fin::disk_usage`](crate::Fin::disk_usage)
+#[derive(Debug, Default, Serialize)]
+pub struct DiskUsage {
+    /// Total size of the tracked files still on disk
+    pub bytes: u64,
+    /// Tracked files that are gone from disk
+    pub missing_files: Vec<PathBuf>,
+}
+
+/// A plugin's lock entry and the state of its files, from [`Fin::states`](crate::Fin::states)
+#[derive(Debug, Serialize)]
+pub struct PluginState {
+    #[serde(flatten)]
+    pub plugin: Plugin,
+    /// Every installed file and whether it is still on disk, when asked for
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub files: Option<Vec<FileState>>,
+}
+
+/// An installed file of a [`PluginState`]
+#[derive(Debug, Serialize)]
+pub struct FileState {
+    pub path: PathBuf,
+    pub exists: bool,
+}
+
+/// Version of the [`Inventory`] document, raised whenever a field changes meaning or goes away
+pub const INVENTORY_VERSION: u32 = 2;
+
+/// Everything fin manages, from [`Fin::inventory`](crate::Fin::inventory)
+///
+/// Meant for external tools, so unlike the lock file its layout only
+/// changes together with `inventory_version`. New fields may appear.
+#[derive(Debug, Serialize)]
+pub struct Inventory {
+    pub inventory_version: u32,
+    pub lock: LockInventory,
+    /// Sorted by name
+    pub plugins: Vec<PluginInventory>,
+}
+
+/// Where the lock file is and what it says about itself
+#[derive(Debug, Serialize)]
+pub struct LockInventory {
+    pub path: PathBuf,
+    pub version: String,
+    pub generated_at: DateTime<Utc>,
+}
+
+/// A plugin's lock entry, its files and whether they drifted
+#[derive(Debug, Serialize)]
+pub struct PluginInventory {
+    #[serde(flatten)]
+    pub plugin: Plugin,
+    pub files: Vec<FileInventory>,
+    pub drift: Drift,
+}
+
+/// An installed file of a [`PluginInventory`], `size` and `checksum` are unset when it is missing
+#[derive(Debug, Serialize)]
+pub struct FileInventory {
+    pub path: PathBuf,
+    pub exists: bool,
+    pub size: Option<u64>,
+    pub checksum: Option<String>,
+}
+
+/// How a plugin's installed files differ from what the lock file records
+#[derive(Debug, Serialize)]
+pub struct Drift {
+    /// Installed files no longer on disk
+    pub missing_files: usize,
+    /// Whether the files differ from the recorded checksum, unset when
+    /// there is none to compare offline
+    pub modified: Option<bool>,
+}
+
+/// Outcome of [`Fin::verify`](crate::Fin::verify)
+#[derive(Debug, Default, Serialize)]
+pub struct VerifyReport {
+    /// Plugins whose files match the recorded checksum
+    pub ok: Vec<String>,
+    /// Plugins whose files changed or went missing since install
+    pub mismatched: Vec<String>,
+    /// Plugins installed before checksums were recorded
+    pub unchecked: Vec<String>,
+    /// How long hashing each checked plugin's files took
+    pub timings: Vec<(String, Duration)>,
+    /// Plugins whose archive could not be downloaded again to check it
+    #[serde(serialize_with = "serialize_failures")]
+    pub failed: Vec<Failure>,
+}
+
+/// An installed plugin that ships a prompt, from [`Fin::themes`](crate::Fin::themes)
+#[derive(Debug, Serialize)]
+pub struct Theme {
+    pub name: String,
+    /// Prompt functions the plugin ships, like `fish_prompt`
+    pub prompts: Vec<String>,
+    /// Fish uses the plugin's prompt functions
+    pub active: bool,
+}
+
+/// Outcome of [`Fin::doctor`](crate::Fin::doctor)
+#[derive(Debug, Default, Serialize)]
+pub struct DoctorReport {
+    /// fisher is installed too and may overwrite the files fin installs
+    pub fisher: Option<FisherInstall>,
+    /// Installed completion files that replace ones Fish ships, by plugin name
+    pub shadowed_completions: Vec<(String, String)>,
+    /// Functions more than one plugin defines, whichever Fish loads last wins
+    pub duplicate_functions: Vec<DuplicateFunction>,
+    /// The installed Fish version, unset when `fish` cannot tell
+    pub fish_version: Option<String>,
+    /// Plugins that need a newer Fish than the installed one, with the version they need
+    pub fish_too_old: Vec<(String, String)>,
+    /// Installed plugins the registry marks as deprecated or renamed
+    pub deprecated: Vec<Deprecation>,
+}
+
+impl DoctorReport {
+    /// Number of problems found
+    pub fn problems(&self) -> usize {
+        usize::from(self.fisher.is_some())
+            + self.shadowed_completions.len()
+            + self.duplicate_functions.len()
+            + self.fish_too_old.len()
+            + self.deprecated.len()
+    }
+}
+
+/// The license of an installed plugin, from [`Fin::licenses`](crate::Fin::licenses)
+#[derive(Debug, Serialize)]
+pub struct LicenseInfo {
+    pub name: String,
+    /// SPDX identifier, unset when it could not be determined
+    pub license: Option<String>,
+    /// The license file's text kept at install time, when asked for
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+}
+
+/// An installed plugin the registry marks as deprecated or renamed
+#[derive(Debug, Clone, Serialize)]
+pub struct Deprecation {
+    pub name: String,
+    /// Why it should no longer be used, when the registry says
+    pub reason: Option<String>,
+    /// Install spec of the plugin that takes its place
+    pub replacement: Option<String>,
+}
+
+/// A function defined by more than one installed plugin
+#[derive(Debug, Serialize)]
+pub struct DuplicateFunction {
+    pub name: String,
+    /// Plugin names and the files defining the function, relative to the
+    /// Fish config directory
+    pub definitions: Vec<(String, String)>,
+}
+
+/// An installed plugin whose ref moved upstream
+#[derive(Debug, Serialize)]
+pub struct Outdated {
+    pub name: String,
+    /// The installed commit, if recorded
+    pub current: Option<String>,
+    /// The commit the plugin's ref points to now
+    pub latest: String,
+}
+
+/// Outcome of [`Fin::outdated`](crate::Fin::outdated)
+#[derive(Debug, Default, Serialize)]
+pub struct OutdatedReport {
+    pub outdated: Vec<Outdated>,
+    /// Names of plugins already at the latest commit
+    pub up_to_date: Vec<String>,
+    /// Names of plugins installed at a commit hash, whose ref cannot move
+    pub pinned: Vec<String>,
+    /// Plugins that could not be checked, e.g. because they are not on GitHub
+    #[serde(serialize_with = "serialize_failures")]
+    pub failed: Vec<Failure>,
+    /// Checked plugins the registry marks as deprecated or renamed
+    pub deprecated: Vec<Deprecation>,
+}
+
+/// Outcome of [`Fin::status`](crate::Fin::status)
+#[derive(Debug, Default, Serialize)]
+pub struct StatusReport {
+    /// Names of plugins whose installed files are all in place
+    pub ok: Vec<String>,
+    /// Plugins with installed files that are gone, and those files
+    pub incomplete: Vec<(String, Vec<PathBuf>)>,
+    /// Files in the plugin directories that no plugin owns
+    pub orphaned_files: Vec<PathBuf>,
+}
+
+/// Failures serialize as `{ plugin, error }` with the full error chain
+fn serialize_failures<S>(failures: &[Failure], serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    #[derive(Serialize)]
+    struct Entry<'a> {
+        plugin: &'a Plugin,
+        error: String,
+    }
+
+    let mut seq = serializer.serialize_seq(Some(failures.len()))?;
+    for (plugin, error) in failures {
+        seq.serialize_element(&Entry {
+            plugin,
+            error: error.full_message(),
+        })?;
+    }
+    seq.end()
+}
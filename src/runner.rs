@@ -0,0 +1,206 @@
+use std::{
+    io::{self, Read},
+    path::PathBuf,
+    process::{Child, Command, ExitStatus, Stdio},
+    sync::Mutex,
+    thread,
+    time::{Duration, Instant},
+};
+
+/// An external program and its arguments
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Invocation {
+    pub program: String,
+    pub args: Vec<String>,
+    /// Kill the program once it ran this long
+    pub timeout: Option<Duration>,
+    /// Collect stdout and stderr in the [`Output`] instead of passing them on
+    pub capture: bool,
+    /// Give the program the terminal, stdin included
+    pub interactive: bool,
+    /// Working directory, fin's own when unset
+    pub dir: Option<PathBuf>,
+}
+
+impl Invocation {
+    pub fn new(program: &str, args: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self {
+            program: program.to_string(),
+            args: args.into_iter().map(Into::into).collect(),
+            timeout: None,
+            capture: false,
+            interactive: false,
+            dir: None,
+        }
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn captured(mut self) -> Self {
+        self.capture = true;
+        self
+    }
+
+    pub fn interactive(mut self) -> Self {
+        self.interactive = true;
+        self
+    }
+
+    pub fn in_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.dir = Some(dir.into());
+        self
+    }
+}
+
+/// How an external program finished
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Output {
+    /// Exit code, `None` when the program was killed by a signal
+    pub code: Option<i32>,
+    /// What the program wrote to stdout, when captured
+    pub stdout: String,
+    /// What the program wrote to stderr, when the runner captures it
+    pub stderr: String,
+    /// The program was killed for running past its timeout
+    pub timed_out: bool,
+}
+
+impl Output {
+    pub fn success(&self) -> bool {
+        self.code == Some(0)
+    }
+}
+
+/// Runs external programs like `fish`
+///
+/// Everything fin starts goes through here so the handling of missing
+/// binaries and failing programs can be exercised without them.
+pub trait CommandRunner: Send + Sync {
+    /// Run `invocation` to completion with stdin closed and stdout sent to stderr
+    ///
+    /// [Interactive](Invocation::interactive) programs get the terminal instead.
+    ///
+    /// A program that cannot be started is an error, one that starts and
+    /// fails is reported in the [`Output`].
+    fn run(&self, invocation: &Invocation) -> io::Result<Output>;
+}
+
+/// Starts real processes
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemRunner;
+
+impl CommandRunner for SystemRunner {
+    fn run(&self, invocation: &Invocation) -> io::Result<Output> {
+        let mut command = Command::new(&invocation.program);
+        command.args(&invocation.args);
+        if let Some(dir) = &invocation.dir {
+            command.current_dir(dir);
+        }
+        if invocation.interactive {
+            command.stdin(Stdio::inherit()).stdout(Stdio::inherit());
+        } else if invocation.capture {
+            command.stdin(Stdio::null());
+            command.stdout(Stdio::piped()).stderr(Stdio::piped());
+        } else {
+            // stderr is inherited, handler output belongs on the terminal
+            command.stdin(Stdio::null()).stdout(io::stderr());
+        }
+        let mut child = command.spawn()?;
+
+        // Pipes are drained while waiting so a chatty program cannot block on them
+        let stdout = child
+            .stdout
+            .take()
+            .map(|pipe| thread::spawn(|| read_lossy(pipe)));
+        let stderr = child
+            .stderr
+            .take()
+            .map(|pipe| thread::spawn(|| read_lossy(pipe)));
+        let (status, timed_out) = match invocation.timeout {
+            Some(timeout) => wait_timeout(&mut child, timeout)?,
+            None => (child.wait()?, false),
+        };
+
+        // A killed program's children may hold the pipes open, its output is dropped
+        let collect = |reader: Option<thread::JoinHandle<String>>| match reader {
+            Some(reader) if !timed_out => reader.join().unwrap_or_default(),
+            _ => String::new(),
+        };
+        Ok(Output {
+            code: status.code(),
+            stdout: collect(stdout),
+            stderr: collect(stderr),
+            timed_out,
+        })
+    }
+}
+
+/// Wait for `child`, killing it once `timeout` passed
+fn wait_timeout(child: &mut Child, timeout: Duration) -> io::Result<(ExitStatus, bool)> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok((status, false));
+        }
+        if Instant::now() >= deadline {
+            child.kill()?;
+            return Ok((child.wait()?, true));
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+}
+
+fn read_lossy(mut pipe: impl Read) -> String {
+    let mut bytes = Vec::new();
+    let _ = pipe.read_to_end(&mut bytes);
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// Records invocations instead of running them and answers with canned results
+///
+/// Results are handed out in the order they were queued, once they run out
+/// every invocation succeeds.
+#[derive(Debug, Default)]
+pub struct RecordingRunner {
+    invocations: Mutex<Vec<Invocation>>,
+    results: Mutex<Vec<io::Result<Output>>>,
+}
+
+impl RecordingRunner {
+    /// Answer the next unanswered invocation with `result`
+    pub fn respond(self, result: io::Result<Output>) -> Self {
+        self.results
+            .lock()
+            .expect("runner lock poisoned")
+            .insert(0, result);
+        self
+    }
+
+    /// Every invocation so far, oldest first
+    pub fn invocations(&self) -> Vec<Invocation> {
+        self.invocations
+            .lock()
+            .expect("runner lock poisoned")
+            .clone()
+    }
+}
+
+impl CommandRunner for RecordingRunner {
+    fn run(&self, invocation: &Invocation) -> io::Result<Output> {
+        self.invocations
+            .lock()
+            .expect("runner lock poisoned")
+            .push(invocation.clone());
+        self.results
+            .lock()
+            .expect("runner lock poisoned")
+            .pop()
+            .unwrap_or(Ok(Output {
+                code: Some(0),
+                ..Default::default()
+            }))
+    }
+}
@@ -0,0 +1,28 @@
+use clap::CommandFactory;
+use clap_complete::{generate, shells::Fish};
+
+use crate::Cli;
+
+/// Wraps the binary so `fin install` loads new plugins into the calling shell
+pub const WRAPPER_FUNCTION: &str = r#"function fin --wraps fin --description "A plugin manager for Fish"
+    if test "$argv[1]" = install; and not contains -- --activate $argv; and not contains -- --help $argv; and not contains -- -h $argv
+        set -l script (command fin $argv --activate)
+        set -l code $status
+        string join \n -- $script | source
+        return $code
+    end
+    command fin $argv
+end
+"#;
+
+/// Installed plugin names, completed for commands that take them
+const PLUGIN_COMPLETIONS: &str = "complete -c fin -f -n '__fish_seen_subcommand_from remove update info verify enable-confd disable-confd set-load-order set preview' -a '(command fin list 2>/dev/null)'\n";
+
+/// Fish completions for every fin command and flag
+pub fn completions() -> String {
+    let mut buf = Vec::new();
+    generate(Fish, &mut Cli::command(), "fin", &mut buf);
+    let mut completions = String::from_utf8_lossy(&buf).into_owned();
+    completions.push_str(PLUGIN_COMPLETIONS);
+    completions
+}
@@ -0,0 +1,98 @@
+//! Helpers shared by the unit tests of several modules
+
+use flate2::{Compression, write::GzEncoder};
+use std::{
+    fs,
+    io::{BufRead, BufReader, Write},
+    net::TcpListener,
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    thread,
+};
+use tar::{EntryType, Header};
+
+pub(crate) enum Entry<'a> {
+    File(&'a str, &'a str),
+    /// A file with mode 0755
+    Executable(&'a str, &'a str),
+    Symlink(&'a str, &'a str),
+    HardLink(&'a str, &'a str),
+}
+
+/// Write a gzipped tarball of `entries` into `dir`, in order
+pub(crate) fn tarball(dir: &Path, entries: &[Entry]) -> PathBuf {
+    let path = dir.join("plugin.tar.gz");
+    let file = fs::File::create(&path).unwrap();
+    let mut builder = tar::Builder::new(GzEncoder::new(file, Compression::fast()));
+    for entry in entries {
+        let mut header = Header::new_gnu();
+        header.set_mode(match entry {
+            Entry::Executable(..) => 0o755,
+            _ => 0o644,
+        });
+        match entry {
+            Entry::File(path, contents) | Entry::Executable(path, contents) => {
+                header.set_size(contents.len() as u64);
+                builder
+                    .append_data(&mut header, path, contents.as_bytes())
+                    .unwrap();
+            }
+            Entry::Symlink(path, target) | Entry::HardLink(path, target) => {
+                header.set_entry_type(match entry {
+                    Entry::Symlink(..) => EntryType::Symlink,
+                    _ => EntryType::Link,
+                });
+                header.set_size(0);
+                builder.append_link(&mut header, path, target).unwrap();
+            }
+        }
+    }
+    builder.into_inner().unwrap().finish().unwrap();
+    path
+}
+
+/// An HTTP server on localhost answering with canned responses
+pub(crate) struct Server {
+    /// Base URL, without a trailing slash
+    pub url: String,
+    hits: Arc<AtomicUsize>,
+}
+
+impl Server {
+    /// Answer one request per `(status, body)` in `responses`, then stop listening
+    pub fn start(responses: Vec<(u16, Vec<u8>)>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let hits = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&hits);
+        thread::spawn(move || {
+            for (status, body) in responses {
+                let Ok((stream, _)) = listener.accept() else {
+                    return;
+                };
+                let mut reader = BufReader::new(stream);
+                let mut line = String::new();
+                while reader.read_line(&mut line).is_ok_and(|n| n > 0) && line != "\r\n" {
+                    line.clear();
+                }
+                counter.fetch_add(1, Ordering::SeqCst);
+                let mut stream = reader.into_inner();
+                let head = format!(
+                    "HTTP/1.1 {status} Canned\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    body.len()
+                );
+                let _ = stream.write_all(head.as_bytes());
+                let _ = stream.write_all(&body);
+            }
+        });
+        Self { url, hits }
+    }
+
+    /// Requests answered so far
+    pub fn hits(&self) -> usize {
+        self.hits.load(Ordering::SeqCst)
+    }
+}
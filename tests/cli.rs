@@ -0,0 +1,90 @@
+use fin::{LockFile, LockFormat};
+use std::{
+    path::{Path, PathBuf},
+    process::{Command, Output},
+};
+use tempfile::TempDir;
+
+/// A plugin with a function, its completions and a README that is not installed
+fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name)
+}
+
+/// Run fin against the Fish config directory `config_dir`, away from the user's setup
+fn fin(config_dir: &Path, args: &[&str]) -> Output {
+    let home = config_dir.join("home");
+    Command::new(env!("CARGO_BIN_EXE_fin"))
+        .args(args)
+        .env("FIN_FISH_CONFIG_DIR", config_dir)
+        .env("FIN_CONFIG", home.join("config.toml"))
+        .env("XDG_CACHE_HOME", home.join("cache"))
+        .env_remove("__fish_config_dir")
+        .output()
+        .unwrap()
+}
+
+fn assert_success(output: &Output) {
+    assert!(
+        output.status.success(),
+        "fin failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+fn read_lock(config_dir: &Path) -> LockFile {
+    LockFile::read(&config_dir.join(LockFormat::Toml.filename())).unwrap()
+}
+
+#[test]
+fn install_and_remove_a_local_plugin() {
+    let config_dir = TempDir::new().unwrap();
+    let config_dir = config_dir.path();
+    let plugin = fixture("hello");
+    let spec = plugin.to_str().unwrap();
+
+    assert_success(&fin(config_dir, &["install", spec]));
+
+    let function = config_dir.join("functions/hello.fish");
+    let completions = config_dir.join("completions/hello.fish");
+    assert_eq!(
+        std::fs::read(&function).unwrap(),
+        std::fs::read(plugin.join("functions/hello.fish")).unwrap()
+    );
+    assert!(completions.is_file());
+    assert!(!config_dir.join("README.md").exists());
+    let lock = read_lock(config_dir);
+    let entry = lock.plugins.get(spec).expect("plugin is in the lock file");
+    let mut files: Vec<&String> = entry.installed_files.iter().flatten().collect();
+    files.sort();
+    assert_eq!(files, ["completions/hello.fish", "functions/hello.fish"]);
+    assert!(entry.checksum.is_some());
+
+    let list = fin(config_dir, &["list"]);
+    assert_success(&list);
+    assert!(String::from_utf8_lossy(&list.stdout).contains("hello"));
+
+    assert_success(&fin(config_dir, &["remove", spec]));
+
+    assert!(!function.exists());
+    assert!(!completions.exists());
+    assert!(read_lock(config_dir).plugins.is_empty());
+}
+
+#[test]
+fn installing_again_keeps_the_lock_file() {
+    let config_dir = TempDir::new().unwrap();
+    let config_dir = config_dir.path();
+    let plugin = fixture("hello");
+    let spec = plugin.to_str().unwrap();
+    assert_success(&fin(config_dir, &["install", spec]));
+    let lock = std::fs::read_to_string(config_dir.join(LockFormat::Toml.filename())).unwrap();
+
+    let output = fin(config_dir, &["install", spec]);
+
+    assert_success(&output);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("already installed"));
+    let again = std::fs::read_to_string(config_dir.join(LockFormat::Toml.filename())).unwrap();
+    assert_eq!(lock, again);
+}
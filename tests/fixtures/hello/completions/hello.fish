@@ -0,0 +1 @@
+complete -c hello -f -a "(__fish_complete_users)"
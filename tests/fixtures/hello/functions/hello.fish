@@ -0,0 +1,3 @@
+function hello --description "Say hello"
+    echo "Hello, $argv[1]"
+end